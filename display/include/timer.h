@@ -29,6 +29,15 @@ namespace timer
     bool clock;
   };
 
+  // time added to a segment, all times are in ms after start_at
+  struct SegmentExtension
+  {
+    bool valid;
+    long long start;
+    long long end;
+    long long time;
+  };
+
   struct TimerData
   {
     bool valid;
@@ -37,6 +46,7 @@ namespace timer
     TIME stop_at;
     DisplayOptions display_options;
     TimerSegment segments[10];
+    SegmentExtension extension;
   };
 
   struct ActiveSegment
@@ -195,6 +195,15 @@ namespace WebSocket
     JsonObject display_options = data["display_options"];
     _loadDisplayOptions(display_options);
 
+    JsonObject extension = data["extension"];
+    _timerData->extension.valid = !extension.isNull();
+    if (_timerData->extension.valid)
+    {
+      _timerData->extension.start = extension["start"].as<long long>();
+      _timerData->extension.end = extension["end"].as<long long>();
+      _timerData->extension.time = extension["time"].as<long long>();
+    }
+
     JsonArray segments = data["segments"];
     for (size_t i = 0; i < segments.size() && i < 10; i++)
     {
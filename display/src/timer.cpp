@@ -11,6 +11,47 @@ namespace timer
 
   TimerData *timerData() { return &_timerData; }
 
+  long long _elapsedTime(TIME currentTime)
+  {
+    if (_timerData.stop_at != 0 && currentTime > _timerData.stop_at)
+    {
+      return _timerData.stop_at - _timerData.start_at;
+    }
+
+    return (long long)currentTime - (long long)_timerData.start_at;
+  }
+
+  // the time added to the current segment which is still left
+  long long _extensionLeft(long long elapsedTime)
+  {
+    SegmentExtension &extension = _timerData.extension;
+    if (!extension.valid || elapsedTime <= extension.start)
+    {
+      return 0;
+    }
+
+    long long left = extension.end + extension.time - elapsedTime;
+    return left < 0 ? 0 : (left > extension.time ? extension.time : left);
+  }
+
+  // the time in the playlist, which stands still at the end of an extended segment
+  // until the extension is over
+  long long _playlistTime(long long elapsedTime)
+  {
+    SegmentExtension &extension = _timerData.extension;
+    if (!extension.valid || elapsedTime <= extension.end)
+    {
+      return elapsedTime;
+    }
+
+    if (elapsedTime > extension.end + extension.time)
+    {
+      return elapsedTime - extension.time;
+    }
+
+    return extension.end;
+  }
+
   TIME calculateTimeInCurrentRound(TIME currentTime)
   {
 
@@ -34,12 +75,9 @@ namespace timer
       return totalTimePerRound;
     }
 
-    long long elapsedTime = (long long)currentTime - (long long)_timerData.start_at;
-
-    if (_timerData.stop_at != 0 && currentTime > _timerData.stop_at)
-    {
-      elapsedTime = _timerData.stop_at - _timerData.start_at;
-    }
+    long long elapsedTime = _elapsedTime(currentTime);
+    bool extending = _extensionLeft(elapsedTime) > 0;
+    elapsedTime = _playlistTime(elapsedTime);
 
     if (!_timerData.repeat && elapsedTime > totalTimePerRound)
     {
@@ -48,7 +86,16 @@ namespace timer
 
     // Serial.printf("elapsedTime: %lld, totalTimePerRound: %ld", elapsedTime, totalTimePerRound);
 
-    return (((elapsedTime % totalTimePerRound) + totalTimePerRound) % totalTimePerRound);
+    long long timeInCurrentRound =
+        (((elapsedTime % totalTimePerRound) + totalTimePerRound) % totalTimePerRound);
+
+    // the extended segment is shown until its extension is over, even at the end of a round
+    if (timeInCurrentRound == 0 && extending)
+    {
+      return totalTimePerRound;
+    }
+
+    return timeInCurrentRound;
   }
 
   ActiveSegment calculateCurrentSegment(TIME timeOffset)
@@ -74,6 +121,7 @@ namespace timer
       currentSegmentIndex++;
     }
 
+    timeInCurrentSegment += _extensionLeft(_elapsedTime(currentTime));
     timeInCurrentSegment += _timerData.segments[currentSegmentIndex - 1].count_to;
 
     return {timeInCurrentSegment / 1000,
@@ -0,0 +1,188 @@
+//! The history of the changes to a timer, so it can be traced who changed what and when.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::Role;
+use crate::repository::Timer;
+
+/// Who made a change
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Actor {
+    /// a token issued for the timer
+    Token { role: Role },
+    /// the user account owning the timer
+    User { username: String },
+    /// a group the timer is a member of
+    Group { id: String },
+    /// someone who knows the password of the timer
+    Password,
+    /// a refresh token issued for the timer
+    RefreshToken,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Create,
+    Update,
+    Delete,
+    Start,
+    Stop,
+    Pause,
+    Resume,
+    Next,
+    Previous,
+    Adjust,
+    Token,
+    Logout,
+    Password,
+    Webhooks,
+    Osc,
+    Owner,
+    Revert,
+    /// a copy of the timer was created
+    Clone,
+    /// the timer was added to a group
+    JoinGroup,
+    /// the timer was removed from a group or the group was deleted
+    LeaveGroup,
+    /// the tokens of a group the timer is a member of were revoked
+    LogoutGroup,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    /// unix time in ms
+    pub time: u64,
+    pub action: Action,
+    pub actor: Actor,
+    pub client_ip: String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// The timer as json object, without its secrets
+fn fields(timer: Option<&Timer>) -> serde_json::Map<String, Value> {
+    let mut fields = match timer.map(serde_json::to_value) {
+        Some(Ok(Value::Object(fields))) => fields,
+        _ => return serde_json::Map::new(),
+    };
+
+    // a changed password is recorded, but not the hashes
+    fields.insert("password".to_owned(), Value::Null);
+    if let Some(timer) = timer {
+        let urls = timer.webhooks.iter().map(|w| Value::from(w.url.clone()));
+        fields.insert("webhooks".to_owned(), Value::Array(urls.collect()));
+    }
+
+    fields
+}
+
+/// The fields which differ between two versions of a timer, `None` if it didn't exist
+pub fn diff(before: Option<&Timer>, after: Option<&Timer>) -> Vec<FieldChange> {
+    let password_changed = matches!((before, after), (Some(before), Some(after))
+        if before.password != after.password);
+    let before = fields(before);
+    let after = fields(after);
+
+    let mut names = before.keys().chain(after.keys()).collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .filter(|name| {
+            before.get(*name) != after.get(*name) || (*name == "password" && password_changed)
+        })
+        .map(|name| FieldChange {
+            field: name.clone(),
+            before: before.get(name).cloned().unwrap_or(Value::Null),
+            after: after.get(name).cloned().unwrap_or(Value::Null),
+        })
+        .collect()
+}
+
+/// The entries since the timer was created the last time, entries of
+/// a deleted timer with the same id are left out
+pub fn current_history(entries: Vec<HistoryEntry>) -> Vec<HistoryEntry> {
+    let start = entries
+        .iter()
+        .rposition(|entry| entry.action == Action::Delete)
+        .map(|index| index + 1)
+        .unwrap_or(0);
+
+    entries.into_iter().skip(start).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::Webhook;
+
+    fn entry(action: Action) -> HistoryEntry {
+        HistoryEntry {
+            time: 0,
+            action,
+            actor: Actor::Password,
+            client_ip: "127.0.0.1".to_owned(),
+            changes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = Timer {
+            id: "test".to_owned(),
+            password: "hash".to_owned(),
+            ..Default::default()
+        };
+        let after = Timer {
+            repeat: true,
+            password: "other hash".to_owned(),
+            webhooks: vec![Webhook {
+                url: "http://localhost/hook".to_owned(),
+                secret: "secret".to_owned(),
+            }],
+            ..before.clone()
+        };
+
+        let changes = diff(Some(&before), Some(&after));
+        assert_eq!(
+            changes.iter().map(|c| c.field.as_str()).collect::<Vec<_>>(),
+            vec!["password", "repeat", "webhooks"]
+        );
+        assert_eq!(changes[0].after, Value::Null);
+        assert_eq!(changes[1].after, Value::Bool(true));
+        assert_eq!(
+            changes[2].after,
+            serde_json::json!(["http://localhost/hook"])
+        );
+
+        assert!(diff(Some(&before), Some(&before)).is_empty());
+        assert_eq!(
+            diff(Some(&before), None).len(),
+            diff(None, Some(&before)).len()
+        );
+    }
+
+    #[test]
+    fn test_current_history() {
+        let entries = vec![
+            entry(Action::Create),
+            entry(Action::Delete),
+            entry(Action::Create),
+            entry(Action::Start),
+        ];
+
+        let history = current_history(entries);
+        assert_eq!(history, vec![entry(Action::Create), entry(Action::Start)]);
+    }
+}
@@ -0,0 +1,333 @@
+//! The formats timers can be exported to and imported from.
+//!
+//! Whole timers are exported as a json document which names its format and version,
+//! so older exports can still be imported. The segments can also be exported as csv,
+//! to prepare schedules in a spreadsheet.
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::redis_migrations::{RedisTimer, CURRENT_VERSION};
+use crate::repository::{Segment, Sound, Timer};
+
+pub const FORMAT: &str = "distributed-timer";
+
+#[derive(Serialize)]
+pub struct TimerExport {
+    pub format: &'static str,
+    pub version: u32,
+    /// unix time in ms
+    pub exported_at: u64,
+    pub timer: Timer,
+}
+
+/// An exported timer of any version
+#[derive(Deserialize)]
+pub struct TimerImport {
+    pub format: String,
+    pub version: u32,
+    pub timer: RedisTimer,
+}
+
+/// Exports the timer without its password, webhooks, tokens and revision
+pub fn export_timer(timer: &Timer, now: u64) -> TimerExport {
+    TimerExport {
+        format: FORMAT,
+        version: CURRENT_VERSION,
+        exported_at: now,
+        timer: Timer {
+            password: String::new(),
+            webhooks: Vec::new(),
+            token_generation: 0,
+            owner: None,
+            revision: 0,
+            ..timer.clone()
+        },
+    }
+}
+
+/// The timer of an export, which is upgraded to the current version like stored timers are
+pub fn import_timer(import: TimerImport) -> Result<Timer, String> {
+    if import.format != FORMAT {
+        return Err(format!("Unknown format {}", import.format));
+    }
+
+    if import.version > CURRENT_VERSION {
+        return Err(format!(
+            "Version {} is newer than this server supports",
+            import.version
+        ));
+    }
+
+    Ok(import.timer.into())
+}
+
+#[derive(Serialize, Deserialize)]
+struct CsvSegment {
+    label: String,
+    duration: String,
+    #[serde(default)]
+    color: String,
+    #[serde(default)]
+    count_to: String,
+    #[serde(default)]
+    sounds: String,
+}
+
+/// Formats ms as `[h:]mm:ss[.mmm]`
+fn format_duration(time: u32) -> String {
+    let seconds = time / 1000;
+    let millis = time % 1000;
+
+    let mut duration = match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    };
+    if millis > 0 {
+        duration += &format!(".{:03}", millis);
+    }
+
+    duration
+}
+
+/// Parses seconds or `[[h:]m:]s[.mmm]` to ms
+fn parse_duration(duration: &str) -> Result<u32, String> {
+    let error = || format!("Invalid duration {}", duration);
+    let duration = duration.trim();
+
+    let (time, fraction) = duration.split_once('.').unwrap_or((duration, ""));
+    if fraction.len() > 3 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return Err(error());
+    }
+    let millis = format!("{:0<3}", fraction)
+        .parse::<u32>()
+        .map_err(|_| error())?;
+
+    let parts = time
+        .split(':')
+        .map(|part| part.parse::<u32>().map_err(|_| error()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if parts.len() > 3 {
+        return Err(error());
+    }
+
+    let seconds = parts
+        .iter()
+        .try_fold(0u32, |seconds, part| {
+            seconds.checked_mul(60)?.checked_add(*part)
+        })
+        .ok_or_else(error)?;
+
+    seconds
+        .checked_mul(1000)
+        .and_then(|time| time.checked_add(millis))
+        .ok_or_else(error)
+}
+
+/// Formats sounds as `<trigger time>:<filename>`, separated by commas
+fn format_sounds(sounds: &[Sound]) -> String {
+    let mut sounds = sounds.to_vec();
+    sounds.sort_by_key(|sound| std::cmp::Reverse(sound.trigger_time));
+
+    sounds
+        .iter()
+        .map(|sound| format!("{}:{}", sound.trigger_time, sound.filename))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn parse_sounds(sounds: &str) -> Result<Vec<Sound>, String> {
+    sounds
+        .split(',')
+        .map(str::trim)
+        .filter(|sound| !sound.is_empty())
+        .map(|sound| {
+            let (trigger_time, filename) = sound
+                .split_once(':')
+                .ok_or_else(|| format!("Invalid sound {}", sound))?;
+
+            Ok(Sound {
+                filename: filename.trim().to_owned(),
+                trigger_time: trigger_time
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid sound {}", sound))?,
+            })
+        })
+        .collect()
+}
+
+fn parse_color(color: &str) -> Result<Option<Color>, String> {
+    match color.trim() {
+        "" => Ok(None),
+        color => serde_json::from_value(serde_json::Value::String(color.to_owned()))
+            .map(Some)
+            .map_err(|_| format!("Invalid color {}", color)),
+    }
+}
+
+/// One row with a header per segment: label, duration, color, count_to and sounds
+pub fn segments_to_csv(segments: &[Segment]) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    for segment in segments {
+        writer
+            .serialize(CsvSegment {
+                label: segment.label.clone(),
+                duration: format_duration(segment.time),
+                color: segment
+                    .color
+                    .as_ref()
+                    .map(|color| serde_json::to_value(color).unwrap())
+                    .and_then(|color| color.as_str().map(str::to_owned))
+                    .unwrap_or_default(),
+                count_to: format_duration(segment.count_to),
+                sounds: format_sounds(&segment.sounds),
+            })
+            .unwrap();
+    }
+
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+/// Parses the segments of [`segments_to_csv`], the error names the row which is invalid
+pub fn segments_from_csv(csv: &str) -> Result<Vec<Segment>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::Headers)
+        .from_reader(csv.as_bytes());
+
+    reader
+        .deserialize::<CsvSegment>()
+        .enumerate()
+        .map(|(index, row)| {
+            let row_error = |error: String| format!("Row {}: {}", index + 1, error);
+            let row = row.map_err(|e| row_error(e.to_string()))?;
+
+            Ok(Segment {
+                label: row.label,
+                time: parse_duration(&row.duration).map_err(row_error)?,
+                color: parse_color(&row.color).map_err(row_error)?,
+                count_to: match row.count_to.trim() {
+                    "" => 0,
+                    count_to => parse_duration(count_to).map_err(row_error)?,
+                },
+                sounds: parse_sounds(&row.sounds).map_err(row_error)?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(format_duration(240000), "4:00");
+        assert_eq!(format_duration(11500), "0:11.500");
+        assert_eq!(format_duration(3723000), "1:02:03");
+
+        assert_eq!(parse_duration("240"), Ok(240000));
+        assert_eq!(parse_duration("4:00"), Ok(240000));
+        assert_eq!(parse_duration("0:11.5"), Ok(11500));
+        assert_eq!(parse_duration("1:02:03"), Ok(3723000));
+        assert!(parse_duration("4 minutes").is_err());
+        assert!(parse_duration("1:2:3:4").is_err());
+        assert!(parse_duration("99999999").is_err());
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let csv = "label,duration,color,count_to,sounds\n\
+                   Boulder,3:50,#26A269,0:11,\"60:beep.mp3,5:countdown.mp3\"\n\
+                   Change,15,,,\n";
+
+        let segments = segments_from_csv(csv).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].time, 230000);
+        assert_eq!(segments[0].count_to, 11000);
+        assert_eq!(segments[0].sounds.len(), 2);
+        assert_eq!(segments[0].sounds[1].filename, "countdown.mp3");
+        assert!(segments[1].color.is_none());
+
+        let exported = segments_to_csv(&segments);
+        assert_eq!(
+            exported,
+            "label,duration,color,count_to,sounds\n\
+             Boulder,3:50,#26A269,0:11,\"60:beep.mp3,5:countdown.mp3\"\n\
+             Change,0:15,,0:00,\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_errors() {
+        let csv = "label,duration,color,count_to,sounds\nBoulder,soon,,,\n";
+        assert_eq!(
+            segments_from_csv(csv).err(),
+            Some("Row 1: Invalid duration soon".to_owned())
+        );
+
+        let csv = "label,duration,color,count_to,sounds\nBoulder,1,red,,\n";
+        assert!(segments_from_csv(csv).is_err());
+
+        // only the label and duration are required
+        let segments = segments_from_csv("label,duration\nBoulder,4:00\n").unwrap();
+        assert_eq!(segments[0].time, 240000);
+    }
+
+    #[test]
+    fn test_import_export() {
+        let timer = Timer {
+            id: "test".to_owned(),
+            password: "hash".to_owned(),
+            repeat: true,
+            token_generation: 2,
+            revision: 3,
+            ..Default::default()
+        };
+
+        let export = serde_json::to_string(&export_timer(&timer, 0)).unwrap();
+        assert!(!export.contains("hash"));
+
+        let import: TimerImport = serde_json::from_str(&export).unwrap();
+        let imported = import_timer(import).unwrap();
+        assert!(imported.repeat);
+        assert_eq!(imported.token_generation, 0);
+        assert_eq!(imported.revision, 0);
+    }
+
+    #[test]
+    fn test_import_older_version() {
+        let import = r##"
+            {
+                "format": "distributed-timer",
+                "version": 7,
+                "timer": {
+                    "sequences": [],
+                    "id": "v7",
+                    "repeat": true,
+                    "display_options": null,
+                    "start_at": 0,
+                    "stop_at": null,
+                    "paused_time": 0,
+                    "password": "",
+                    "metadata": {"delay_start_stop": 0},
+                    "webhooks": [],
+                    "osc_targets": [],
+                    "private": false,
+                    "token_generation": 0
+                }
+            }
+            "##;
+
+        let imported = import_timer(serde_json::from_str(import).unwrap()).unwrap();
+        assert_eq!(imported.id, "v7");
+        assert!(imported.owner.is_none());
+
+        let other_format = import.replace("\"distributed-timer\"", "\"other\"");
+        assert!(import_timer(serde_json::from_str(&other_format).unwrap()).is_err());
+
+        let newer_version = import.replace("\"version\": 7", "\"version\": 1000");
+        assert!(import_timer(serde_json::from_str(&newer_version).unwrap()).is_err());
+    }
+}
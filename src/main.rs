@@ -1,20 +1,36 @@
-use axum::{http::Request, response::Response, Router};
+use axum::{http::Request, response::Response};
 use tower_http::catch_panic::CatchPanicLayer;
 
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::{env, time::Duration};
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 use tracing::Span;
+mod audit;
 mod color;
+mod export;
 mod models;
+mod mqtt;
+mod osc;
+mod patch;
 mod redis_migrations;
 mod repository;
 mod routes;
+mod targets;
+mod templates;
+mod timer_events;
+mod timer_state;
+mod validation;
+mod webhooks;
 
 use models::*;
 
-use crate::repository::Repository;
+use crate::mqtt::{spawn_mqtt_bridge, MqttConfig};
+use crate::osc::spawn_osc_sender;
+use crate::repository::{MemoryStorage, RedisStorage, Repository, SqliteStorage};
+use crate::webhooks::WebhookDispatcher;
 
 #[tokio::main]
 async fn main() {
@@ -31,21 +47,60 @@ async fn main() {
         s3_host: env::var("S3_HOST").unwrap_or("".to_owned()),
     };
     let jwt_key = env::var("JWT_KEY").expect("JWT_KEY is not set");
-    let redis_string = env::var("REDIS_STRING").expect("REDIS_STRING is not set");
+    let repository = match env::var("STORAGE_BACKEND")
+        .unwrap_or("redis".to_owned())
+        .as_str()
+    {
+        "redis" => {
+            let redis_string = env::var("REDIS_STRING").expect("REDIS_STRING is not set");
+            Repository::new(RedisStorage::new(redis_string).await)
+        }
+        "memory" => {
+            let snapshot_file = env::var("MEMORY_SNAPSHOT_FILE").ok().map(PathBuf::from);
+            Repository::new(MemoryStorage::new(snapshot_file).await)
+        }
+        "sqlite" => {
+            let sqlite_path = env::var("SQLITE_PATH").expect("SQLITE_PATH is not set");
+            Repository::new(SqliteStorage::new(sqlite_path))
+        }
+        other => panic!("Unknown STORAGE_BACKEND {}", other),
+    };
+
+    if let Ok(mqtt_host) = env::var("MQTT_HOST") {
+        let config = MqttConfig {
+            host: mqtt_host,
+            port: env::var("MQTT_PORT")
+                .map(|port| port.parse().expect("MQTT_PORT is not a number"))
+                .unwrap_or(1883),
+            client_id: env::var("MQTT_CLIENT_ID").unwrap_or("distributed-timer".to_owned()),
+            username: env::var("MQTT_USERNAME").ok(),
+            password: env::var("MQTT_PASSWORD").ok(),
+            topic_prefix: env::var("MQTT_TOPIC_PREFIX").unwrap_or("distributed-timer".to_owned()),
+        };
+        spawn_mqtt_bridge(config, repository.clone());
+    }
+
+    let allow_private_targets = env::var("ALLOW_PRIVATE_TARGETS").unwrap_or_default() == "true";
+
+    let osc_enabled = env::var("OSC_ENABLED").unwrap_or_default() == "true";
+    if osc_enabled {
+        spawn_osc_sender(repository.clone(), allow_private_targets);
+    }
 
-    let repository = Repository::new(redis_string).await;
+    let webhooks = WebhookDispatcher::new(allow_private_targets);
+    webhooks.spawn_transition_task(repository.clone());
 
     let state: SharedState = Arc::new(AppState {
         repository,
+        webhooks,
         jwt_key,
+        client_ip_header: env::var("CLIENT_IP_HEADER").ok(),
+        allow_private_targets,
+        osc_enabled,
         instance_properties,
     });
 
-    let app = Router::new()
-        .nest("/api/ws", routes::ws::routes())
-        .nest("/api/timer", routes::timer::routes(state.clone()))
-        .nest("/api/instance", routes::instance::routes())
-        .fallback(routes::web::web_assets)
+    let app = routes::app(state.clone())
         .layer(cors)
         .layer(
             TraceLayer::new_for_http()
@@ -65,7 +120,7 @@ async fn main() {
 
     // run it with hyper on localhost:3000
     axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 
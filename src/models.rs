@@ -1,4 +1,11 @@
-use crate::repository::{DisplayOptions, Repository, Segment, Timer, TimerMetadata};
+use crate::export::TimerImport;
+use crate::repository::{
+    unroll, DisplayOptions, Repository, Revision, Segment, SegmentExtension, Sequence, Template,
+    Timer, TimerGroup, TimerMetadata,
+};
+use crate::timer_events::TimerEvent;
+use crate::timer_state::TimerState;
+use crate::webhooks::WebhookDispatcher;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -18,33 +25,62 @@ pub struct InstanceProperties {
 pub type SharedState = Arc<AppState>;
 pub struct AppState {
     pub repository: Repository,
+    pub webhooks: WebhookDispatcher,
     pub jwt_key: String,
+    /// the header a reverse proxy puts the address of the client in
+    pub client_ip_header: Option<String>,
+    /// allows webhooks and OSC messages to hosts in the local network of the server
+    pub allow_private_targets: bool,
+    /// whether timers can have OSC targets
+    pub osc_enabled: bool,
     pub instance_properties: InstanceProperties,
 }
 
 //timer.rs
 
+/// Clients which don't know about playlists only send a flat list of segments,
+/// which becomes a playlist with a single sequence
+pub fn playlist(sequences: Vec<Sequence>, segments: Vec<Segment>) -> Vec<Sequence> {
+    if sequences.is_empty() {
+        vec![segments.into()]
+    } else {
+        sequences
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TimerResponse {
+    pub sequences: Vec<Sequence>,
+    /// all segments of the playlist, for clients which don't know about sequences
     pub segments: Vec<Segment>,
     pub id: String,
     pub repeat: bool,
     pub display_options: DisplayOptions,
     pub start_at: u64,
     pub stop_at: Option<u64>,
+    pub paused_time: u64,
     pub metadata: TimerMetadata,
+    pub private: bool,
+    /// increases with every change, the same as the `ETag` of the timer
+    pub revision: u64,
+    pub extension: Option<SegmentExtension>,
 }
 
-impl Into<TimerResponse> for Timer {
-    fn into(self) -> TimerResponse {
+impl From<Timer> for TimerResponse {
+    fn from(value: Timer) -> Self {
         TimerResponse {
-            segments: self.segments,
-            id: self.id,
-            repeat: self.repeat,
-            display_options: self.display_options,
-            start_at: self.start_at,
-            stop_at: self.stop_at,
-            metadata: self.metadata,
+            segments: value.segments(),
+            sequences: value.sequences,
+            id: value.id,
+            repeat: value.repeat,
+            display_options: value.display_options,
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            paused_time: value.paused_time,
+            metadata: value.metadata,
+            private: value.private,
+            revision: value.revision,
+            extension: value.extension,
         }
     }
 }
@@ -52,61 +88,335 @@ impl Into<TimerResponse> for Timer {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TimerCreationResponse {
     pub timer: TimerResponse,
-    pub token: String,
+    #[serde(flatten)]
+    pub tokens: TokenResponse,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct TimerCreationRequest {
+    #[serde(default)]
+    pub sequences: Vec<Sequence>,
+    #[serde(default)]
     pub segments: Vec<Segment>,
     pub id: String,
     pub password: String,
+    #[serde(default)]
     pub repeat: bool,
+    #[serde(default)]
     pub start_at: u64,
+    #[serde(default)]
     pub metadata: TimerMetadata,
+    #[serde(default)]
     pub display_options: DisplayOptions,
+    #[serde(default)]
+    pub private: bool,
+    /// the id of a template which replaces the playlist and settings of the request
+    #[serde(default)]
+    pub template: Option<String>,
 }
 
 impl TimerCreationRequest {
     pub fn into(self, hashed_password: String) -> Timer {
         Timer {
-            segments: self.segments,
+            sequences: playlist(self.sequences, self.segments),
             repeat: self.repeat,
             display_options: self.display_options,
             start_at: self.start_at,
             stop_at: None,
+            paused_time: 0,
             password: hashed_password,
             id: self.id,
             metadata: self.metadata,
+            webhooks: Vec::new(),
+            osc_targets: Vec::new(),
+            private: self.private,
+            token_generation: 0,
+            owner: None,
+            revision: 0,
+            extension: None,
         }
     }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct TimerUpdateRequest {
+    #[serde(default)]
+    pub sequences: Vec<Sequence>,
+    #[serde(default)]
     pub segments: Vec<Segment>,
     pub repeat: bool,
     pub display_options: DisplayOptions,
     pub metadata: TimerMetadata,
     pub start_at: u64,
     pub stop_at: Option<u64>,
+    /// keeps the current setting if not given
+    #[serde(default)]
+    pub private: Option<bool>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TimerStateResponse {
+    pub id: String,
+    /// the server time the state was calculated at
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub state: TimerState,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StartRequest {
+    pub start_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TimerAdjustRequest {
+    /// time in ms added to the current segment, negative values remove time
+    pub offset: i64,
 }
 
+/// What a token may be used for, every role includes the ones before it
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// see private timers
+    Viewer,
+    /// start, stop, pause and skip segments
+    Operator,
+    /// change and delete, tokens from before roles existed are admin tokens
+    #[default]
+    Admin,
+}
+
+/// Logging in with the password always gives an admin token, as the password allows
+/// everything anyway. Tokens with less rights are created with a [`ScopedTokenRequest`]
 #[derive(Serialize, Deserialize)]
 pub struct TokenRequest {
     pub id: String,
     pub password: String,
 }
 
+/// Used by admins to create tokens with less rights, e.g. for judges
+#[derive(Serialize, Deserialize)]
+pub struct ScopedTokenRequest {
+    pub role: Role,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TemplateRequest {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub sequences: Vec<Sequence>,
+    #[serde(default)]
+    pub segments: Vec<Segment>,
+    #[serde(default)]
+    pub repeat: bool,
+    #[serde(default)]
+    pub display_options: DisplayOptions,
+    #[serde(default)]
+    pub metadata: TimerMetadata,
+}
+
+impl From<TemplateRequest> for Template {
+    fn from(value: TemplateRequest) -> Self {
+        Template {
+            id: value.id,
+            name: value.name,
+            sequences: playlist(value.sequences, value.segments),
+            repeat: value.repeat,
+            display_options: value.display_options,
+            metadata: value.metadata,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TemplateResponse {
+    pub id: String,
+    pub name: String,
+    pub sequences: Vec<Sequence>,
+    /// the segments of all sequences, for clients which don't know about sequences
+    pub segments: Vec<Segment>,
+    pub repeat: bool,
+    pub display_options: DisplayOptions,
+    pub metadata: TimerMetadata,
+    /// built-in templates are available to everyone, the others belong to the user
+    pub builtin: bool,
+}
+
+impl TemplateResponse {
+    pub fn new(template: Template, builtin: bool) -> Self {
+        TemplateResponse {
+            segments: unroll(&template.sequences),
+            id: template.id,
+            name: template.name,
+            sequences: template.sequences,
+            repeat: template.repeat,
+            display_options: template.display_options,
+            metadata: template.metadata,
+            builtin,
+        }
+    }
+}
+
+/// The playlist and settings of a previous version of a timer
+#[derive(Serialize, Deserialize)]
+pub struct RevisionResponse {
+    pub number: u64,
+    pub time: u64,
+    pub sequences: Vec<Sequence>,
+    /// the segments of all sequences, for clients which don't know about sequences
+    pub segments: Vec<Segment>,
+    pub repeat: bool,
+    pub display_options: DisplayOptions,
+    pub metadata: TimerMetadata,
+    pub private: bool,
+}
+
+impl From<Revision> for RevisionResponse {
+    fn from(value: Revision) -> Self {
+        RevisionResponse {
+            number: value.number,
+            time: value.time,
+            segments: unroll(&value.sequences),
+            sequences: value.sequences,
+            repeat: value.repeat,
+            display_options: value.display_options,
+            metadata: value.metadata,
+            private: value.private,
+        }
+    }
+}
+
+/// Creates a new timer with the id and password from an export
+#[derive(Deserialize)]
+pub struct TimerImportRequest {
+    pub id: String,
+    pub password: String,
+    pub document: TimerImport,
+}
+
+/// The id and password of the new timer
+#[derive(Serialize, Deserialize)]
+pub struct CloneRequest {
+    pub id: String,
+    pub password: String,
+}
+
+/// Used to create a user account and to log in to it
+#[derive(Serialize, Deserialize)]
+pub struct UserRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PasswordChangeRequest {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenKind {
+    /// used for all requests, expires soon
+    #[default]
+    Access,
+    /// can only be used to get new tokens
+    Refresh,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Claims {
     pub id: String,
     pub exp: usize,
     pub iss: String,
+    #[serde(default)]
+    pub role: Role,
+    #[serde(default)]
+    pub kind: TokenKind,
+    /// the token generation of the timer or group the token was issued for
+    #[serde(default)]
+    pub generation: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TokenResponse {
     pub token: String,
+    pub refresh_token: String,
+}
+
+//webhooks.rs
+
+#[derive(Serialize, Deserialize)]
+pub struct WebhookRequest {
+    pub url: String,
+    /// kept for webhooks which already exist and generated for new ones if not given
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+/// The secret of a webhook is only returned when the webhook is created
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WebhookResponse {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+//group.rs
+
+#[derive(Serialize, Deserialize)]
+pub struct GroupCreationRequest {
+    pub id: String,
+    pub password: String,
+    /// the ids and passwords of the member timers, to prove they may be controlled
+    pub timers: Vec<TokenRequest>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GroupMembersRequest {
+    pub timers: Vec<TokenRequest>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GroupResponse {
+    pub id: String,
+    pub timer_ids: Vec<String>,
+}
+
+impl From<TimerGroup> for GroupResponse {
+    fn from(value: TimerGroup) -> Self {
+        GroupResponse {
+            id: value.id,
+            timer_ids: value
+                .members
+                .into_iter()
+                .map(|member| member.timer_id)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GroupCreationResponse {
+    pub group: GroupResponse,
+    #[serde(flatten)]
+    pub tokens: TokenResponse,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GroupSegmentsRequest {
+    #[serde(default)]
+    pub sequences: Vec<Sequence>,
+    #[serde(default)]
+    pub segments: Vec<Segment>,
+    pub repeat: bool,
 }
 
 ///
@@ -115,23 +425,35 @@ pub struct TokenResponse {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WsTimerResponse {
+    pub sequences: Vec<Sequence>,
     pub segments: Vec<Segment>,
     pub id: String,
     pub repeat: bool,
     pub display_options: DisplayOptions,
     pub start_at: u64,
     pub stop_at: Option<u64>,
+    pub extension: Option<SegmentExtension>,
 }
 
-impl Into<WsTimerResponse> for Timer {
-    fn into(self) -> WsTimerResponse {
+impl From<Timer> for WsTimerResponse {
+    fn from(value: Timer) -> Self {
         WsTimerResponse {
-            segments: self.segments,
-            id: self.id,
-            repeat: self.repeat,
-            display_options: self.display_options,
-            start_at: self.start_at,
-            stop_at: self.stop_at,
+            segments: value.segments(),
+            sequences: value.sequences,
+            id: value.id,
+            repeat: value.repeat,
+            display_options: value.display_options,
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            extension: value.extension,
         }
     }
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WsEventResponse {
+    /// the time the event happened at
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub event: TimerEvent,
+}
@@ -0,0 +1,231 @@
+//! Publishes the configuration, state and events of timers to an MQTT broker.
+//!
+//! For every timer, these topics are used:
+//! - `<prefix>/<id>/config`: the timer, retained
+//! - `<prefix>/<id>/state`: the current segment and remaining time, retained and
+//!   republished every second while the timer is running
+//! - `<prefix>/<id>/event`: the events of the timer as they happen
+
+use std::{collections::HashMap, time::Duration};
+
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+use tokio::task::JoinHandle;
+
+use crate::{
+    models::{TimerResponse, TimerStateResponse, WsEventResponse},
+    repository::{Repository, Timer},
+    timer_events::{track_timers, TrackedTimerUpdate},
+    timer_state::{calculate_state, current_time, TimerStatus},
+};
+
+const STATE_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub topic_prefix: String,
+}
+
+struct MqttBridge {
+    client: AsyncClient,
+    topic_prefix: String,
+}
+
+impl MqttBridge {
+    fn topic(&self, timer_id: &str, kind: &str) -> String {
+        format!("{}/{}/{}", self.topic_prefix, timer_id, kind)
+    }
+
+    fn publish(&self, topic: String, retain: bool, payload: String) {
+        // don't block if the broker is not reachable, the state is republished anyway
+        if let Err(e) = self
+            .client
+            .try_publish(topic, QoS::AtLeastOnce, retain, payload)
+        {
+            println!("Could not publish to MQTT: {}", e);
+        }
+    }
+
+    fn publish_config(&self, timer: &Timer) {
+        let config: TimerResponse = timer.clone().into();
+        self.publish(
+            self.topic(&timer.id, "config"),
+            true,
+            serde_json::to_string(&config).unwrap(),
+        );
+    }
+
+    /// Returns the status of the timer, `None` if it has no state
+    fn publish_state(&self, timer: &Timer) -> Option<TimerStatus> {
+        let timestamp = current_time();
+        let state = calculate_state(timer, timestamp)?;
+        let status = state.status;
+
+        let state = TimerStateResponse {
+            id: timer.id.clone(),
+            timestamp,
+            state,
+        };
+        self.publish(
+            self.topic(&timer.id, "state"),
+            true,
+            serde_json::to_string(&state).unwrap(),
+        );
+
+        Some(status)
+    }
+
+    fn clear(&self, timer_id: &str) {
+        // an empty retained message deletes the retained message
+        self.publish(self.topic(timer_id, "config"), true, String::new());
+        self.publish(self.topic(timer_id, "state"), true, String::new());
+    }
+}
+
+pub fn spawn_mqtt_bridge(config: MqttConfig, repository: Repository) -> JoinHandle<()> {
+    let mut options = MqttOptions::new(config.client_id, config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let (Some(username), Some(password)) = (config.username, config.password) {
+        options.set_credentials(username, password);
+    }
+
+    let (client, mut eventloop) = AsyncClient::new(options, 100);
+    let bridge = MqttBridge {
+        client,
+        topic_prefix: config.topic_prefix,
+    };
+
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = eventloop.poll().await {
+                println!("MQTT connection error: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    });
+
+    // private timers are only published to clients with a token
+    let mut tracker_rx = track_timers(repository, |timer| !timer.private);
+
+    tokio::spawn(async move {
+        // timers which are running and need their state to be republished
+        let mut running: HashMap<String, Timer> = HashMap::new();
+        let mut interval = tokio::time::interval(STATE_INTERVAL);
+
+        loop {
+            tokio::select! {
+                update = tracker_rx.recv() => {
+                    let timer = match update {
+                        Some(TrackedTimerUpdate::Updated(timer)) => {
+                            bridge.publish_config(&timer);
+                            timer
+                        }
+                        Some(TrackedTimerUpdate::Events(timer, timestamp, events)) => {
+                            for event in events {
+                                let event = WsEventResponse { timestamp, event };
+                                bridge.publish(
+                                    bridge.topic(&timer.id, "event"),
+                                    false,
+                                    serde_json::to_string(&event).unwrap(),
+                                );
+                            }
+                            timer
+                        }
+                        Some(TrackedTimerUpdate::Removed(id)) => {
+                            running.remove(&id);
+                            bridge.clear(&id);
+                            continue;
+                        }
+                        None => break,
+                    };
+
+                    if bridge.publish_state(&timer) == Some(TimerStatus::Running) {
+                        running.insert(timer.id.clone(), timer);
+                    } else {
+                        running.remove(&timer.id);
+                    }
+                }
+                _ = interval.tick() => {
+                    running.retain(|_, timer| {
+                        bridge.publish_state(timer) == Some(TimerStatus::Running)
+                    });
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{MemoryStorage, Segment};
+    use rumqttc::{Event, Packet};
+
+    /// Needs a broker, run with `MQTT_TEST_HOST=localhost cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_publish_retained_state() {
+        let host = std::env::var("MQTT_TEST_HOST").unwrap_or("localhost".to_owned());
+        let topic_prefix = format!("distributed-timer-test-{}", current_time());
+
+        let repository = Repository::new(MemoryStorage::new(None).await);
+        spawn_mqtt_bridge(
+            MqttConfig {
+                host: host.clone(),
+                port: 1883,
+                client_id: format!("{}-bridge", topic_prefix),
+                username: None,
+                password: None,
+                topic_prefix: topic_prefix.clone(),
+            },
+            repository.clone(),
+        );
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        repository
+            .create_timer(&Timer {
+                sequences: vec![vec![Segment {
+                    label: "Boulder".to_owned(),
+                    time: 240000,
+                    color: None,
+                    count_to: 0,
+                    sounds: vec![],
+                }]
+                .into()],
+                repeat: true,
+                start_at: current_time(),
+                id: "test".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        // subscribe late, the retained state has to be sent anyway
+        let options = MqttOptions::new(format!("{}-subscriber", topic_prefix), host, 1883);
+        let (client, mut eventloop) = AsyncClient::new(options, 10);
+        client
+            .subscribe(format!("{}/test/state", topic_prefix), QoS::AtLeastOnce)
+            .await
+            .unwrap();
+
+        let publish = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Event::Incoming(Packet::Publish(publish)) = eventloop.poll().await.unwrap() {
+                    return publish;
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert!(publish.retain);
+        let state: serde_json::Value = serde_json::from_slice(&publish.payload).unwrap();
+        assert_eq!(state["id"], "test");
+        assert_eq!(state["status"], "Running");
+        assert_eq!(state["label"], "Boulder");
+    }
+}
@@ -0,0 +1,250 @@
+//! Sends the segment changes, sounds and remaining time of timers to the OSC targets
+//! configured for them, so show-control systems can follow a timer.
+//!
+//! These messages are sent over UDP:
+//! - `/timer/<id>/segment <index> <label>` whenever the current segment changes
+//! - `/timer/<id>/remaining <seconds>` whenever the shown seconds change
+//! - `/timer/<id>/sound <filename> <trigger_time>` when a sound is played
+
+use std::{collections::HashMap, time::Duration};
+
+use rosc::{OscMessage, OscPacket, OscType};
+use tokio::{net::UdpSocket, task::JoinHandle};
+
+use crate::{
+    repository::{Repository, Timer},
+    targets::resolve,
+    timer_events::{track_timers, TimerEvent, TrackedTimerUpdate},
+    timer_state::{calculate_state, current_time, TimerState, TimerStatus},
+};
+
+const STATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What was sent last for a timer, so only changes are sent
+#[derive(Clone, Copy, PartialEq)]
+struct SentState {
+    round: u64,
+    segment_index: usize,
+    seconds: i32,
+}
+
+fn sound_message(timer_id: &str, event: &TimerEvent) -> Option<OscMessage> {
+    match event {
+        TimerEvent::SoundTrigger {
+            filename,
+            trigger_time,
+            ..
+        } => Some(OscMessage {
+            addr: format!("/timer/{}/sound", timer_id),
+            args: vec![
+                OscType::String(filename.clone()),
+                OscType::Int(*trigger_time as i32),
+            ],
+        }),
+        _ => None,
+    }
+}
+
+/// The messages for everything which changed since `last`
+fn state_messages(timer_id: &str, state: &TimerState, last: Option<SentState>) -> Vec<OscMessage> {
+    let mut messages = vec![];
+    let seconds = (state.display_time / 1000) as i32;
+    let segment_changed = !matches!(last, Some(last)
+        if last.round == state.round && last.segment_index == state.segment_index);
+
+    if segment_changed {
+        messages.push(OscMessage {
+            addr: format!("/timer/{}/segment", timer_id),
+            args: vec![
+                OscType::Int(state.segment_index as i32),
+                OscType::String(state.label.clone()),
+            ],
+        });
+    }
+
+    if segment_changed || !matches!(last, Some(last) if last.seconds == seconds) {
+        messages.push(OscMessage {
+            addr: format!("/timer/{}/remaining", timer_id),
+            args: vec![OscType::Int(seconds)],
+        });
+    }
+
+    messages
+}
+
+async fn send(socket: &UdpSocket, timer: &Timer, message: OscMessage, allow_private: bool) {
+    let packet = rosc::encoder::encode(&OscPacket::Message(message)).unwrap();
+
+    for target in &timer.osc_targets {
+        // resolved for every message, the host could resolve to a private address by now
+        let result = match resolve(&target.host, target.port, allow_private).await {
+            Ok(addresses) if !addresses.is_empty() => socket
+                .send_to(&packet, addresses[0])
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            Ok(_) => Err(format!("{} has no addresses", target.host)),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            println!(
+                "Could not send OSC to {}:{}: {}",
+                target.host, target.port, e
+            );
+        }
+    }
+}
+
+/// Sends the changes of the state since `last` and returns the status of the timer
+/// and what was sent
+async fn send_state(
+    socket: &UdpSocket,
+    timer: &Timer,
+    last: Option<SentState>,
+    allow_private: bool,
+) -> Option<(TimerStatus, SentState)> {
+    let state = calculate_state(timer, current_time())?;
+
+    for message in state_messages(&timer.id, &state, last) {
+        send(socket, timer, message, allow_private).await;
+    }
+
+    Some((
+        state.status,
+        SentState {
+            round: state.round,
+            segment_index: state.segment_index,
+            seconds: (state.display_time / 1000) as i32,
+        },
+    ))
+}
+
+/// Sends the state and sounds of all timers with OSC targets.
+///
+/// Unless `allow_private_targets` is set, messages are only sent to public addresses.
+pub fn spawn_osc_sender(repository: Repository, allow_private_targets: bool) -> JoinHandle<()> {
+    let mut tracker_rx = track_timers(repository, |timer| !timer.osc_targets.is_empty());
+
+    tokio::spawn(async move {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .expect("Could not bind OSC socket");
+
+        let mut sent: HashMap<String, SentState> = HashMap::new();
+        // timers which are running and need their state to be checked for changes
+        let mut running: HashMap<String, Timer> = HashMap::new();
+        let mut interval = tokio::time::interval(STATE_INTERVAL);
+
+        loop {
+            let timers = tokio::select! {
+                update = tracker_rx.recv() => {
+                    match update {
+                        Some(TrackedTimerUpdate::Updated(timer)) => vec![timer],
+                        Some(TrackedTimerUpdate::Events(timer, _, events)) => {
+                            for message in events
+                                .iter()
+                                .filter_map(|event| sound_message(&timer.id, event))
+                            {
+                                send(&socket, &timer, message, allow_private_targets).await;
+                            }
+                            vec![timer]
+                        }
+                        Some(TrackedTimerUpdate::Removed(id)) => {
+                            running.remove(&id);
+                            sent.remove(&id);
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+                _ = interval.tick() => running.values().cloned().collect(),
+            };
+
+            for timer in timers {
+                let last = sent.get(&timer.id).copied();
+                match send_state(&socket, &timer, last, allow_private_targets).await {
+                    Some((status, state)) => {
+                        sent.insert(timer.id.clone(), state);
+                        if status == TimerStatus::Running {
+                            running.insert(timer.id.clone(), timer);
+                        } else {
+                            running.remove(&timer.id);
+                        }
+                    }
+                    None => {
+                        running.remove(&timer.id);
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(round: u64, segment_index: usize, display_time: u64) -> TimerState {
+        TimerState {
+            status: TimerStatus::Running,
+            round,
+            segment_index,
+            sequence_index: 0,
+            sequence_name: String::new(),
+            label: "Boulder".to_owned(),
+            color: None,
+            time_in_round: 0,
+            time_remaining: display_time,
+            display_time,
+        }
+    }
+
+    #[test]
+    fn test_state_messages() {
+        let messages = state_messages("test", &state(0, 0, 59500), None);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].addr, "/timer/test/segment");
+        assert_eq!(
+            messages[0].args,
+            vec![OscType::Int(0), OscType::String("Boulder".to_owned())]
+        );
+        assert_eq!(messages[1].addr, "/timer/test/remaining");
+        assert_eq!(messages[1].args, vec![OscType::Int(59)]);
+
+        let last = SentState {
+            round: 0,
+            segment_index: 0,
+            seconds: 59,
+        };
+        assert!(state_messages("test", &state(0, 0, 59000), Some(last)).is_empty());
+
+        let messages = state_messages("test", &state(0, 0, 58999), Some(last));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].args, vec![OscType::Int(58)]);
+
+        // the same segment in the next round is a new segment
+        let messages = state_messages("test", &state(1, 0, 59500), Some(last));
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].addr, "/timer/test/segment");
+    }
+
+    #[test]
+    fn test_sound_message() {
+        let message = sound_message(
+            "test",
+            &TimerEvent::SoundTrigger {
+                segment_index: 0,
+                filename: "beep.mp3".to_owned(),
+                trigger_time: 60,
+            },
+        )
+        .unwrap();
+        assert_eq!(message.addr, "/timer/test/sound");
+        assert_eq!(
+            message.args,
+            vec![OscType::String("beep.mp3".to_owned()), OscType::Int(60)]
+        );
+
+        assert!(sound_message("test", &TimerEvent::TimerFinished).is_none());
+    }
+}
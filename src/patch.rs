@@ -0,0 +1,148 @@
+use json_patch::Patch;
+use serde_json::Value;
+
+use crate::models::TimerUpdateRequest;
+use crate::repository::Timer;
+use crate::validation::FieldError;
+
+/// A change of some settings of a timer, which are applied to the fields of a
+/// [`TimerUpdateRequest`] with the current settings
+pub enum TimerPatch {
+    /// [JSON Merge Patch](https://www.rfc-editor.org/rfc/rfc7386), replaces the fields it contains
+    Merge(Value),
+    /// [JSON Patch](https://www.rfc-editor.org/rfc/rfc6902), a list of operations,
+    /// e.g. to change a single segment
+    Operations(Patch),
+}
+
+/// The current settings of the timer, as they would be sent to update it
+fn update_document(timer: &Timer) -> Value {
+    serde_json::to_value(TimerUpdateRequest {
+        sequences: timer.sequences.clone(),
+        segments: timer.segments(),
+        repeat: timer.repeat,
+        display_options: timer.display_options.clone(),
+        metadata: timer.metadata.clone(),
+        start_at: timer.start_at,
+        stop_at: timer.stop_at,
+        private: Some(timer.private),
+    })
+    .unwrap()
+}
+
+/// Applies the patch to the settings of the timer.
+///
+/// The playlist can be changed through `sequences` or, for clients which don't know
+/// about sequences, through `segments`, which replaces the playlist with a single sequence.
+pub fn patch_timer(timer: &Timer, patch: TimerPatch) -> Result<TimerUpdateRequest, FieldError> {
+    let original = update_document(timer);
+    let mut document = original.clone();
+
+    match patch {
+        TimerPatch::Merge(patch) => json_patch::merge(&mut document, &patch),
+        TimerPatch::Operations(patch) => json_patch::patch(&mut document, &patch)
+            .map_err(|e| FieldError::new(e.path.clone(), e.to_string()))?,
+    }
+
+    let sequences_changed = document.get("sequences") != original.get("sequences");
+    let segments_changed = document.get("segments") != original.get("segments");
+    if sequences_changed && segments_changed {
+        return Err(FieldError::new(
+            "segments",
+            "Only one of sequences and segments can be changed",
+        ));
+    }
+
+    let mut request: TimerUpdateRequest =
+        serde_json::from_value(document).map_err(|e| FieldError::new("", e.to_string()))?;
+    // only the changed one is kept, as a request with both has to have matching ones
+    if segments_changed {
+        request.sequences = Vec::new();
+    } else {
+        request.segments = Vec::new();
+    }
+
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::models::playlist;
+    use crate::repository::{Segment, Sequence};
+
+    fn segment(label: &str) -> Segment {
+        Segment {
+            label: label.to_owned(),
+            time: 1000,
+            color: None,
+            count_to: 0,
+            sounds: vec![],
+        }
+    }
+
+    fn timer() -> Timer {
+        Timer {
+            sequences: vec![Sequence {
+                name: "Qualification".to_owned(),
+                segments: vec![segment("Boulder"), segment("Change")],
+                repetitions: 2,
+            }],
+            start_at: 1000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let patch = TimerPatch::Merge(json!({"repeat": true, "metadata": {"delay_start_stop": 5}}));
+        let request = patch_timer(&timer(), patch).unwrap();
+
+        assert!(request.repeat);
+        assert_eq!(request.metadata.delay_start_stop, 5);
+        // everything else is kept
+        assert_eq!(request.start_at, 1000);
+        assert_eq!(request.sequences[0].name, "Qualification");
+        assert_eq!(request.sequences[0].repetitions, 2);
+    }
+
+    #[test]
+    fn test_json_patch() {
+        let patch: Patch = serde_json::from_value(json!([
+            {"op": "replace", "path": "/sequences/0/segments/1/label", "value": "Rest"},
+        ]))
+        .unwrap();
+        let request = patch_timer(&timer(), TimerPatch::Operations(patch)).unwrap();
+        let sequences = playlist(request.sequences, request.segments);
+        assert_eq!(sequences[0].segments[1].label, "Rest");
+        assert_eq!(sequences[0].repetitions, 2);
+
+        let patch: Patch = serde_json::from_value(json!([
+            {"op": "test", "path": "/repeat", "value": true},
+        ]))
+        .unwrap();
+        assert!(patch_timer(&timer(), TimerPatch::Operations(patch)).is_err());
+    }
+
+    #[test]
+    fn test_patch_segments() {
+        // changing the unrolled segments replaces the playlist
+        let patch: Patch = serde_json::from_value(json!([
+            {"op": "remove", "path": "/segments/3"},
+        ]))
+        .unwrap();
+        let request = patch_timer(&timer(), TimerPatch::Operations(patch)).unwrap();
+        let sequences = playlist(request.sequences, request.segments);
+        assert_eq!(sequences.len(), 1);
+        assert_eq!(sequences[0].segments.len(), 3);
+        assert_eq!(sequences[0].repetitions, 1);
+
+        let patch = TimerPatch::Merge(json!({"sequences": [], "segments": []}));
+        assert!(patch_timer(&timer(), patch).is_err());
+
+        let patch = TimerPatch::Merge(json!({"repeat": "yes"}));
+        assert!(patch_timer(&timer(), patch).is_err());
+    }
+}
@@ -10,17 +10,17 @@ pub enum RedisDisplayOptions {
     V0(DisplayOptionsV0),
 }
 
-impl Into<DisplayOptions> for RedisDisplayOptions {
-    fn into(self) -> DisplayOptions {
-        match self {
+impl From<RedisDisplayOptions> for DisplayOptions {
+    fn from(value: RedisDisplayOptions) -> Self {
+        match value {
             RedisDisplayOptions::V0(v0) => v0.into(),
         }
     }
 }
 
-impl Into<DisplayOptions> for Option<RedisDisplayOptions> {
-    fn into(self) -> DisplayOptions {
-        self.map(|o| o.into()).unwrap_or(DisplayOptions::default())
+impl From<Option<RedisDisplayOptions>> for DisplayOptions {
+    fn from(value: Option<RedisDisplayOptions>) -> Self {
+        value.map(|o| o.into()).unwrap_or_default()
     }
 }
 
@@ -32,17 +32,17 @@ pub struct DisplayOptionsV0 {
     pre_start_behaviour: RedisPreStartBehaviour,
 }
 
-impl Into<DisplayOptions> for DisplayOptionsV0 {
-    fn into(self) -> DisplayOptions {
+impl From<DisplayOptionsV0> for DisplayOptions {
+    fn from(value: DisplayOptionsV0) -> Self {
         DisplayOptions {
-            clock: self.clock,
-            pre_start_behaviour: self.pre_start_behaviour.into(),
+            clock: value.clock,
+            pre_start_behaviour: value.pre_start_behaviour.into(),
         }
     }
 }
 
-impl Into<DisplayOptions> for Option<DisplayOptionsV0> {
-    fn into(self) -> DisplayOptions {
-        self.map(|o| o.into()).unwrap_or(DisplayOptions::default())
+impl From<Option<DisplayOptionsV0>> for DisplayOptions {
+    fn from(value: Option<DisplayOptionsV0>) -> Self {
+        value.map(|o| o.into()).unwrap_or_default()
     }
 }
@@ -0,0 +1,32 @@
+use serde::Deserialize;
+
+use crate::repository::GroupMember;
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RedisGroupMember {
+    V0(GroupMemberV0),
+}
+
+impl From<RedisGroupMember> for GroupMember {
+    fn from(value: RedisGroupMember) -> Self {
+        match value {
+            RedisGroupMember::V0(v0) => v0.into(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct GroupMemberV0 {
+    pub timer_id: String,
+    pub token_generation: u64,
+}
+
+impl From<GroupMemberV0> for GroupMember {
+    fn from(value: GroupMemberV0) -> Self {
+        GroupMember {
+            timer_id: value.timer_id,
+            token_generation: value.token_generation,
+        }
+    }
+}
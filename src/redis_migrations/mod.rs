@@ -1,9 +1,22 @@
 mod display_options;
+mod group_member;
+mod osc_target;
 mod pre_start_behaviour;
+mod revision;
 mod segment;
+mod segment_extension;
+mod sequence;
 mod sound;
+mod template;
 mod tests;
 mod timer;
+mod timer_group;
 mod timer_metadata;
+mod user;
+mod webhook;
 
-pub use timer::RedisTimer;
+pub use revision::RedisRevision;
+pub use template::RedisTemplate;
+pub use timer::{RedisTimer, CURRENT_VERSION};
+pub use timer_group::RedisTimerGroup;
+pub use user::RedisUser;
@@ -0,0 +1,32 @@
+use serde::Deserialize;
+
+use crate::repository::OscTarget;
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RedisOscTarget {
+    V0(OscTargetV0),
+}
+
+impl From<RedisOscTarget> for OscTarget {
+    fn from(value: RedisOscTarget) -> Self {
+        match value {
+            RedisOscTarget::V0(v0) => v0.into(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct OscTargetV0 {
+    pub host: String,
+    pub port: u16,
+}
+
+impl From<OscTargetV0> for OscTarget {
+    fn from(value: OscTargetV0) -> Self {
+        OscTarget {
+            host: value.host,
+            port: value.port,
+        }
+    }
+}
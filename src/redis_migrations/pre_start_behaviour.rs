@@ -9,9 +9,9 @@ pub enum RedisPreStartBehaviour {
     V0(PreStartBehaviourV0),
 }
 
-impl Into<PreStartBehaviour> for RedisPreStartBehaviour {
-    fn into(self) -> PreStartBehaviour {
-        match self {
+impl From<RedisPreStartBehaviour> for PreStartBehaviour {
+    fn from(value: RedisPreStartBehaviour) -> Self {
+        match value {
             RedisPreStartBehaviour::V0(v0) => v0.into(),
             RedisPreStartBehaviour::V1(v1) => v1.into(),
         }
@@ -25,22 +25,17 @@ impl Default for RedisPreStartBehaviour {
 }
 
 /// === V1 ===
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Default)]
 pub enum PreStartBehaviourV1 {
+    #[default]
     ShowFirstSegment,
     ShowLastSegment,
     RunNormally,
 }
 
-impl Default for PreStartBehaviourV1 {
-    fn default() -> Self {
-        PreStartBehaviourV1::ShowFirstSegment
-    }
-}
-
-impl Into<PreStartBehaviour> for PreStartBehaviourV1 {
-    fn into(self) -> PreStartBehaviour {
-        match self {
+impl From<PreStartBehaviourV1> for PreStartBehaviour {
+    fn from(value: PreStartBehaviourV1) -> Self {
+        match value {
             PreStartBehaviourV1::RunNormally => PreStartBehaviour::RunNormally,
             PreStartBehaviourV1::ShowFirstSegment => PreStartBehaviour::ShowFirstSegment,
             PreStartBehaviourV1::ShowLastSegment => PreStartBehaviour::ShowLastSegment,
@@ -49,21 +44,16 @@ impl Into<PreStartBehaviour> for PreStartBehaviourV1 {
 }
 
 /// === V0 ===
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Default)]
 pub enum PreStartBehaviourV0 {
+    #[default]
     ShowZero,
     RunNormally,
 }
 
-impl Default for PreStartBehaviourV0 {
-    fn default() -> Self {
-        PreStartBehaviourV0::ShowZero
-    }
-}
-
-impl Into<PreStartBehaviour> for PreStartBehaviourV0 {
-    fn into(self) -> PreStartBehaviour {
-        match self {
+impl From<PreStartBehaviourV0> for PreStartBehaviour {
+    fn from(value: PreStartBehaviourV0) -> Self {
+        match value {
             PreStartBehaviourV0::RunNormally => PreStartBehaviour::RunNormally,
             PreStartBehaviourV0::ShowZero => PreStartBehaviour::ShowFirstSegment,
         }
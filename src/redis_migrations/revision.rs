@@ -0,0 +1,75 @@
+use serde::Deserialize;
+
+use crate::repository::Revision;
+
+use super::display_options::RedisDisplayOptions;
+use super::sequence::RedisSequence;
+use super::timer::RedisTimer;
+use super::timer_metadata::RedisTimerMetadata;
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RedisRevision {
+    V1(RevisionV1),
+    V0(RevisionV0),
+}
+
+impl From<RedisRevision> for Revision {
+    fn from(value: RedisRevision) -> Self {
+        match value {
+            RedisRevision::V1(v1) => v1.into(),
+            RedisRevision::V0(v0) => v0.into(),
+        }
+    }
+}
+
+/// === V1 ===
+/// Only the playlist and settings, which are restored by a revert
+#[derive(Deserialize, Clone)]
+pub struct RevisionV1 {
+    pub number: u64,
+    pub time: u64,
+    pub sequences: Vec<RedisSequence>,
+    pub repeat: bool,
+    pub display_options: RedisDisplayOptions,
+    pub metadata: RedisTimerMetadata,
+    pub private: bool,
+}
+
+impl From<RevisionV1> for Revision {
+    fn from(value: RevisionV1) -> Self {
+        Revision {
+            number: value.number,
+            time: value.time,
+            sequences: value.sequences.into_iter().map(|s| s.into()).collect(),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            metadata: value.metadata.into(),
+            private: value.private,
+        }
+    }
+}
+
+/// === V0 ===
+/// The whole timer, stored together with the other revisions of the timer in one document
+#[derive(Deserialize, Clone)]
+pub struct RevisionV0 {
+    pub number: u64,
+    pub time: u64,
+    pub timer: RedisTimer,
+}
+
+impl From<RevisionV0> for Revision {
+    fn from(value: RevisionV0) -> Self {
+        let timer: crate::repository::Timer = value.timer.into();
+        Revision {
+            number: value.number,
+            time: value.time,
+            sequences: timer.sequences,
+            repeat: timer.repeat,
+            display_options: timer.display_options,
+            metadata: timer.metadata,
+            private: timer.private,
+        }
+    }
+}
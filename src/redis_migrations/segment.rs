@@ -14,9 +14,9 @@ pub enum RedisSegment {
     V0(SegmentV0),
 }
 
-impl Into<Segment> for RedisSegment {
-    fn into(self) -> Segment {
-        match self {
+impl From<RedisSegment> for Segment {
+    fn from(value: RedisSegment) -> Self {
+        match value {
             RedisSegment::V0(v0) => v0.into(),
             RedisSegment::V1(v1) => v1.into(),
         }
@@ -39,14 +39,14 @@ pub struct SegmentV1 {
     sounds: Vec<RedisSound>,
 }
 
-impl Into<Segment> for SegmentV1 {
-    fn into(self) -> Segment {
+impl From<SegmentV1> for Segment {
+    fn from(value: SegmentV1) -> Self {
         Segment {
-            label: self.label,
-            time: self.time,
-            color: self.color,
-            count_to: self.count_to,
-            sounds: self.sounds.into_iter().map(|s| s.into()).collect(),
+            label: value.label,
+            time: value.time,
+            color: value.color,
+            count_to: value.count_to,
+            sounds: value.sounds.into_iter().map(|s| s.into()).collect(),
         }
     }
 }
@@ -63,11 +63,11 @@ pub struct SegmentV0 {
     count_to: u32,
 }
 
-impl Into<Segment> for SegmentV0 {
-    fn into(self) -> Segment {
+impl From<SegmentV0> for Segment {
+    fn from(value: SegmentV0) -> Self {
         let mut sounds: Vec<Sound> = Vec::new();
 
-        if self.sound {
+        if value.sound {
             sounds.push(Sound {
                 filename: "beep.mp3".to_string(),
                 trigger_time: 60,
@@ -79,11 +79,11 @@ impl Into<Segment> for SegmentV0 {
         }
 
         Segment {
-            label: self.label,
-            time: self.time,
-            color: self.color,
-            count_to: self.count_to,
-            sounds: sounds,
+            label: value.label,
+            time: value.time,
+            color: value.color,
+            count_to: value.count_to,
+            sounds,
         }
     }
 }
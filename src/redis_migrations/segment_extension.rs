@@ -0,0 +1,34 @@
+use serde::Deserialize;
+
+use crate::repository::SegmentExtension;
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RedisSegmentExtension {
+    V0(SegmentExtensionV0),
+}
+
+impl From<RedisSegmentExtension> for SegmentExtension {
+    fn from(value: RedisSegmentExtension) -> Self {
+        match value {
+            RedisSegmentExtension::V0(v0) => v0.into(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SegmentExtensionV0 {
+    pub start: u64,
+    pub end: u64,
+    pub time: u64,
+}
+
+impl From<SegmentExtensionV0> for SegmentExtension {
+    fn from(value: SegmentExtensionV0) -> Self {
+        SegmentExtension {
+            start: value.start,
+            end: value.end,
+            time: value.time,
+        }
+    }
+}
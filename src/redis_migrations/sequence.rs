@@ -0,0 +1,36 @@
+use serde::Deserialize;
+
+use crate::repository::Sequence;
+
+use super::segment::RedisSegment;
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RedisSequence {
+    V0(SequenceV0),
+}
+
+impl From<RedisSequence> for Sequence {
+    fn from(value: RedisSequence) -> Self {
+        match value {
+            RedisSequence::V0(v0) => v0.into(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SequenceV0 {
+    pub name: String,
+    pub segments: Vec<RedisSegment>,
+    pub repetitions: u32,
+}
+
+impl From<SequenceV0> for Sequence {
+    fn from(value: SequenceV0) -> Self {
+        Sequence {
+            name: value.name,
+            segments: value.segments.into_iter().map(|s| s.into()).collect(),
+            repetitions: value.repetitions,
+        }
+    }
+}
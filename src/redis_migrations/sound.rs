@@ -8,9 +8,9 @@ pub enum RedisSound {
     V0(SoundV0),
 }
 
-impl Into<Sound> for RedisSound {
-    fn into(self) -> Sound {
-        match self {
+impl From<RedisSound> for Sound {
+    fn from(value: RedisSound) -> Self {
+        match value {
             RedisSound::V0(v0) => v0.into(),
         }
     }
@@ -22,11 +22,11 @@ pub struct SoundV0 {
     pub trigger_time: u32,
 }
 
-impl Into<Sound> for SoundV0 {
-    fn into(self) -> Sound {
+impl From<SoundV0> for Sound {
+    fn from(value: SoundV0) -> Self {
         Sound {
-            filename: self.filename,
-            trigger_time: self.trigger_time,
+            filename: value.filename,
+            trigger_time: value.trigger_time,
         }
     }
 }
@@ -0,0 +1,45 @@
+use serde::Deserialize;
+
+use crate::repository::Template;
+
+use super::display_options::RedisDisplayOptions;
+use super::sequence::RedisSequence;
+use super::timer_metadata::RedisTimerMetadata;
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RedisTemplate {
+    V0(TemplateV0),
+}
+
+impl From<RedisTemplate> for Template {
+    fn from(value: RedisTemplate) -> Self {
+        match value {
+            RedisTemplate::V0(v0) => v0.into(),
+        }
+    }
+}
+
+/// === V0 ===
+#[derive(Deserialize, Clone)]
+pub struct TemplateV0 {
+    pub id: String,
+    pub name: String,
+    pub sequences: Vec<RedisSequence>,
+    pub repeat: bool,
+    pub display_options: RedisDisplayOptions,
+    pub metadata: RedisTimerMetadata,
+}
+
+impl From<TemplateV0> for Template {
+    fn from(value: TemplateV0) -> Self {
+        Template {
+            id: value.id,
+            name: value.name,
+            sequences: value.sequences.into_iter().map(|s| s.into()).collect(),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            metadata: value.metadata.into(),
+        }
+    }
+}
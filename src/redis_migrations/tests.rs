@@ -1,7 +1,13 @@
 #[allow(unused_imports)]
 use crate::repository::PreStartBehaviour;
 #[allow(unused_imports)]
-use crate::{redis_migrations::timer::RedisTimer, repository::Timer};
+use crate::{
+    redis_migrations::{
+        revision::RedisRevision, template::RedisTemplate, timer::RedisTimer,
+        timer_group::RedisTimerGroup, user::RedisUser,
+    },
+    repository::{unroll, Revision, Template, Timer, TimerGroup, User},
+};
 
 #[test]
 fn test_v0() {
@@ -30,13 +36,15 @@ fn test_v0() {
 
     let timer: RedisTimer = serde_json::from_str(payload).unwrap();
     let timer: Timer = timer.into();
-    assert_eq!(timer.segments.len(), 1);
-    assert_eq!(timer.segments[0].label, "Boulder");
-    assert_eq!(timer.segments[0].sounds.len(), 2);
-    assert_eq!(timer.segments[0].sounds[0].filename, "beep.mp3");
-    assert_eq!(timer.segments[0].sounds[0].trigger_time, 60);
-    assert_eq!(timer.segments[0].sounds[1].filename, "countdown.mp3");
-    assert_eq!(timer.segments[0].sounds[1].trigger_time, 5);
+    assert_eq!(timer.sequences.len(), 1);
+    assert_eq!(timer.sequences[0].repetitions, 1);
+    assert_eq!(timer.segments().len(), 1);
+    assert_eq!(timer.segments()[0].label, "Boulder");
+    assert_eq!(timer.segments()[0].sounds.len(), 2);
+    assert_eq!(timer.segments()[0].sounds[0].filename, "beep.mp3");
+    assert_eq!(timer.segments()[0].sounds[0].trigger_time, 60);
+    assert_eq!(timer.segments()[0].sounds[1].filename, "countdown.mp3");
+    assert_eq!(timer.segments()[0].sounds[1].trigger_time, 5);
     assert_eq!(timer.metadata.delay_start_stop, 0);
     assert_eq!(
         timer.display_options.pre_start_behaviour,
@@ -79,14 +87,387 @@ fn test_v1() {
 
     let timer: RedisTimer = serde_json::from_str(payload).unwrap();
     let timer: Timer = timer.into();
-    assert_eq!(timer.segments.len(), 1);
-    assert_eq!(timer.segments[0].label, "Boulder");
-    assert_eq!(timer.segments[0].sounds.len(), 1);
-    assert_eq!(timer.segments[0].sounds[0].filename, "beep.mp3");
-    assert_eq!(timer.segments[0].sounds[0].trigger_time, 60);
+    assert_eq!(timer.segments().len(), 1);
+    assert_eq!(timer.segments()[0].label, "Boulder");
+    assert_eq!(timer.segments()[0].sounds.len(), 1);
+    assert_eq!(timer.segments()[0].sounds[0].filename, "beep.mp3");
+    assert_eq!(timer.segments()[0].sounds[0].trigger_time, 60);
     assert_eq!(timer.metadata.delay_start_stop, 5);
     assert_eq!(
         timer.display_options.pre_start_behaviour,
         PreStartBehaviour::ShowLastSegment
     );
 }
+
+#[test]
+fn test_v2() {
+    let payload = r##"
+        {
+            "segments":[
+               {
+                  "label":"Boulder",
+                  "time":230000,
+                  "color":"#26A269",
+                  "count_to":11000,
+                  "sounds": []
+               }
+            ],
+            "id":"v2",
+            "repeat":true,
+            "display_options":{
+               "clock":false,
+               "pre_start_behaviour":"ShowLastSegment"
+            },
+            "start_at":1688236579108,
+            "stop_at":1688236589108,
+            "paused_time":5000,
+            "password": "test",
+            "metadata": {
+               "delay_start_stop": 5
+            }
+         }
+        "##;
+
+    let timer: RedisTimer = serde_json::from_str(payload).unwrap();
+    let timer: Timer = timer.into();
+    assert_eq!(timer.paused_time, 5000);
+    assert_eq!(timer.stop_at, Some(1688236589108));
+    assert_eq!(timer.metadata.delay_start_stop, 5);
+}
+
+#[test]
+fn test_v4() {
+    let payload = r##"
+        {
+            "segments":[],
+            "id":"v4",
+            "repeat":false,
+            "display_options":null,
+            "start_at":1688236579108,
+            "stop_at":null,
+            "paused_time":0,
+            "password": "test",
+            "metadata": {
+               "delay_start_stop": 0
+            },
+            "webhooks": [
+               {
+                  "url": "https://example.com/hook",
+                  "secret": "secret"
+               }
+            ],
+            "osc_targets": [
+               {
+                  "host": "192.168.1.20",
+                  "port": 53000
+               }
+            ]
+         }
+        "##;
+
+    let timer: RedisTimer = serde_json::from_str(payload).unwrap();
+    let timer: Timer = timer.into();
+    assert_eq!(timer.webhooks.len(), 1);
+    assert_eq!(timer.webhooks[0].url, "https://example.com/hook");
+    assert_eq!(timer.osc_targets.len(), 1);
+    assert_eq!(timer.osc_targets[0].host, "192.168.1.20");
+    assert_eq!(timer.osc_targets[0].port, 53000);
+}
+
+#[test]
+fn test_v5() {
+    let payload = r##"
+        {
+            "sequences":[
+               {
+                  "name":"Qualification",
+                  "segments":[
+                     {
+                        "label":"Boulder",
+                        "time":240000,
+                        "color":"#26A269",
+                        "count_to":0,
+                        "sounds":[]
+                     }
+                  ],
+                  "repetitions":5
+               },
+               {
+                  "name":"Break",
+                  "segments":[],
+                  "repetitions":1
+               }
+            ],
+            "id":"v5",
+            "repeat":false,
+            "display_options":null,
+            "start_at":1688236579108,
+            "stop_at":null,
+            "paused_time":0,
+            "password": "test",
+            "metadata": {
+               "delay_start_stop": 0
+            },
+            "webhooks": [],
+            "osc_targets": []
+         }
+        "##;
+
+    let timer: RedisTimer = serde_json::from_str(payload).unwrap();
+    let timer: Timer = timer.into();
+    assert_eq!(timer.sequences.len(), 2);
+    assert_eq!(timer.sequences[0].name, "Qualification");
+    assert_eq!(timer.sequences[0].repetitions, 5);
+    assert_eq!(timer.segments().len(), 5);
+}
+
+#[test]
+fn test_v6() {
+    let payload = r##"
+        {
+            "sequences":[],
+            "id":"v6",
+            "repeat":false,
+            "display_options":null,
+            "start_at":1688236579108,
+            "stop_at":null,
+            "paused_time":0,
+            "password": "test",
+            "metadata": {
+               "delay_start_stop": 0
+            },
+            "webhooks": [],
+            "osc_targets": [],
+            "private": true
+         }
+        "##;
+
+    let timer: RedisTimer = serde_json::from_str(payload).unwrap();
+    let timer: Timer = timer.into();
+    assert!(timer.private);
+}
+
+#[test]
+fn test_v7() {
+    let payload = r##"
+        {
+            "sequences":[],
+            "id":"v7",
+            "repeat":false,
+            "display_options":null,
+            "start_at":1688236579108,
+            "stop_at":null,
+            "paused_time":0,
+            "password": "test",
+            "metadata": {
+               "delay_start_stop": 0
+            },
+            "webhooks": [],
+            "osc_targets": [],
+            "private": false,
+            "token_generation": 3
+         }
+        "##;
+
+    let timer: RedisTimer = serde_json::from_str(payload).unwrap();
+    let timer: Timer = timer.into();
+    assert_eq!(timer.token_generation, 3);
+}
+
+#[test]
+fn test_v8() {
+    let payload = r##"
+        {
+            "sequences":[],
+            "id":"v8",
+            "repeat":false,
+            "display_options":null,
+            "start_at":1688236579108,
+            "stop_at":null,
+            "paused_time":0,
+            "password": "test",
+            "metadata": {
+               "delay_start_stop": 0
+            },
+            "webhooks": [],
+            "osc_targets": [],
+            "private": false,
+            "token_generation": 0,
+            "owner": "someone"
+         }
+        "##;
+
+    let timer: RedisTimer = serde_json::from_str(payload).unwrap();
+    let timer: Timer = timer.into();
+    assert_eq!(timer.owner.as_deref(), Some("someone"));
+    assert_eq!(timer.revision, 0);
+}
+
+#[test]
+fn test_v9() {
+    let payload = r##"
+        {
+            "sequences":[],
+            "id":"v9",
+            "repeat":false,
+            "display_options":null,
+            "start_at":1688236579108,
+            "stop_at":null,
+            "paused_time":0,
+            "password": "test",
+            "metadata": {
+               "delay_start_stop": 0
+            },
+            "webhooks": [],
+            "osc_targets": [],
+            "private": false,
+            "token_generation": 0,
+            "owner": null,
+            "revision": 12
+         }
+        "##;
+
+    let timer: RedisTimer = serde_json::from_str(payload).unwrap();
+    let timer: Timer = timer.into();
+    assert_eq!(timer.revision, 12);
+    assert!(timer.extension.is_none());
+}
+
+#[test]
+fn test_v10() {
+    let payload = r##"
+        {
+            "sequences":[],
+            "id":"v10",
+            "repeat":false,
+            "display_options":null,
+            "start_at":1688236579108,
+            "stop_at":null,
+            "paused_time":0,
+            "password": "test",
+            "metadata": {
+               "delay_start_stop": 0
+            },
+            "webhooks": [],
+            "osc_targets": [],
+            "private": false,
+            "token_generation": 0,
+            "owner": null,
+            "revision": 12,
+            "extension": {"start": 240000, "end": 255000, "time": 60000}
+         }
+        "##;
+
+    let timer: RedisTimer = serde_json::from_str(payload).unwrap();
+    let timer: Timer = timer.into();
+    assert_eq!(timer.extension.unwrap().time, 60000);
+}
+
+#[test]
+fn test_timer_group_v0() {
+    let payload = r##"{"id": "walls", "password": "test", "timer_ids": ["wall-1"]}"##;
+
+    let group: RedisTimerGroup = serde_json::from_str(payload).unwrap();
+    let group: TimerGroup = group.into();
+    assert_eq!(group.members[0].timer_id, "wall-1");
+    assert_eq!(group.members[0].token_generation, 0);
+    assert_eq!(group.token_generation, 0);
+}
+
+#[test]
+fn test_timer_group_v2() {
+    let payload = r##"{
+        "id": "walls",
+        "password": "test",
+        "members": [{"timer_id": "wall-1", "token_generation": 3}],
+        "token_generation": 1
+    }"##;
+
+    let group: RedisTimerGroup = serde_json::from_str(payload).unwrap();
+    let group: TimerGroup = group.into();
+    assert_eq!(group.members[0].timer_id, "wall-1");
+    assert_eq!(group.members[0].token_generation, 3);
+    assert_eq!(group.token_generation, 1);
+}
+
+#[test]
+fn test_user_v0() {
+    let payload = r##"{"username": "someone", "password": "test", "timer_ids": ["wall-1"], "token_generation": 1}"##;
+
+    let user: RedisUser = serde_json::from_str(payload).unwrap();
+    let user: User = user.into();
+    assert_eq!(user.username, "someone");
+    assert_eq!(user.timer_ids, vec!["wall-1"]);
+}
+
+#[test]
+fn test_template_v0() {
+    let payload = r##"
+        {
+            "id": "quali",
+            "name": "Quali",
+            "sequences": [{"name": "", "segments": [], "repetitions": 1}],
+            "repeat": true,
+            "display_options": {"clock": true, "pre_start_behaviour": "RunNormally"},
+            "metadata": {"delay_start_stop": 0}
+        }
+        "##;
+
+    let template: RedisTemplate = serde_json::from_str(payload).unwrap();
+    let template: Template = template.into();
+    assert_eq!(template.name, "Quali");
+    assert_eq!(
+        template.display_options.pre_start_behaviour,
+        PreStartBehaviour::RunNormally
+    );
+}
+
+#[test]
+fn test_revision_v0() {
+    // revisions keep the timer in the version it was stored with
+    let payload = r##"
+        {
+            "number": 3,
+            "time": 1000,
+            "timer": {
+                "segments": [{"label": "Boulder", "time": 230000, "sound": true, "color": null}],
+                "id": "v0",
+                "repeat": true,
+                "display_options": {"clock": false, "pre_start_behaviour": "ShowZero"},
+                "start_at": 0,
+                "stop_at": null,
+                "password": "test"
+            }
+        }
+        "##;
+
+    let revision: RedisRevision = serde_json::from_str(payload).unwrap();
+    let revision: Revision = revision.into();
+    assert_eq!(revision.number, 3);
+    assert!(revision.repeat);
+    assert_eq!(unroll(&revision.sequences)[0].label, "Boulder");
+}
+
+#[test]
+fn test_revision_v1() {
+    // revisions only keep the playlist and settings
+    let payload = r##"
+        {
+            "number": 7,
+            "time": 2000,
+            "sequences": [{"name": "Qualification", "repetitions": 1, "segments": [{"label": "Boulder", "time": 230000, "color": null, "count_to": 0, "sounds": []}]}],
+            "repeat": false,
+            "display_options": {"clock": true, "pre_start_behaviour": "ShowZero"},
+            "metadata": {"delay_start_stop": 0},
+            "private": true
+        }
+        "##;
+
+    let revision: RedisRevision = serde_json::from_str(payload).unwrap();
+    assert!(matches!(revision, RedisRevision::V1(_)));
+    let revision: Revision = revision.into();
+    assert_eq!(revision.number, 7);
+    assert!(revision.private);
+    assert!(revision.display_options.clock);
+    assert_eq!(unroll(&revision.sequences)[0].label, "Boulder");
+}
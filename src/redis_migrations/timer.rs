@@ -1,23 +1,420 @@
 use serde::Deserialize;
 
-use crate::repository::{Timer, TimerMetadata};
+use crate::repository::{Sequence, Timer, TimerMetadata};
 
 use super::display_options::RedisDisplayOptions;
+use super::osc_target::RedisOscTarget;
 use super::segment::RedisSegment;
+use super::segment_extension::RedisSegmentExtension;
+use super::sequence::RedisSequence;
 use super::timer_metadata::RedisTimerMetadata;
+use super::webhook::RedisWebhook;
+
+/// The version timers are stored and exported with, has to be increased with every new version
+pub const CURRENT_VERSION: u32 = 10;
 
 #[derive(Deserialize, Clone)]
 #[serde(untagged)]
 pub enum RedisTimer {
+    V10(TimerV10),
+    V9(TimerV9),
+    V8(TimerV8),
+    V7(TimerV7),
+    V6(TimerV6),
+    V5(TimerV5),
+    V4(TimerV4),
+    V3(TimerV3),
+    V2(TimerV2),
     V1(TimerV1),
     V0(TimerV0),
 }
 
-impl Into<Timer> for RedisTimer {
-    fn into(self) -> Timer {
-        match self {
+impl From<RedisTimer> for Timer {
+    fn from(value: RedisTimer) -> Self {
+        match value {
             RedisTimer::V0(t) => t.into(),
             RedisTimer::V1(t) => t.into(),
+            RedisTimer::V2(t) => t.into(),
+            RedisTimer::V3(t) => t.into(),
+            RedisTimer::V4(t) => t.into(),
+            RedisTimer::V5(t) => t.into(),
+            RedisTimer::V6(t) => t.into(),
+            RedisTimer::V7(t) => t.into(),
+            RedisTimer::V8(t) => t.into(),
+            RedisTimer::V9(t) => t.into(),
+            RedisTimer::V10(t) => t.into(),
+        }
+    }
+}
+
+/// Up to V4, timers had a flat list of segments, which is a playlist with one sequence
+fn single_sequence(segments: Vec<RedisSegment>) -> Vec<Sequence> {
+    vec![segments
+        .into_iter()
+        .map(|s| s.into())
+        .collect::<Vec<_>>()
+        .into()]
+}
+
+/// === V10 ===
+#[derive(Deserialize, Clone)]
+pub struct TimerV10 {
+    pub sequences: Vec<RedisSequence>,
+    pub repeat: bool,
+    pub display_options: Option<RedisDisplayOptions>,
+    pub start_at: u64,
+    pub stop_at: Option<u64>,
+    pub paused_time: u64,
+    pub password: String,
+    pub id: String,
+    pub metadata: RedisTimerMetadata,
+    pub webhooks: Vec<RedisWebhook>,
+    pub osc_targets: Vec<RedisOscTarget>,
+    pub private: bool,
+    pub token_generation: u64,
+    pub owner: Option<String>,
+    pub revision: u64,
+    pub extension: Option<RedisSegmentExtension>,
+}
+
+impl From<TimerV10> for Timer {
+    fn from(value: TimerV10) -> Self {
+        Timer {
+            sequences: value.sequences.into_iter().map(|s| s.into()).collect(),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            paused_time: value.paused_time,
+            password: value.password,
+            id: value.id,
+            metadata: value.metadata.into(),
+            webhooks: value.webhooks.into_iter().map(|w| w.into()).collect(),
+            osc_targets: value.osc_targets.into_iter().map(|t| t.into()).collect(),
+            private: value.private,
+            token_generation: value.token_generation,
+            owner: value.owner,
+            revision: value.revision,
+            extension: value.extension.map(|e| e.into()),
+        }
+    }
+}
+
+/// === V9 ===
+#[derive(Deserialize, Clone)]
+pub struct TimerV9 {
+    pub sequences: Vec<RedisSequence>,
+    pub repeat: bool,
+    pub display_options: Option<RedisDisplayOptions>,
+    pub start_at: u64,
+    pub stop_at: Option<u64>,
+    pub paused_time: u64,
+    pub password: String,
+    pub id: String,
+    pub metadata: RedisTimerMetadata,
+    pub webhooks: Vec<RedisWebhook>,
+    pub osc_targets: Vec<RedisOscTarget>,
+    pub private: bool,
+    pub token_generation: u64,
+    pub owner: Option<String>,
+    pub revision: u64,
+}
+
+impl From<TimerV9> for Timer {
+    fn from(value: TimerV9) -> Self {
+        Timer {
+            sequences: value.sequences.into_iter().map(|s| s.into()).collect(),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            paused_time: value.paused_time,
+            password: value.password,
+            id: value.id,
+            metadata: value.metadata.into(),
+            webhooks: value.webhooks.into_iter().map(|w| w.into()).collect(),
+            osc_targets: value.osc_targets.into_iter().map(|t| t.into()).collect(),
+            private: value.private,
+            token_generation: value.token_generation,
+            owner: value.owner,
+            revision: value.revision,
+            extension: None,
+        }
+    }
+}
+
+/// === V8 ===
+#[derive(Deserialize, Clone)]
+pub struct TimerV8 {
+    pub sequences: Vec<RedisSequence>,
+    pub repeat: bool,
+    pub display_options: Option<RedisDisplayOptions>,
+    pub start_at: u64,
+    pub stop_at: Option<u64>,
+    pub paused_time: u64,
+    pub password: String,
+    pub id: String,
+    pub metadata: RedisTimerMetadata,
+    pub webhooks: Vec<RedisWebhook>,
+    pub osc_targets: Vec<RedisOscTarget>,
+    pub private: bool,
+    pub token_generation: u64,
+    pub owner: Option<String>,
+}
+
+impl From<TimerV8> for Timer {
+    fn from(value: TimerV8) -> Self {
+        Timer {
+            sequences: value.sequences.into_iter().map(|s| s.into()).collect(),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            paused_time: value.paused_time,
+            password: value.password,
+            id: value.id,
+            metadata: value.metadata.into(),
+            webhooks: value.webhooks.into_iter().map(|w| w.into()).collect(),
+            osc_targets: value.osc_targets.into_iter().map(|t| t.into()).collect(),
+            private: value.private,
+            token_generation: value.token_generation,
+            owner: value.owner,
+            revision: 0,
+            extension: None,
+        }
+    }
+}
+
+/// === V7 ===
+#[derive(Deserialize, Clone)]
+pub struct TimerV7 {
+    pub sequences: Vec<RedisSequence>,
+    pub repeat: bool,
+    pub display_options: Option<RedisDisplayOptions>,
+    pub start_at: u64,
+    pub stop_at: Option<u64>,
+    pub paused_time: u64,
+    pub password: String,
+    pub id: String,
+    pub metadata: RedisTimerMetadata,
+    pub webhooks: Vec<RedisWebhook>,
+    pub osc_targets: Vec<RedisOscTarget>,
+    pub private: bool,
+    pub token_generation: u64,
+}
+
+impl From<TimerV7> for Timer {
+    fn from(value: TimerV7) -> Self {
+        Timer {
+            sequences: value.sequences.into_iter().map(|s| s.into()).collect(),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            paused_time: value.paused_time,
+            password: value.password,
+            id: value.id,
+            metadata: value.metadata.into(),
+            webhooks: value.webhooks.into_iter().map(|w| w.into()).collect(),
+            osc_targets: value.osc_targets.into_iter().map(|t| t.into()).collect(),
+            private: value.private,
+            token_generation: value.token_generation,
+            owner: None,
+            revision: 0,
+            extension: None,
+        }
+    }
+}
+
+/// === V6 ===
+#[derive(Deserialize, Clone)]
+pub struct TimerV6 {
+    pub sequences: Vec<RedisSequence>,
+    pub repeat: bool,
+    pub display_options: Option<RedisDisplayOptions>,
+    pub start_at: u64,
+    pub stop_at: Option<u64>,
+    pub paused_time: u64,
+    pub password: String,
+    pub id: String,
+    pub metadata: RedisTimerMetadata,
+    pub webhooks: Vec<RedisWebhook>,
+    pub osc_targets: Vec<RedisOscTarget>,
+    pub private: bool,
+}
+
+impl From<TimerV6> for Timer {
+    fn from(value: TimerV6) -> Self {
+        Timer {
+            sequences: value.sequences.into_iter().map(|s| s.into()).collect(),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            paused_time: value.paused_time,
+            password: value.password,
+            id: value.id,
+            metadata: value.metadata.into(),
+            webhooks: value.webhooks.into_iter().map(|w| w.into()).collect(),
+            osc_targets: value.osc_targets.into_iter().map(|t| t.into()).collect(),
+            private: value.private,
+            token_generation: 0,
+            owner: None,
+            revision: 0,
+            extension: None,
+        }
+    }
+}
+
+/// === V5 ===
+#[derive(Deserialize, Clone)]
+pub struct TimerV5 {
+    pub sequences: Vec<RedisSequence>,
+    pub repeat: bool,
+    pub display_options: Option<RedisDisplayOptions>,
+    pub start_at: u64,
+    pub stop_at: Option<u64>,
+    pub paused_time: u64,
+    pub password: String,
+    pub id: String,
+    pub metadata: RedisTimerMetadata,
+    pub webhooks: Vec<RedisWebhook>,
+    pub osc_targets: Vec<RedisOscTarget>,
+}
+
+impl From<TimerV5> for Timer {
+    fn from(value: TimerV5) -> Self {
+        Timer {
+            sequences: value.sequences.into_iter().map(|s| s.into()).collect(),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            paused_time: value.paused_time,
+            password: value.password,
+            id: value.id,
+            metadata: value.metadata.into(),
+            webhooks: value.webhooks.into_iter().map(|w| w.into()).collect(),
+            osc_targets: value.osc_targets.into_iter().map(|t| t.into()).collect(),
+            private: false,
+            token_generation: 0,
+            owner: None,
+            revision: 0,
+            extension: None,
+        }
+    }
+}
+
+/// === V4 ===
+#[derive(Deserialize, Clone)]
+pub struct TimerV4 {
+    pub segments: Vec<RedisSegment>,
+    pub repeat: bool,
+    pub display_options: Option<RedisDisplayOptions>,
+    pub start_at: u64,
+    pub stop_at: Option<u64>,
+    pub paused_time: u64,
+    pub password: String,
+    pub id: String,
+    pub metadata: RedisTimerMetadata,
+    pub webhooks: Vec<RedisWebhook>,
+    pub osc_targets: Vec<RedisOscTarget>,
+}
+
+impl From<TimerV4> for Timer {
+    fn from(value: TimerV4) -> Self {
+        Timer {
+            sequences: single_sequence(value.segments),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            paused_time: value.paused_time,
+            password: value.password,
+            id: value.id,
+            metadata: value.metadata.into(),
+            webhooks: value.webhooks.into_iter().map(|w| w.into()).collect(),
+            osc_targets: value.osc_targets.into_iter().map(|t| t.into()).collect(),
+            private: false,
+            token_generation: 0,
+            owner: None,
+            revision: 0,
+            extension: None,
+        }
+    }
+}
+
+/// === V3 ===
+#[derive(Deserialize, Clone)]
+pub struct TimerV3 {
+    pub segments: Vec<RedisSegment>,
+    pub repeat: bool,
+    pub display_options: Option<RedisDisplayOptions>,
+    pub start_at: u64,
+    pub stop_at: Option<u64>,
+    pub paused_time: u64,
+    pub password: String,
+    pub id: String,
+    pub metadata: RedisTimerMetadata,
+    pub webhooks: Vec<RedisWebhook>,
+}
+
+impl From<TimerV3> for Timer {
+    fn from(value: TimerV3) -> Self {
+        Timer {
+            sequences: single_sequence(value.segments),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            paused_time: value.paused_time,
+            password: value.password,
+            id: value.id,
+            metadata: value.metadata.into(),
+            webhooks: value.webhooks.into_iter().map(|w| w.into()).collect(),
+            osc_targets: Vec::new(),
+            private: false,
+            token_generation: 0,
+            owner: None,
+            revision: 0,
+            extension: None,
+        }
+    }
+}
+
+/// === V2 ===
+#[derive(Deserialize, Clone)]
+pub struct TimerV2 {
+    pub segments: Vec<RedisSegment>,
+    pub repeat: bool,
+    pub display_options: Option<RedisDisplayOptions>,
+    pub start_at: u64,
+    pub stop_at: Option<u64>,
+    pub paused_time: u64,
+    pub password: String,
+    pub id: String,
+    pub metadata: RedisTimerMetadata,
+}
+
+impl From<TimerV2> for Timer {
+    fn from(value: TimerV2) -> Self {
+        Timer {
+            sequences: single_sequence(value.segments),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            paused_time: value.paused_time,
+            password: value.password,
+            id: value.id,
+            metadata: value.metadata.into(),
+            webhooks: Vec::new(),
+            osc_targets: Vec::new(),
+            private: false,
+            token_generation: 0,
+            owner: None,
+            revision: 0,
+            extension: None,
         }
     }
 }
@@ -35,17 +432,25 @@ pub struct TimerV1 {
     pub metadata: RedisTimerMetadata,
 }
 
-impl Into<Timer> for TimerV1 {
-    fn into(self) -> Timer {
+impl From<TimerV1> for Timer {
+    fn from(value: TimerV1) -> Self {
         Timer {
-            segments: self.segments.into_iter().map(|s| s.into()).collect(),
-            repeat: self.repeat,
-            display_options: self.display_options.into(),
-            start_at: self.start_at,
-            stop_at: self.stop_at,
-            password: self.password,
-            id: self.id,
-            metadata: self.metadata.into(),
+            sequences: single_sequence(value.segments),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            paused_time: 0,
+            password: value.password,
+            id: value.id,
+            metadata: value.metadata.into(),
+            webhooks: Vec::new(),
+            osc_targets: Vec::new(),
+            private: false,
+            token_generation: 0,
+            owner: None,
+            revision: 0,
+            extension: None,
         }
     }
 }
@@ -62,17 +467,25 @@ pub struct TimerV0 {
     pub id: String,
 }
 
-impl Into<Timer> for TimerV0 {
-    fn into(self) -> Timer {
+impl From<TimerV0> for Timer {
+    fn from(value: TimerV0) -> Self {
         Timer {
-            segments: self.segments.into_iter().map(|s| s.into()).collect(),
-            repeat: self.repeat,
-            display_options: self.display_options.into(),
-            start_at: self.start_at,
-            stop_at: self.stop_at,
-            password: self.password,
-            id: self.id,
+            sequences: single_sequence(value.segments),
+            repeat: value.repeat,
+            display_options: value.display_options.into(),
+            start_at: value.start_at,
+            stop_at: value.stop_at,
+            paused_time: 0,
+            password: value.password,
+            id: value.id,
             metadata: TimerMetadata::default(),
+            webhooks: Vec::new(),
+            osc_targets: Vec::new(),
+            private: false,
+            token_generation: 0,
+            owner: None,
+            revision: 0,
+            extension: None,
         }
     }
 }
@@ -0,0 +1,94 @@
+use serde::Deserialize;
+
+use crate::repository::{GroupMember, TimerGroup};
+
+use super::group_member::RedisGroupMember;
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RedisTimerGroup {
+    V2(TimerGroupV2),
+    V1(TimerGroupV1),
+    V0(TimerGroupV0),
+}
+
+impl From<RedisTimerGroup> for TimerGroup {
+    fn from(value: RedisTimerGroup) -> Self {
+        match value {
+            RedisTimerGroup::V0(v0) => v0.into(),
+            RedisTimerGroup::V1(v1) => v1.into(),
+            RedisTimerGroup::V2(v2) => v2.into(),
+        }
+    }
+}
+
+/// The members of groups from before were added with the first token generation
+/// of their timers, so they are dropped if the timers revoked their tokens since
+fn members(timer_ids: Vec<String>) -> Vec<GroupMember> {
+    timer_ids
+        .into_iter()
+        .map(|timer_id| GroupMember {
+            timer_id,
+            token_generation: 0,
+        })
+        .collect()
+}
+
+/// === V2 ===
+#[derive(Deserialize, Clone)]
+pub struct TimerGroupV2 {
+    pub id: String,
+    pub password: String,
+    pub members: Vec<RedisGroupMember>,
+    pub token_generation: u64,
+}
+
+impl From<TimerGroupV2> for TimerGroup {
+    fn from(value: TimerGroupV2) -> Self {
+        TimerGroup {
+            id: value.id,
+            password: value.password,
+            members: value.members.into_iter().map(|m| m.into()).collect(),
+            token_generation: value.token_generation,
+        }
+    }
+}
+
+/// === V1 ===
+#[derive(Deserialize, Clone)]
+pub struct TimerGroupV1 {
+    pub id: String,
+    pub password: String,
+    pub timer_ids: Vec<String>,
+    pub token_generation: u64,
+}
+
+impl From<TimerGroupV1> for TimerGroup {
+    fn from(value: TimerGroupV1) -> Self {
+        TimerGroup {
+            id: value.id,
+            password: value.password,
+            members: members(value.timer_ids),
+            token_generation: value.token_generation,
+        }
+    }
+}
+
+/// === V0 ===
+#[derive(Deserialize, Clone)]
+pub struct TimerGroupV0 {
+    pub id: String,
+    pub password: String,
+    pub timer_ids: Vec<String>,
+}
+
+impl From<TimerGroupV0> for TimerGroup {
+    fn from(value: TimerGroupV0) -> Self {
+        TimerGroup {
+            id: value.id,
+            password: value.password,
+            members: members(value.timer_ids),
+            token_generation: 0,
+        }
+    }
+}
@@ -8,9 +8,9 @@ pub enum RedisTimerMetadata {
     V0(TimerMetadataV0),
 }
 
-impl Into<TimerMetadata> for RedisTimerMetadata {
-    fn into(self) -> TimerMetadata {
-        match self {
+impl From<RedisTimerMetadata> for TimerMetadata {
+    fn from(value: RedisTimerMetadata) -> Self {
+        match value {
             RedisTimerMetadata::V0(v0) => v0.into(),
         }
     }
@@ -21,10 +21,10 @@ pub struct TimerMetadataV0 {
     pub delay_start_stop: u32,
 }
 
-impl Into<TimerMetadata> for TimerMetadataV0 {
-    fn into(self) -> TimerMetadata {
+impl From<TimerMetadataV0> for TimerMetadata {
+    fn from(value: TimerMetadataV0) -> Self {
         TimerMetadata {
-            delay_start_stop: self.delay_start_stop,
+            delay_start_stop: value.delay_start_stop,
         }
     }
 }
@@ -0,0 +1,37 @@
+use serde::Deserialize;
+
+use crate::repository::User;
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RedisUser {
+    V0(UserV0),
+}
+
+impl From<RedisUser> for User {
+    fn from(value: RedisUser) -> Self {
+        match value {
+            RedisUser::V0(v0) => v0.into(),
+        }
+    }
+}
+
+/// === V0 ===
+#[derive(Deserialize, Clone)]
+pub struct UserV0 {
+    pub username: String,
+    pub password: String,
+    pub timer_ids: Vec<String>,
+    pub token_generation: u64,
+}
+
+impl From<UserV0> for User {
+    fn from(value: UserV0) -> Self {
+        User {
+            username: value.username,
+            password: value.password,
+            timer_ids: value.timer_ids,
+            token_generation: value.token_generation,
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use serde::Deserialize;
+
+use crate::repository::Webhook;
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RedisWebhook {
+    V0(WebhookV0),
+}
+
+impl From<RedisWebhook> for Webhook {
+    fn from(value: RedisWebhook) -> Self {
+        match value {
+            RedisWebhook::V0(v0) => v0.into(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct WebhookV0 {
+    pub url: String,
+    pub secret: String,
+}
+
+impl From<WebhookV0> for Webhook {
+    fn from(value: WebhookV0) -> Self {
+        Webhook {
+            url: value.url,
+            secret: value.secret,
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{
+    broadcast::{self, Receiver},
+    Mutex, RwLock,
+};
+
+use crate::redis_migrations::RedisTimer;
+use crate::timer_state::current_time;
+
+use super::{StorageBackend, Timer, TimerUpdate, UPDATES_CAPACITY};
+
+#[derive(Serialize, Default)]
+struct Store {
+    timers: HashMap<String, Timer>,
+    /// the documents of each collection by their id
+    documents: HashMap<String, HashMap<String, String>>,
+    /// the entries of each log by the id they belong to
+    logs: HashMap<String, HashMap<String, Vec<String>>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Snapshot {
+    V2 {
+        timers: HashMap<String, RedisTimer>,
+        documents: HashMap<String, HashMap<String, String>>,
+        logs: HashMap<String, HashMap<String, Vec<String>>>,
+    },
+    V1 {
+        timers: HashMap<String, RedisTimer>,
+        documents: HashMap<String, HashMap<String, String>>,
+    },
+    /// only contains the timers
+    V0(HashMap<String, RedisTimer>),
+}
+
+/// Keeps all timers in memory.
+///
+/// If a snapshot file is given, the timers are loaded from it on startup and
+/// the whole store is written back to it after every change.
+/// Expiring records are not part of the snapshot, so they don't cause a write.
+pub struct MemoryStorage {
+    store: RwLock<Store>,
+    /// the data and expiry time of the records of each collection by their id
+    expiring: Mutex<HashMap<(String, String), (String, u64)>>,
+    snapshot_file: Option<PathBuf>,
+    updates_tx: broadcast::Sender<TimerUpdate>,
+}
+
+impl MemoryStorage {
+    pub async fn new(snapshot_file: Option<PathBuf>) -> Self {
+        let store = match &snapshot_file {
+            Some(path) => load_snapshot(path).await,
+            None => Store::default(),
+        };
+
+        let (updates_tx, _) = broadcast::channel::<TimerUpdate>(UPDATES_CAPACITY);
+
+        MemoryStorage {
+            store: RwLock::new(store),
+            expiring: Mutex::new(HashMap::new()),
+            snapshot_file,
+            updates_tx,
+        }
+    }
+
+    /// The change is kept in memory if the snapshot can't be written, e.g. because the
+    /// disk is full, and written with the next snapshot that succeeds
+    async fn write_snapshot(&self, store: &Store) {
+        let path = match &self.snapshot_file {
+            Some(path) => path,
+            None => return,
+        };
+
+        if let Err(e) = write_snapshot_file(path, store).await {
+            println!("Could not write snapshot file {}: {}", path.display(), e);
+        }
+    }
+}
+
+async fn write_snapshot_file(path: &Path, store: &Store) -> std::io::Result<()> {
+    // write to a temporary file first, so a crash can't leave a half written snapshot
+    let tmp_path = path.with_extension("tmp");
+    tokio::fs::write(&tmp_path, serde_json::to_string(store)?).await?;
+    tokio::fs::rename(&tmp_path, path).await
+}
+
+async fn load_snapshot(path: &PathBuf) -> Store {
+    let snapshot = match tokio::fs::read_to_string(path).await {
+        Ok(snapshot) => snapshot,
+        Err(_) => return Store::default(),
+    };
+
+    let snapshot: Snapshot =
+        serde_json::from_str(&snapshot).expect("Could not parse snapshot file");
+    let (timers, documents, logs) = match snapshot {
+        Snapshot::V2 {
+            timers,
+            documents,
+            logs,
+        } => (timers, documents, logs),
+        Snapshot::V1 { timers, documents } => (timers, documents, HashMap::new()),
+        Snapshot::V0(timers) => (timers, HashMap::new(), HashMap::new()),
+    };
+
+    Store {
+        timers: timers
+            .into_iter()
+            .map(|(id, timer)| (id, timer.into()))
+            .collect(),
+        documents,
+        logs,
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    async fn get_timer(&self, id: String) -> Option<Timer> {
+        self.store.read().await.timers.get(&id).cloned()
+    }
+
+    async fn get_timers(&self) -> Vec<Timer> {
+        self.store.read().await.timers.values().cloned().collect()
+    }
+
+    async fn create_timer(&self, timer: &Timer) -> Result<(), ()> {
+        let mut store = self.store.write().await;
+        if store.timers.contains_key(&timer.id) {
+            return Err(());
+        }
+
+        store.timers.insert(timer.id.clone(), timer.clone());
+        self.write_snapshot(&store).await;
+        let _ = self
+            .updates_tx
+            .send(TimerUpdate::Changed(Box::new(timer.clone())));
+
+        Ok(())
+    }
+
+    async fn update_timer(&self, timer: &Timer) -> Result<(), ()> {
+        self.update_timers(std::slice::from_ref(timer)).await
+    }
+
+    async fn update_timers(&self, timers: &[Timer]) -> Result<(), ()> {
+        let mut store = self.store.write().await;
+        let up_to_date = timers.iter().all(|timer| {
+            matches!(store.timers.get(&timer.id), Some(stored) if stored.revision + 1 == timer.revision)
+        });
+        if !up_to_date {
+            return Err(());
+        }
+
+        for timer in timers {
+            store.timers.insert(timer.id.clone(), timer.clone());
+        }
+        self.write_snapshot(&store).await;
+
+        for timer in timers {
+            let _ = self
+                .updates_tx
+                .send(TimerUpdate::Changed(Box::new(timer.clone())));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_timer(&self, id: String) -> Result<(), ()> {
+        let mut store = self.store.write().await;
+        store.timers.remove(&id).ok_or(())?;
+        self.write_snapshot(&store).await;
+        let _ = self.updates_tx.send(TimerUpdate::Deleted(id));
+
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Receiver<TimerUpdate> {
+        self.updates_tx.subscribe()
+    }
+
+    async fn get_document(&self, collection: &str, id: &str) -> Option<String> {
+        self.store
+            .read()
+            .await
+            .documents
+            .get(collection)?
+            .get(id)
+            .cloned()
+    }
+
+    async fn create_document(&self, collection: &str, id: &str, data: String) -> Result<(), ()> {
+        let mut store = self.store.write().await;
+        let documents = store.documents.entry(collection.to_owned()).or_default();
+        if documents.contains_key(id) {
+            return Err(());
+        }
+
+        documents.insert(id.to_owned(), data);
+        self.write_snapshot(&store).await;
+
+        Ok(())
+    }
+
+    async fn update_document(&self, collection: &str, id: &str, data: String) {
+        let mut store = self.store.write().await;
+        store
+            .documents
+            .entry(collection.to_owned())
+            .or_default()
+            .insert(id.to_owned(), data);
+        self.write_snapshot(&store).await;
+    }
+
+    async fn swap_document(
+        &self,
+        collection: &str,
+        id: &str,
+        expected: &str,
+        data: String,
+    ) -> bool {
+        let mut store = self.store.write().await;
+        let document = store
+            .documents
+            .get_mut(collection)
+            .and_then(|documents| documents.get_mut(id));
+        match document {
+            Some(document) if document == expected => *document = data,
+            _ => return false,
+        }
+        self.write_snapshot(&store).await;
+
+        true
+    }
+
+    async fn delete_document(&self, collection: &str, id: &str) -> Result<(), ()> {
+        let mut store = self.store.write().await;
+        store
+            .documents
+            .get_mut(collection)
+            .and_then(|documents| documents.remove(id))
+            .ok_or(())?;
+        self.write_snapshot(&store).await;
+
+        Ok(())
+    }
+
+    async fn append_log(&self, log: &str, id: &str, entry: String) {
+        let mut store = self.store.write().await;
+        store
+            .logs
+            .entry(log.to_owned())
+            .or_default()
+            .entry(id.to_owned())
+            .or_default()
+            .push(entry);
+        self.write_snapshot(&store).await;
+    }
+
+    async fn get_log(&self, log: &str, id: &str) -> Vec<String> {
+        self.store
+            .read()
+            .await
+            .logs
+            .get(log)
+            .and_then(|logs| logs.get(id))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    async fn trim_log(&self, log: &str, id: &str, keep: usize) {
+        let mut store = self.store.write().await;
+        let entries = match store.logs.get_mut(log).and_then(|logs| logs.get_mut(id)) {
+            Some(entries) if entries.len() > keep => entries,
+            _ => return,
+        };
+
+        let excess = entries.len() - keep;
+        entries.drain(..excess);
+        if entries.is_empty() {
+            store.logs.get_mut(log).unwrap().remove(id);
+        }
+        self.write_snapshot(&store).await;
+    }
+
+    async fn get_expiring(&self, collection: &str, id: &str) -> Option<String> {
+        let key = (collection.to_owned(), id.to_owned());
+        match self.expiring.lock().await.get(&key) {
+            Some((data, expires_at)) if *expires_at > current_time() => Some(data.clone()),
+            _ => None,
+        }
+    }
+
+    async fn swap_expiring(
+        &self,
+        collection: &str,
+        id: &str,
+        expected: Option<&str>,
+        data: &str,
+        ttl: u64,
+    ) -> bool {
+        let now = current_time();
+        let mut expiring = self.expiring.lock().await;
+        expiring.retain(|_, (_, expires_at)| *expires_at > now);
+
+        let key = (collection.to_owned(), id.to_owned());
+        if expiring.get(&key).map(|(data, _)| data.as_str()) != expected {
+            return false;
+        }
+
+        expiring.insert(key, (data.to_owned(), now + ttl));
+        true
+    }
+}
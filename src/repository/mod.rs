@@ -0,0 +1,575 @@
+use std::sync::Arc;
+
+use crate::audit::HistoryEntry;
+use crate::color::Color;
+use crate::redis_migrations::{RedisRevision, RedisTemplate, RedisTimerGroup, RedisUser};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use tokio::sync::broadcast::Receiver;
+
+mod memory_storage;
+mod redis_storage;
+mod sqlite_storage;
+#[cfg(test)]
+mod tests;
+
+pub use memory_storage::MemoryStorage;
+pub use redis_storage::RedisStorage;
+pub use sqlite_storage::SqliteStorage;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub label: String,
+    pub time: u32,
+    pub color: Option<Color>,
+    pub count_to: u32,
+    pub sounds: Vec<Sound>,
+}
+
+/// A named list of segments within the playlist of a timer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Sequence {
+    pub name: String,
+    pub segments: Vec<Segment>,
+    /// how often the segments run one after another, a sequence with 0 is skipped
+    pub repetitions: u32,
+}
+
+/// Wraps a flat list of segments as a sequence which runs once
+impl From<Vec<Segment>> for Sequence {
+    fn from(segments: Vec<Segment>) -> Self {
+        Sequence {
+            name: String::new(),
+            segments,
+            repetitions: 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Sound {
+    pub filename: String,
+    pub trigger_time: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub enum PreStartBehaviour {
+    #[default]
+    ShowFirstSegment,
+    ShowLastSegment,
+    RunNormally,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct DisplayOptions {
+    pub clock: bool,
+    pub pre_start_behaviour: PreStartBehaviour,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct TimerMetadata {
+    pub delay_start_stop: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Webhook {
+    pub url: String,
+    /// used to sign the payload, so the receiver can verify it
+    pub secret: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OscTarget {
+    pub host: String,
+    pub port: u16,
+}
+
+/// Time added to the segment which was running when the timer was adjusted.
+/// All times are in ms after `start_at`, so they don't change when the timer is paused.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SegmentExtension {
+    /// the start of the extended segment
+    pub start: u64,
+    /// the end of the extended segment without the extension
+    pub end: u64,
+    /// the time added to the segment, the segments after it start that much later
+    pub time: u64,
+}
+
+/// The segments of the sequences in the order they run, with the repetitions unrolled
+pub fn unroll(sequences: &[Sequence]) -> Vec<Segment> {
+    playlist_segments(sequences).cloned().collect()
+}
+
+/// Iterates over the segments like [`unroll`] without copying them
+pub fn playlist_segments(sequences: &[Sequence]) -> impl Iterator<Item = &Segment> {
+    sequences
+        .iter()
+        .flat_map(|sequence| (0..sequence.repetitions).flat_map(move |_| sequence.segments.iter()))
+}
+
+/// The number of segments of the sequences with the repetitions unrolled
+pub fn segment_count(sequences: &[Sequence]) -> u64 {
+    sequences
+        .iter()
+        .map(|sequence| sequence.segments.len() as u64 * sequence.repetitions as u64)
+        .sum()
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct Timer {
+    /// the sequences run back to back
+    pub sequences: Vec<Sequence>,
+    /// repeat the whole playlist
+    pub repeat: bool,
+    pub display_options: DisplayOptions,
+    pub start_at: u64,
+    pub stop_at: Option<u64>,
+    /// total time the timer was paused, `start_at` is already shifted by it
+    pub paused_time: u64,
+    pub password: String,
+    pub id: String,
+    pub metadata: TimerMetadata,
+    pub webhooks: Vec<Webhook>,
+    pub osc_targets: Vec<OscTarget>,
+    /// private timers can only be seen with a token
+    pub private: bool,
+    /// tokens issued for an older generation are revoked
+    pub token_generation: u64,
+    /// the username of the user account the timer belongs to
+    pub owner: Option<String>,
+    /// increases with every change of the timer, so concurrent changes can be detected
+    pub revision: u64,
+    /// time added to the current segment
+    pub extension: Option<SegmentExtension>,
+}
+
+impl Timer {
+    /// All segments of the playlist in the order they run, with the repetitions
+    /// of the sequences unrolled
+    pub fn segments(&self) -> Vec<Segment> {
+        unroll(&self.sequences)
+    }
+
+    /// The index of the sequence the segment at `segment_index` of [`Timer::segments`]
+    /// belongs to
+    pub fn sequence_index(&self, segment_index: usize) -> Option<usize> {
+        self.locate(segment_index).map(|(index, _)| index)
+    }
+
+    /// The segment at `segment_index` of [`Timer::segments`], without unrolling the playlist
+    pub fn segment(&self, segment_index: usize) -> Option<&Segment> {
+        self.locate(segment_index)
+            .map(|(index, offset)| &self.sequences[index].segments[offset])
+    }
+
+    /// The sequence and the index in its segments of the segment at `segment_index`
+    fn locate(&self, segment_index: usize) -> Option<(usize, usize)> {
+        let mut segment_start = 0;
+        for (index, sequence) in self.sequences.iter().enumerate() {
+            let count = sequence.segments.len() * sequence.repetitions as usize;
+            if segment_index < segment_start + count {
+                return Some((
+                    index,
+                    (segment_index - segment_start) % sequence.segments.len(),
+                ));
+            }
+            segment_start += count;
+        }
+
+        None
+    }
+}
+
+/// Several timers which are controlled as one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TimerGroup {
+    pub id: String,
+    pub password: String,
+    pub members: Vec<GroupMember>,
+    /// tokens issued for an older generation are revoked
+    pub token_generation: u64,
+}
+
+/// A timer of a group, added with the password of the timer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GroupMember {
+    pub timer_id: String,
+    /// the token generation of the timer when it was added. Once the timer revokes its
+    /// tokens, e.g. by changing its password, the group can't control it anymore
+    pub token_generation: u64,
+}
+
+impl GroupMember {
+    /// Whether the group may still control the timer
+    pub fn is_current(&self, timer: &Timer) -> bool {
+        timer.id == self.timer_id && timer.token_generation == self.token_generation
+    }
+}
+
+/// An account which owns several timers, so they can be used with a single login
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct User {
+    pub username: String,
+    pub password: String,
+    /// the timers which were owned by the user at some point,
+    /// only those with the user as [`Timer::owner`] still are
+    pub timer_ids: Vec<String>,
+    /// tokens issued for an older generation are revoked
+    pub token_generation: u64,
+}
+
+/// The playlist and settings a timer can be created from
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Template {
+    pub id: String,
+    pub name: String,
+    pub sequences: Vec<Sequence>,
+    pub repeat: bool,
+    pub display_options: DisplayOptions,
+    pub metadata: TimerMetadata,
+}
+
+/// The playlist and settings of a previous version of a timer, kept so they can be restored
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Revision {
+    /// the revision of the timer which was replaced, increases with every revision
+    pub number: u64,
+    /// unix time in ms when the timer was replaced by a newer version
+    pub time: u64,
+    pub sequences: Vec<Sequence>,
+    pub repeat: bool,
+    pub display_options: DisplayOptions,
+    pub metadata: TimerMetadata,
+    pub private: bool,
+}
+
+/// Failed password attempts for a timer, group or client
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LoginAttempts {
+    pub failures: u32,
+    /// unix time in ms
+    pub last_failure: u64,
+}
+
+/// A change of a stored timer
+#[derive(Clone, Debug)]
+pub enum TimerUpdate {
+    /// the timer was created or updated
+    Changed(Box<Timer>),
+    /// the id of the deleted timer
+    Deleted(String),
+}
+
+/// A place timers can be stored in.
+///
+/// Every backend has to broadcast each created, updated or deleted timer to the receivers
+/// handed out by [`StorageBackend::subscribe`], so websocket clients get live updates
+/// no matter which backend is in use.
+///
+/// Everything else is stored as json documents, identified by a collection and an id,
+/// or as entries of append-only logs. Documents which are read and written back, like groups,
+/// are replaced with compare-and-swap. Short-lived records like failed login attempts
+/// are stored with a time to live and only changed with compare-and-swap, so concurrent
+/// requests can't overwrite each other.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    async fn get_timer(&self, id: String) -> Option<Timer>;
+    /// All stored timers, used to pick up the running timers when the server starts
+    async fn get_timers(&self) -> Vec<Timer>;
+    /// Fails if a timer with the same id already exists
+    async fn create_timer(&self, timer: &Timer) -> Result<(), ()>;
+    /// Replaces the stored timer, whose revision has to be the one before the revision of `timer`.
+    /// Fails if the timer was changed or deleted in the meantime
+    async fn update_timer(&self, timer: &Timer) -> Result<(), ()>;
+    /// Updates all timers at once, so no client can see only some of them changed.
+    /// Fails without changing any of them if one fails like in [`StorageBackend::update_timer`]
+    async fn update_timers(&self, timers: &[Timer]) -> Result<(), ()>;
+    async fn delete_timer(&self, id: String) -> Result<(), ()>;
+    fn subscribe(&self) -> Receiver<TimerUpdate>;
+
+    async fn get_document(&self, collection: &str, id: &str) -> Option<String>;
+    /// Fails if a document with the same id already exists in the collection
+    async fn create_document(&self, collection: &str, id: &str, data: String) -> Result<(), ()>;
+    async fn update_document(&self, collection: &str, id: &str, data: String);
+    /// Replaces the document with `data` if it is still `expected`.
+    /// Returns `false` without changing anything if it was changed or deleted
+    async fn swap_document(&self, collection: &str, id: &str, expected: &str, data: String)
+        -> bool;
+    async fn delete_document(&self, collection: &str, id: &str) -> Result<(), ()>;
+
+    /// Appends an entry to a log, entries can't be changed or removed afterwards
+    async fn append_log(&self, log: &str, id: &str, entry: String);
+    /// All entries of a log, the oldest first
+    async fn get_log(&self, log: &str, id: &str) -> Vec<String>;
+    /// Removes all but the newest `keep` entries of a log
+    async fn trim_log(&self, log: &str, id: &str, keep: usize);
+
+    /// A record which isn't returned anymore once its time to live is over
+    async fn get_expiring(&self, collection: &str, id: &str) -> Option<String>;
+    /// Replaces the record with `data`, which expires after `ttl` ms, if it is still
+    /// `expected`, `None` if there was none. Returns `false` without changing anything otherwise
+    async fn swap_expiring(
+        &self,
+        collection: &str,
+        id: &str,
+        expected: Option<&str>,
+        data: &str,
+        ttl: u64,
+    ) -> bool;
+}
+
+const GROUPS: &str = "group";
+const LOGIN_ATTEMPTS: &str = "login_attempts";
+const USERS: &str = "user";
+const HISTORY: &str = "history";
+/// the templates of each user are stored in one document
+const TEMPLATES: &str = "templates";
+/// the revisions of each timer are a log, the revisions of older versions
+/// are stored in one document instead
+const REVISIONS: &str = "revisions";
+
+/// How many revisions are kept for each timer, older ones are dropped
+pub const MAX_REVISIONS: usize = 50;
+
+/// How many timer updates a receiver may fall behind before it misses the oldest ones
+pub const UPDATES_CAPACITY: usize = 1024;
+
+#[derive(Clone)]
+pub struct Repository {
+    storage: Arc<dyn StorageBackend>,
+    pub updates_rx: Arc<Receiver<TimerUpdate>>,
+}
+
+impl Repository {
+    pub fn new(storage: impl StorageBackend + 'static) -> Self {
+        let updates_rx = storage.subscribe();
+
+        Repository {
+            storage: Arc::new(storage),
+            updates_rx: Arc::new(updates_rx),
+        }
+    }
+
+    pub async fn get_timer(&self, id: String) -> Option<Timer> {
+        self.storage.get_timer(id).await
+    }
+
+    pub async fn get_timers(&self) -> Vec<Timer> {
+        self.storage.get_timers().await
+    }
+
+    pub async fn create_timer(&self, timer: &Timer) -> Result<(), ()> {
+        self.storage.create_timer(timer).await
+    }
+
+    /// Stores the timer with the next revision.
+    /// Fails if the timer was changed or deleted since it was read
+    pub async fn update_timer(&self, timer: &mut Timer) -> Result<(), ()> {
+        timer.revision += 1;
+        self.storage.update_timer(timer).await
+    }
+
+    /// Stores all timers with their next revision, or none of them if one was changed
+    /// or deleted since it was read
+    pub async fn update_timers(&self, timers: &mut [Timer]) -> Result<(), ()> {
+        for timer in timers.iter_mut() {
+            timer.revision += 1;
+        }
+        self.storage.update_timers(timers).await
+    }
+
+    /// The revisions of the timer are deleted as well
+    pub async fn delete_timer(&self, id: String) -> Result<(), ()> {
+        self.storage.delete_timer(id.clone()).await?;
+        let _ = self.storage.delete_document(REVISIONS, &id).await;
+        self.storage.trim_log(REVISIONS, &id, 0).await;
+        Ok(())
+    }
+
+    pub async fn get_group(&self, id: String) -> Option<TimerGroup> {
+        let group = self.storage.get_document(GROUPS, &id).await?;
+        let group: RedisTimerGroup = serde_json::from_str(&group).unwrap();
+        Some(group.into())
+    }
+
+    /// Fails if a group with the same id already exists
+    pub async fn create_group(&self, group: &TimerGroup) -> Result<(), ()> {
+        self.storage
+            .create_document(GROUPS, &group.id, serde_json::to_string(group).unwrap())
+            .await
+    }
+
+    /// Replaces the group, which has to be the same as `old` still.
+    /// Fails if the group was changed or deleted since it was read
+    pub async fn update_group(&self, old: &TimerGroup, group: &TimerGroup) -> Result<(), ()> {
+        let stored = self
+            .storage
+            .get_document(GROUPS, &group.id)
+            .await
+            .ok_or(())?;
+        let current: RedisTimerGroup = serde_json::from_str(&stored).unwrap();
+        if TimerGroup::from(current) != *old {
+            return Err(());
+        }
+
+        let data = serde_json::to_string(group).unwrap();
+        match self
+            .storage
+            .swap_document(GROUPS, &group.id, &stored, data)
+            .await
+        {
+            true => Ok(()),
+            false => Err(()),
+        }
+    }
+
+    pub async fn delete_group(&self, id: String) -> Result<(), ()> {
+        self.storage.delete_document(GROUPS, &id).await
+    }
+
+    pub async fn get_user(&self, username: String) -> Option<User> {
+        let user = self.storage.get_document(USERS, &username).await?;
+        let user: RedisUser = serde_json::from_str(&user).unwrap();
+        Some(user.into())
+    }
+
+    /// Fails if a user with the same name already exists
+    pub async fn create_user(&self, user: &User) -> Result<(), ()> {
+        self.storage
+            .create_document(USERS, &user.username, serde_json::to_string(user).unwrap())
+            .await
+    }
+
+    /// Changes the user with `change`, which is tried again with the stored user if it was
+    /// changed in the meantime. Returns the changed user, `None` if there is no such user
+    pub async fn update_user(&self, username: &str, change: impl Fn(&mut User)) -> Option<User> {
+        loop {
+            let stored = self.storage.get_document(USERS, username).await?;
+            let user: RedisUser = serde_json::from_str(&stored).unwrap();
+            let mut user: User = user.into();
+            change(&mut user);
+
+            let data = serde_json::to_string(&user).unwrap();
+            if self
+                .storage
+                .swap_document(USERS, username, &stored, data)
+                .await
+            {
+                return Some(user);
+            }
+        }
+    }
+
+    pub async fn get_templates(&self, username: &str) -> Vec<Template> {
+        let templates = match self.storage.get_document(TEMPLATES, username).await {
+            Some(templates) => templates,
+            None => return Vec::new(),
+        };
+
+        let templates: Vec<RedisTemplate> = serde_json::from_str(&templates).unwrap();
+        templates.into_iter().map(|t| t.into()).collect()
+    }
+
+    pub async fn update_templates(&self, username: &str, templates: &[Template]) {
+        self.storage
+            .update_document(
+                TEMPLATES,
+                username,
+                serde_json::to_string(templates).unwrap(),
+            )
+            .await
+    }
+
+    /// The kept revisions of the timer, the oldest first
+    pub async fn get_revisions(&self, timer_id: &str) -> Vec<Revision> {
+        let old_revisions = match self.storage.get_document(REVISIONS, timer_id).await {
+            Some(revisions) => serde_json::from_str(&revisions).unwrap(),
+            None => Vec::new(),
+        };
+        let revisions = self.storage.get_log(REVISIONS, timer_id).await;
+
+        // the numbers of older versions are lower than the revisions they were replaced at
+        let revisions: Vec<Revision> = old_revisions
+            .into_iter()
+            .chain(revisions.iter().map(|r| serde_json::from_str(r).unwrap()))
+            .map(|r: RedisRevision| r.into())
+            .collect();
+        let excess = revisions.len().saturating_sub(MAX_REVISIONS);
+        revisions.into_iter().skip(excess).collect()
+    }
+
+    /// Keeps the playlist and settings of `timer` as a revision before it is replaced
+    /// at `time`, only the newest [`MAX_REVISIONS`] are kept
+    pub async fn add_revision(&self, timer: &Timer, time: u64) {
+        let revision = Revision {
+            number: timer.revision,
+            time,
+            sequences: timer.sequences.clone(),
+            repeat: timer.repeat,
+            display_options: timer.display_options.clone(),
+            metadata: timer.metadata.clone(),
+            private: timer.private,
+        };
+
+        self.storage
+            .append_log(
+                REVISIONS,
+                &timer.id,
+                serde_json::to_string(&revision).unwrap(),
+            )
+            .await;
+        self.storage
+            .trim_log(REVISIONS, &timer.id, MAX_REVISIONS)
+            .await;
+    }
+
+    pub async fn append_history(&self, timer_id: &str, entry: &HistoryEntry) {
+        self.storage
+            .append_log(HISTORY, timer_id, serde_json::to_string(entry).unwrap())
+            .await
+    }
+
+    /// All entries for the id, including those of deleted timers with the same id
+    pub async fn get_history(&self, timer_id: &str) -> Vec<HistoryEntry> {
+        self.storage
+            .get_log(HISTORY, timer_id)
+            .await
+            .iter()
+            .map(|entry| serde_json::from_str(entry).unwrap())
+            .collect()
+    }
+
+    /// Changes the attempts for `key` with `change`, which is tried again with the new attempts
+    /// if they were changed in the meantime. `change` returns `None` to keep them as they are.
+    /// Returns the attempts before and after the change, which are forgotten after `ttl` ms.
+    pub async fn update_login_attempts(
+        &self,
+        key: &str,
+        ttl: u64,
+        change: impl Fn(&LoginAttempts) -> Option<LoginAttempts>,
+    ) -> (LoginAttempts, Option<LoginAttempts>) {
+        loop {
+            let stored = self.storage.get_expiring(LOGIN_ATTEMPTS, key).await;
+            let attempts = stored
+                .as_deref()
+                .map(|attempts| serde_json::from_str(attempts).unwrap())
+                .unwrap_or_default();
+
+            let changed = match change(&attempts) {
+                Some(changed) => changed,
+                None => return (attempts, None),
+            };
+            let data = serde_json::to_string(&changed).unwrap();
+
+            if self
+                .storage
+                .swap_expiring(LOGIN_ATTEMPTS, key, stored.as_deref(), &data, ttl)
+                .await
+            {
+                return (attempts, Some(changed));
+            }
+        }
+    }
+}
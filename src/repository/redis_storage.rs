@@ -0,0 +1,342 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use redis::AsyncCommands;
+use tokio::{
+    sync::broadcast::{self, Receiver},
+    task::JoinHandle,
+};
+
+use crate::redis_migrations::RedisTimer;
+
+use super::{StorageBackend, Timer, TimerUpdate, UPDATES_CAPACITY};
+
+/// Sets the timers in `KEYS` to the new versions in `ARGV`, but only if each stored timer
+/// has the revision before the new one. Scripts run atomically, so no other client can
+/// change the timers in between.
+const UPDATE_TIMERS_SCRIPT: &str = r"
+for i, id in ipairs(KEYS) do
+    local stored = redis.call('GET', id)
+    if not stored then
+        return 0
+    end
+    local revision = cjson.decode(stored)['revision'] or 0
+    if revision + 1 ~= cjson.decode(ARGV[i])['revision'] then
+        return 0
+    end
+end
+for i, id in ipairs(KEYS) do
+    redis.call('SET', id, ARGV[i])
+end
+return 1
+";
+
+/// Sets `KEYS[1]` to `ARGV[2]` if it is still `ARGV[1]`
+const SWAP_DOCUMENT_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) ~= ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[2])
+return 1
+";
+
+/// Sets `KEYS[1]` to `ARGV[2]` for `ARGV[3]` ms if it is still `ARGV[1]`,
+/// an empty `ARGV[1]` if it shouldn't exist
+const SWAP_EXPIRING_SCRIPT: &str = r"
+if (redis.call('GET', KEYS[1]) or '') ~= ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[2], 'PX', ARGV[3])
+return 1
+";
+
+#[derive(Clone)]
+pub struct RedisStorage {
+    redis: redis::aio::ConnectionManager,
+    updates_tx: broadcast::Sender<TimerUpdate>,
+}
+
+impl RedisStorage {
+    pub async fn new(redis_string: String) -> Self {
+        let client = redis::Client::open(redis_string).expect("Could not connect to redis");
+        let manager = redis::aio::ConnectionManager::new(client.clone())
+            .await
+            .unwrap();
+
+        let (updates_tx, _) = broadcast::channel::<TimerUpdate>(UPDATES_CAPACITY);
+        spawn_global_redis_listener_task(manager.clone(), client, updates_tx.clone());
+
+        RedisStorage {
+            redis: manager,
+            updates_tx,
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for RedisStorage {
+    async fn get_timer(&self, id: String) -> Option<Timer> {
+        let mut redis = self.redis.clone();
+        let timer = &redis.get::<String, String>(id).await;
+
+        if timer.is_err() {
+            return None;
+        }
+
+        let timer: RedisTimer = serde_json::from_str(timer.as_ref().unwrap()).unwrap();
+        Some(timer.into())
+    }
+
+    async fn get_timers(&self) -> Vec<Timer> {
+        let mut redis = self.redis.clone();
+        // documents and logs have a `:` in their key, timers don't
+        let ids = match redis.scan::<String>().await {
+            Ok(keys) => keys.filter(|key| futures::future::ready(!key.contains(':'))),
+            Err(_) => return vec![],
+        }
+        .collect::<Vec<_>>()
+        .await;
+
+        let mut timers = vec![];
+        for id in ids {
+            if let Some(timer) = self.get_timer(id).await {
+                timers.push(timer);
+            }
+        }
+        timers
+    }
+
+    async fn create_timer(&self, timer: &Timer) -> Result<(), ()> {
+        let created = self
+            .redis
+            .clone()
+            .set_nx::<String, String, bool>(timer.id.clone(), serde_json::to_string(timer).unwrap())
+            .await
+            .unwrap();
+
+        if created {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    async fn update_timer(&self, timer: &Timer) -> Result<(), ()> {
+        self.update_timers(std::slice::from_ref(timer)).await
+    }
+
+    async fn update_timers(&self, timers: &[Timer]) -> Result<(), ()> {
+        let script = redis::Script::new(UPDATE_TIMERS_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        for timer in timers {
+            invocation
+                .key(timer.id.clone())
+                .arg(serde_json::to_string(timer).unwrap());
+        }
+
+        let updated = invocation
+            .invoke_async::<_, bool>(&mut self.redis.clone())
+            .await
+            .unwrap();
+
+        if updated {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    async fn delete_timer(&self, id: String) -> Result<(), ()> {
+        self.redis
+            .clone()
+            .del::<String, ()>(id)
+            .await
+            .map_err(|_| ())
+    }
+
+    fn subscribe(&self) -> Receiver<TimerUpdate> {
+        self.updates_tx.subscribe()
+    }
+
+    async fn get_document(&self, collection: &str, id: &str) -> Option<String> {
+        self.redis
+            .clone()
+            .get::<String, String>(document_key(collection, id))
+            .await
+            .ok()
+    }
+
+    async fn create_document(&self, collection: &str, id: &str, data: String) -> Result<(), ()> {
+        let created = self
+            .redis
+            .clone()
+            .set_nx::<String, String, bool>(document_key(collection, id), data)
+            .await
+            .unwrap();
+
+        if created {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    async fn update_document(&self, collection: &str, id: &str, data: String) {
+        self.redis
+            .clone()
+            .set::<String, String, ()>(document_key(collection, id), data)
+            .await
+            .unwrap();
+    }
+
+    async fn swap_document(
+        &self,
+        collection: &str,
+        id: &str,
+        expected: &str,
+        data: String,
+    ) -> bool {
+        redis::Script::new(SWAP_DOCUMENT_SCRIPT)
+            .key(document_key(collection, id))
+            .arg(expected)
+            .arg(data)
+            .invoke_async::<_, bool>(&mut self.redis.clone())
+            .await
+            .unwrap_or(false)
+    }
+
+    async fn delete_document(&self, collection: &str, id: &str) -> Result<(), ()> {
+        let deleted = self
+            .redis
+            .clone()
+            .del::<String, usize>(document_key(collection, id))
+            .await
+            .map_err(|_| ())?;
+
+        if deleted == 0 {
+            return Err(());
+        }
+
+        Ok(())
+    }
+
+    async fn append_log(&self, log: &str, id: &str, entry: String) {
+        self.redis
+            .clone()
+            .rpush::<String, String, ()>(log_key(log, id), entry)
+            .await
+            .unwrap();
+    }
+
+    async fn get_log(&self, log: &str, id: &str) -> Vec<String> {
+        self.redis
+            .clone()
+            .lrange::<String, Vec<String>>(log_key(log, id), 0, -1)
+            .await
+            .unwrap_or_default()
+    }
+
+    async fn trim_log(&self, log: &str, id: &str, keep: usize) {
+        let mut redis = self.redis.clone();
+        // LTRIM can't remove all entries with negative indices
+        if keep == 0 {
+            redis.del::<String, ()>(log_key(log, id)).await.unwrap();
+        } else {
+            redis
+                .ltrim::<String, ()>(log_key(log, id), -(keep as isize), -1)
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn get_expiring(&self, collection: &str, id: &str) -> Option<String> {
+        self.redis
+            .clone()
+            .get::<String, Option<String>>(expiring_key(collection, id))
+            .await
+            .unwrap_or_default()
+    }
+
+    async fn swap_expiring(
+        &self,
+        collection: &str,
+        id: &str,
+        expected: Option<&str>,
+        data: &str,
+        ttl: u64,
+    ) -> bool {
+        redis::Script::new(SWAP_EXPIRING_SCRIPT)
+            .key(expiring_key(collection, id))
+            .arg(expected.unwrap_or_default())
+            .arg(data)
+            .arg(ttl)
+            .invoke_async::<_, bool>(&mut self.redis.clone())
+            .await
+            .unwrap_or(false)
+    }
+}
+
+/// Timers are stored with their id as key, which can't contain a `:`,
+/// so documents can't collide with them
+fn document_key(collection: &str, id: &str) -> String {
+    format!("{}:{}", collection, id)
+}
+
+/// Logs are lists with a prefix no document collection uses
+fn log_key(log: &str, id: &str) -> String {
+    format!("log:{}:{}", log, id)
+}
+
+/// Expiring records have their own prefix as well, redis removes them once they expire
+fn expiring_key(collection: &str, id: &str) -> String {
+    format!("expiring:{}:{}", collection, id)
+}
+
+pub fn spawn_global_redis_listener_task(
+    mut redis: redis::aio::ConnectionManager,
+    redis_client: redis::Client,
+    redis_task_tx: broadcast::Sender<TimerUpdate>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut connection = redis_client.get_async_connection().await.unwrap();
+        let _: () = redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("notify-keyspace-events")
+            .arg("KEA")
+            .query_async(&mut connection)
+            .await
+            .unwrap();
+
+        let mut pubsub = connection.into_pubsub();
+
+        pubsub
+            .psubscribe("__keyspace@*__:*")
+            .await
+            .expect("Failed to subscribe to redis channel");
+
+        let mut pubsub = pubsub.into_on_message();
+
+        while let Some(msg) = pubsub.next().await {
+            println!("Updated! {:?}", msg);
+            // the channel is `__keyspace@<db>__:<key>`
+            let timer_id = match msg.get_channel_name().split_once("__:") {
+                Some((_, key)) if !key.contains(':') => key,
+                _ => continue,
+            };
+
+            // the payload is the command which changed the key
+            if msg.get_payload::<String>().as_deref() == Ok("del") {
+                let _ = redis_task_tx.send(TimerUpdate::Deleted(timer_id.to_owned()));
+                continue;
+            }
+
+            let timer_str = match redis.get::<String, String>(String::from(timer_id)).await {
+                Ok(timer_str) => timer_str,
+                Err(_) => continue,
+            };
+            let timer: RedisTimer = serde_json::from_str(&timer_str).unwrap();
+
+            // Broadcast to all listeners
+            let _ = redis_task_tx.send(TimerUpdate::Changed(Box::new(timer.into())));
+        }
+    })
+}
@@ -0,0 +1,392 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use tokio::sync::broadcast::{self, Receiver};
+
+use crate::redis_migrations::RedisTimer;
+use crate::timer_state::current_time;
+
+use super::{StorageBackend, Timer, TimerUpdate, UPDATES_CAPACITY};
+
+/// Stores the timers, all other documents and logs as json in a sqlite database.
+pub struct SqliteStorage {
+    connection: Arc<Mutex<Connection>>,
+    updates_tx: broadcast::Sender<TimerUpdate>,
+}
+
+impl SqliteStorage {
+    pub fn new(path: String) -> Self {
+        let connection = Connection::open(path).expect("Could not open sqlite database");
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS timers (id TEXT PRIMARY KEY, data TEXT NOT NULL);
+                CREATE TABLE IF NOT EXISTS documents (
+                    collection TEXT NOT NULL,
+                    id TEXT NOT NULL,
+                    data TEXT NOT NULL,
+                    PRIMARY KEY (collection, id)
+                );
+                CREATE TABLE IF NOT EXISTS logs (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    log TEXT NOT NULL,
+                    id TEXT NOT NULL,
+                    entry TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS logs_by_id ON logs (log, id, seq);
+                CREATE TABLE IF NOT EXISTS expiring (
+                    collection TEXT NOT NULL,
+                    id TEXT NOT NULL,
+                    data TEXT NOT NULL,
+                    expires_at INTEGER NOT NULL,
+                    PRIMARY KEY (collection, id)
+                );",
+            )
+            .expect("Could not create sqlite tables");
+
+        let (updates_tx, _) = broadcast::channel::<TimerUpdate>(UPDATES_CAPACITY);
+
+        SqliteStorage {
+            connection: Arc::new(Mutex::new(connection)),
+            updates_tx,
+        }
+    }
+
+    /// sqlite blocks, so queries have to run outside of the async runtime
+    async fn run<T, F>(&self, query: F) -> T
+    where
+        F: FnOnce(&Connection) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || query(&connection.lock().unwrap()))
+            .await
+            .unwrap()
+    }
+}
+
+#[async_trait]
+impl StorageBackend for SqliteStorage {
+    async fn get_timer(&self, id: String) -> Option<Timer> {
+        let timer = self
+            .run(move |connection| {
+                connection
+                    .query_row("SELECT data FROM timers WHERE id = ?1", [id], |row| {
+                        row.get::<_, String>(0)
+                    })
+                    .optional()
+                    .unwrap()
+            })
+            .await?;
+
+        let timer: RedisTimer = serde_json::from_str(&timer).unwrap();
+        Some(timer.into())
+    }
+
+    async fn get_timers(&self) -> Vec<Timer> {
+        let timers = self
+            .run(|connection| {
+                connection
+                    .prepare("SELECT data FROM timers")
+                    .unwrap()
+                    .query_map([], |row| row.get::<_, String>(0))
+                    .unwrap()
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap()
+            })
+            .await;
+
+        timers
+            .iter()
+            .map(|timer| serde_json::from_str::<RedisTimer>(timer).unwrap().into())
+            .collect()
+    }
+
+    async fn create_timer(&self, timer: &Timer) -> Result<(), ()> {
+        let id = timer.id.clone();
+        let data = serde_json::to_string(timer).unwrap();
+        let inserted = self
+            .run(move |connection| {
+                connection
+                    .execute(
+                        "INSERT OR IGNORE INTO timers (id, data) VALUES (?1, ?2)",
+                        params![id, data],
+                    )
+                    .unwrap()
+            })
+            .await;
+
+        if inserted == 0 {
+            return Err(());
+        }
+
+        let _ = self
+            .updates_tx
+            .send(TimerUpdate::Changed(Box::new(timer.clone())));
+        Ok(())
+    }
+
+    async fn update_timer(&self, timer: &Timer) -> Result<(), ()> {
+        self.update_timers(std::slice::from_ref(timer)).await
+    }
+
+    async fn update_timers(&self, timers: &[Timer]) -> Result<(), ()> {
+        let rows = timers
+            .iter()
+            .map(|timer| {
+                (
+                    timer.id.clone(),
+                    timer.revision,
+                    serde_json::to_string(timer).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let updated = self
+            .run(move |connection| {
+                // the transaction is rolled back if it isn't committed
+                let transaction = connection.unchecked_transaction().unwrap();
+                for (id, revision, data) in rows {
+                    let stored = transaction
+                        .query_row("SELECT data FROM timers WHERE id = ?1", [&id], |row| {
+                            row.get::<_, String>(0)
+                        })
+                        .optional()
+                        .unwrap()
+                        .map(|stored| {
+                            Timer::from(serde_json::from_str::<RedisTimer>(&stored).unwrap())
+                        });
+                    if !matches!(stored, Some(stored) if stored.revision + 1 == revision) {
+                        return false;
+                    }
+
+                    transaction
+                        .execute(
+                            "UPDATE timers SET data = ?2 WHERE id = ?1",
+                            params![id, data],
+                        )
+                        .unwrap();
+                }
+                transaction.commit().unwrap();
+                true
+            })
+            .await;
+
+        if !updated {
+            return Err(());
+        }
+
+        for timer in timers {
+            let _ = self
+                .updates_tx
+                .send(TimerUpdate::Changed(Box::new(timer.clone())));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_timer(&self, id: String) -> Result<(), ()> {
+        let deleted_id = id.clone();
+        let deleted = self
+            .run(move |connection| {
+                connection
+                    .execute("DELETE FROM timers WHERE id = ?1", [deleted_id])
+                    .unwrap()
+            })
+            .await;
+
+        if deleted == 0 {
+            return Err(());
+        }
+
+        let _ = self.updates_tx.send(TimerUpdate::Deleted(id));
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Receiver<TimerUpdate> {
+        self.updates_tx.subscribe()
+    }
+
+    async fn get_document(&self, collection: &str, id: &str) -> Option<String> {
+        let (collection, id) = (collection.to_owned(), id.to_owned());
+        self.run(move |connection| {
+            connection
+                .query_row(
+                    "SELECT data FROM documents WHERE collection = ?1 AND id = ?2",
+                    [collection, id],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()
+                .unwrap()
+        })
+        .await
+    }
+
+    async fn create_document(&self, collection: &str, id: &str, data: String) -> Result<(), ()> {
+        let (collection, id) = (collection.to_owned(), id.to_owned());
+        let inserted = self
+            .run(move |connection| {
+                connection
+                    .execute(
+                        "INSERT OR IGNORE INTO documents (collection, id, data) VALUES (?1, ?2, ?3)",
+                        params![collection, id, data],
+                    )
+                    .unwrap()
+            })
+            .await;
+
+        if inserted == 0 {
+            return Err(());
+        }
+
+        Ok(())
+    }
+
+    async fn update_document(&self, collection: &str, id: &str, data: String) {
+        let (collection, id) = (collection.to_owned(), id.to_owned());
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "INSERT OR REPLACE INTO documents (collection, id, data) VALUES (?1, ?2, ?3)",
+                    params![collection, id, data],
+                )
+                .unwrap()
+        })
+        .await;
+    }
+
+    async fn swap_document(
+        &self,
+        collection: &str,
+        id: &str,
+        expected: &str,
+        data: String,
+    ) -> bool {
+        let (collection, id, expected) =
+            (collection.to_owned(), id.to_owned(), expected.to_owned());
+        let updated = self
+            .run(move |connection| {
+                connection
+                    .execute(
+                        "UPDATE documents SET data = ?4 WHERE collection = ?1 AND id = ?2 AND data = ?3",
+                        params![collection, id, expected, data],
+                    )
+                    .unwrap()
+            })
+            .await;
+
+        updated == 1
+    }
+
+    async fn delete_document(&self, collection: &str, id: &str) -> Result<(), ()> {
+        let (collection, id) = (collection.to_owned(), id.to_owned());
+        let deleted = self
+            .run(move |connection| {
+                connection
+                    .execute(
+                        "DELETE FROM documents WHERE collection = ?1 AND id = ?2",
+                        [collection, id],
+                    )
+                    .unwrap()
+            })
+            .await;
+
+        if deleted == 0 {
+            return Err(());
+        }
+
+        Ok(())
+    }
+
+    async fn append_log(&self, log: &str, id: &str, entry: String) {
+        let (log, id) = (log.to_owned(), id.to_owned());
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO logs (log, id, entry) VALUES (?1, ?2, ?3)",
+                    params![log, id, entry],
+                )
+                .unwrap()
+        })
+        .await;
+    }
+
+    async fn get_log(&self, log: &str, id: &str) -> Vec<String> {
+        let (log, id) = (log.to_owned(), id.to_owned());
+        self.run(move |connection| {
+            connection
+                .prepare("SELECT entry FROM logs WHERE log = ?1 AND id = ?2 ORDER BY seq")
+                .unwrap()
+                .query_map([log, id], |row| row.get::<_, String>(0))
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        })
+        .await
+    }
+
+    async fn trim_log(&self, log: &str, id: &str, keep: usize) {
+        let (log, id) = (log.to_owned(), id.to_owned());
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "DELETE FROM logs WHERE log = ?1 AND id = ?2 AND seq NOT IN (
+                        SELECT seq FROM logs WHERE log = ?1 AND id = ?2
+                        ORDER BY seq DESC LIMIT ?3
+                    )",
+                    params![log, id, keep as i64],
+                )
+                .unwrap()
+        })
+        .await;
+    }
+
+    async fn get_expiring(&self, collection: &str, id: &str) -> Option<String> {
+        let (collection, id) = (collection.to_owned(), id.to_owned());
+        self.run(move |connection| {
+            connection
+                .query_row(
+                    "SELECT data FROM expiring
+                    WHERE collection = ?1 AND id = ?2 AND expires_at > ?3",
+                    params![collection, id, current_time()],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()
+                .unwrap()
+        })
+        .await
+    }
+
+    async fn swap_expiring(
+        &self,
+        collection: &str,
+        id: &str,
+        expected: Option<&str>,
+        data: &str,
+        ttl: u64,
+    ) -> bool {
+        let (collection, id) = (collection.to_owned(), id.to_owned());
+        let (expected, data) = (expected.map(str::to_owned), data.to_owned());
+        // the connection is locked while the closure runs, so no other query gets in between
+        self.run(move |connection| {
+            let now = current_time();
+            connection
+                .execute("DELETE FROM expiring WHERE expires_at <= ?1", [now])
+                .unwrap();
+
+            let changed = match expected {
+                Some(expected) => connection.execute(
+                    "UPDATE expiring SET data = ?4, expires_at = ?5
+                    WHERE collection = ?1 AND id = ?2 AND data = ?3",
+                    params![collection, id, expected, data, now + ttl],
+                ),
+                None => connection.execute(
+                    "INSERT OR IGNORE INTO expiring (collection, id, data, expires_at)
+                    VALUES (?1, ?2, ?3, ?4)",
+                    params![collection, id, data, now + ttl],
+                ),
+            };
+            changed.unwrap() == 1
+        })
+        .await
+    }
+}
@@ -0,0 +1,515 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::sync::broadcast::Receiver;
+
+use super::{
+    GroupMember, MemoryStorage, Repository, Segment, SqliteStorage, StorageBackend, Timer,
+    TimerGroup, TimerUpdate, MAX_REVISIONS, REVISIONS,
+};
+use crate::audit::{Action, Actor, HistoryEntry};
+
+fn timer(id: &str) -> Timer {
+    Timer {
+        sequences: vec![vec![Segment {
+            label: "Boulder".to_owned(),
+            time: 240000,
+            color: None,
+            count_to: 0,
+            sounds: vec![],
+        }]
+        .into()],
+        repeat: true,
+        id: id.to_owned(),
+        password: "hash".to_owned(),
+        ..Default::default()
+    }
+}
+
+fn group(id: &str) -> TimerGroup {
+    TimerGroup {
+        id: id.to_owned(),
+        password: "hash".to_owned(),
+        members: vec![member("first"), member("second")],
+        token_generation: 0,
+    }
+}
+
+fn member(timer_id: &str) -> GroupMember {
+    GroupMember {
+        timer_id: timer_id.to_owned(),
+        token_generation: 0,
+    }
+}
+
+fn history_entry(action: Action) -> HistoryEntry {
+    HistoryEntry {
+        time: 0,
+        action,
+        actor: Actor::Password,
+        client_ip: "127.0.0.1".to_owned(),
+        changes: vec![],
+    }
+}
+
+async fn changed(updates_rx: &mut Receiver<TimerUpdate>) -> Timer {
+    match updates_rx.recv().await.unwrap() {
+        TimerUpdate::Changed(timer) => *timer,
+        TimerUpdate::Deleted(id) => panic!("{} was deleted", id),
+    }
+}
+
+async fn deleted(updates_rx: &mut Receiver<TimerUpdate>) -> String {
+    match updates_rx.recv().await.unwrap() {
+        TimerUpdate::Deleted(id) => id,
+        TimerUpdate::Changed(timer) => panic!("{} was changed", timer.id),
+    }
+}
+
+fn temp_file(extension: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    std::env::temp_dir().join(format!("distributed-timer-{}.{}", nanos, extension))
+}
+
+#[tokio::test]
+async fn test_memory_create_and_get() {
+    let repository = Repository::new(MemoryStorage::new(None).await);
+
+    assert!(repository.get_timer("test".to_owned()).await.is_none());
+    assert!(repository.create_timer(&timer("test")).await.is_ok());
+    assert!(repository.create_timer(&timer("test")).await.is_err());
+
+    let stored = repository.get_timer("test".to_owned()).await.unwrap();
+    assert_eq!(stored.id, "test");
+    assert_eq!(stored.segments()[0].label, "Boulder");
+}
+
+#[tokio::test]
+async fn test_memory_update_is_broadcast() {
+    let repository = Repository::new(MemoryStorage::new(None).await);
+    let mut updates_rx = repository.updates_rx.resubscribe();
+
+    repository.create_timer(&timer("test")).await.unwrap();
+    assert_eq!(changed(&mut updates_rx).await.id, "test");
+
+    let mut updated = timer("test");
+    updated.stop_at = Some(1000);
+    repository.update_timer(&mut updated).await.unwrap();
+    assert_eq!(changed(&mut updates_rx).await.stop_at, Some(1000));
+}
+
+#[tokio::test]
+async fn test_memory_delete() {
+    let repository = Repository::new(MemoryStorage::new(None).await);
+    let mut updates_rx = repository.updates_rx.resubscribe();
+
+    assert!(repository.delete_timer("test".to_owned()).await.is_err());
+    repository.create_timer(&timer("test")).await.unwrap();
+    changed(&mut updates_rx).await;
+    assert!(repository.delete_timer("test".to_owned()).await.is_ok());
+    assert_eq!(deleted(&mut updates_rx).await, "test");
+    assert!(repository.get_timer("test".to_owned()).await.is_none());
+}
+
+#[tokio::test]
+async fn test_memory_snapshot() {
+    let path = temp_file("json");
+
+    let repository = Repository::new(MemoryStorage::new(Some(path.clone())).await);
+    repository.create_timer(&timer("first")).await.unwrap();
+    repository.create_timer(&timer("second")).await.unwrap();
+    repository.delete_timer("second".to_owned()).await.unwrap();
+
+    let repository = Repository::new(MemoryStorage::new(Some(path.clone())).await);
+    assert!(repository.get_timer("first".to_owned()).await.is_some());
+    assert!(repository.get_timer("second".to_owned()).await.is_none());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_memory_snapshot_with_groups() {
+    let path = temp_file("json");
+
+    let repository = Repository::new(MemoryStorage::new(Some(path.clone())).await);
+    repository.create_timer(&timer("first")).await.unwrap();
+    repository.create_group(&group("walls")).await.unwrap();
+
+    let repository = Repository::new(MemoryStorage::new(Some(path.clone())).await);
+    assert!(repository.get_timer("first".to_owned()).await.is_some());
+    assert_eq!(
+        repository
+            .get_group("walls".to_owned())
+            .await
+            .unwrap()
+            .members,
+        vec![member("first"), member("second")]
+    );
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_memory_loads_old_snapshot() {
+    let path = temp_file("json");
+    std::fs::write(
+        &path,
+        serde_json::to_string(&HashMap::from([("first", timer("first"))])).unwrap(),
+    )
+    .unwrap();
+
+    let repository = Repository::new(MemoryStorage::new(Some(path.clone())).await);
+    assert!(repository.get_timer("first".to_owned()).await.is_some());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_memory_update_timers() {
+    let repository = Repository::new(MemoryStorage::new(None).await);
+    test_update_timers(repository).await;
+}
+
+#[tokio::test]
+async fn test_memory_history() {
+    let path = temp_file("json");
+
+    let repository = Repository::new(MemoryStorage::new(Some(path.clone())).await);
+    test_history(repository).await;
+
+    // the history is kept in the snapshot
+    let repository = Repository::new(MemoryStorage::new(Some(path.clone())).await);
+    assert_eq!(repository.get_history("first").await.len(), 2);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_memory_get_timers() {
+    let repository = Repository::new(MemoryStorage::new(None).await);
+    test_get_timers(repository).await;
+}
+
+#[tokio::test]
+async fn test_memory_revisions() {
+    let repository = Repository::new(MemoryStorage::new(None).await);
+    test_revisions(repository).await;
+}
+
+#[tokio::test]
+async fn test_memory_groups() {
+    let repository = Repository::new(MemoryStorage::new(None).await);
+    test_groups(repository).await;
+}
+
+#[tokio::test]
+async fn test_memory_expiring() {
+    test_expiring(MemoryStorage::new(None).await).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_create_update_delete() {
+    let repository = Repository::new(SqliteStorage::new(":memory:".to_owned()));
+    let mut updates_rx = repository.updates_rx.resubscribe();
+
+    assert!(repository.create_timer(&timer("test")).await.is_ok());
+    assert!(repository.create_timer(&timer("test")).await.is_err());
+    assert_eq!(changed(&mut updates_rx).await.id, "test");
+
+    let mut updated = timer("test");
+    updated.stop_at = Some(1000);
+    repository.update_timer(&mut updated).await.unwrap();
+    assert_eq!(changed(&mut updates_rx).await.stop_at, Some(1000));
+    assert_eq!(
+        repository
+            .get_timer("test".to_owned())
+            .await
+            .unwrap()
+            .stop_at,
+        Some(1000)
+    );
+
+    assert!(repository.delete_timer("test".to_owned()).await.is_ok());
+    assert_eq!(deleted(&mut updates_rx).await, "test");
+    assert!(repository.delete_timer("test".to_owned()).await.is_err());
+    assert!(repository.get_timer("test".to_owned()).await.is_none());
+}
+
+#[tokio::test]
+async fn test_sqlite_migrates_old_timers() {
+    let path = temp_file("sqlite");
+    let storage = SqliteStorage::new(path.to_str().unwrap().to_owned());
+
+    rusqlite::Connection::open(&path)
+        .unwrap()
+        .execute(
+            "INSERT INTO timers (id, data) VALUES ('v0', ?1)",
+            [r##"{
+                "segments": [{"label": "Boulder", "time": 230000, "sound": true, "color": null}],
+                "id": "v0",
+                "repeat": true,
+                "display_options": {"clock": false, "pre_start_behaviour": "ShowZero"},
+                "start_at": 0,
+                "stop_at": null,
+                "password": "test"
+            }"##],
+        )
+        .unwrap();
+
+    let timer = storage.get_timer("v0".to_owned()).await.unwrap();
+    assert_eq!(timer.segments()[0].sounds.len(), 2);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_sqlite_update_timers() {
+    let repository = Repository::new(SqliteStorage::new(":memory:".to_owned()));
+    test_update_timers(repository).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_history() {
+    let repository = Repository::new(SqliteStorage::new(":memory:".to_owned()));
+    test_history(repository).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_get_timers() {
+    let repository = Repository::new(SqliteStorage::new(":memory:".to_owned()));
+    test_get_timers(repository).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_revisions() {
+    let repository = Repository::new(SqliteStorage::new(":memory:".to_owned()));
+    test_revisions(repository).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_groups() {
+    let repository = Repository::new(SqliteStorage::new(":memory:".to_owned()));
+    test_groups(repository).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_expiring() {
+    test_expiring(SqliteStorage::new(":memory:".to_owned())).await;
+}
+
+async fn test_get_timers(repository: Repository) {
+    assert!(repository.get_timers().await.is_empty());
+
+    repository.create_timer(&timer("first")).await.unwrap();
+    repository.create_timer(&timer("second")).await.unwrap();
+    repository.create_group(&group("group")).await.unwrap();
+
+    let mut ids = repository
+        .get_timers()
+        .await
+        .into_iter()
+        .map(|timer| timer.id)
+        .collect::<Vec<_>>();
+    ids.sort();
+    assert_eq!(ids, vec!["first", "second"]);
+}
+
+async fn test_update_timers(repository: Repository) {
+    let mut updates_rx = repository.updates_rx.resubscribe();
+
+    for id in ["first", "second"] {
+        repository.create_timer(&timer(id)).await.unwrap();
+        assert_eq!(changed(&mut updates_rx).await.id, id);
+    }
+
+    let mut timers = ["first", "second"].map(|id| Timer {
+        start_at: 1000,
+        ..timer(id)
+    });
+    repository.update_timers(&mut timers).await.unwrap();
+
+    for id in ["first", "second"] {
+        assert_eq!(changed(&mut updates_rx).await.id, id);
+        let stored = repository.get_timer(id.to_owned()).await.unwrap();
+        assert_eq!(stored.start_at, 1000);
+        assert_eq!(stored.revision, 1);
+    }
+
+    // the first timer is outdated now, so neither of them is changed
+    let mut timers = [
+        timer("first"),
+        repository.get_timer("second".to_owned()).await.unwrap(),
+    ];
+    assert!(repository.update_timers(&mut timers).await.is_err());
+    let stored = repository.get_timer("second".to_owned()).await.unwrap();
+    assert_eq!(stored.revision, 1);
+
+    // deleted timers aren't created again
+    repository.delete_timer("first".to_owned()).await.unwrap();
+    let mut deleted = timer("first");
+    assert!(repository.update_timer(&mut deleted).await.is_err());
+    assert!(repository.get_timer("first".to_owned()).await.is_none());
+}
+
+async fn test_groups(repository: Repository) {
+    assert!(repository.get_group("walls".to_owned()).await.is_none());
+    assert!(repository.create_group(&group("walls")).await.is_ok());
+    assert!(repository.create_group(&group("walls")).await.is_err());
+
+    let mut updated = group("walls");
+    updated.members = vec![member("third")];
+    assert!(repository
+        .update_group(&group("walls"), &updated)
+        .await
+        .is_ok());
+    // the group was changed since it was read
+    let mut stale = group("walls");
+    stale.token_generation += 1;
+    assert!(repository
+        .update_group(&group("walls"), &stale)
+        .await
+        .is_err());
+    assert_eq!(
+        repository
+            .get_group("walls".to_owned())
+            .await
+            .unwrap()
+            .members,
+        vec![member("third")]
+    );
+
+    // groups and timers don't share their ids
+    assert!(repository.get_timer("walls".to_owned()).await.is_none());
+
+    assert!(repository.delete_group("walls".to_owned()).await.is_ok());
+    assert!(repository.delete_group("walls".to_owned()).await.is_err());
+    assert!(repository.get_group("walls".to_owned()).await.is_none());
+    assert!(repository
+        .update_group(&group("walls"), &updated)
+        .await
+        .is_err());
+}
+
+async fn test_history(repository: Repository) {
+    assert!(repository.get_history("first").await.is_empty());
+
+    repository
+        .append_history("first", &history_entry(Action::Create))
+        .await;
+    repository
+        .append_history("second", &history_entry(Action::Create))
+        .await;
+    repository
+        .append_history("first", &history_entry(Action::Start))
+        .await;
+
+    assert_eq!(
+        repository.get_history("first").await,
+        vec![history_entry(Action::Create), history_entry(Action::Start)]
+    );
+    assert_eq!(repository.get_history("second").await.len(), 1);
+}
+
+async fn test_revisions(repository: Repository) {
+    repository.create_timer(&timer("first")).await.unwrap();
+    assert!(repository.get_revisions("first").await.is_empty());
+
+    for revision in 0..MAX_REVISIONS as u64 + 2 {
+        let replaced = Timer {
+            revision,
+            repeat: revision % 2 == 0,
+            ..timer("first")
+        };
+        repository.add_revision(&replaced, revision * 1000).await;
+    }
+
+    // only the newest revisions are kept, numbered by the revision they replaced
+    let revisions = repository.get_revisions("first").await;
+    assert_eq!(revisions.len(), MAX_REVISIONS);
+    assert_eq!(revisions[0].number, 2);
+    assert_eq!(revisions[0].time, 2000);
+    assert!(revisions[0].repeat);
+    assert_eq!(revisions[0].sequences, timer("first").sequences);
+    assert_eq!(revisions.last().unwrap().number, MAX_REVISIONS as u64 + 1);
+
+    repository.delete_timer("first".to_owned()).await.unwrap();
+    assert!(repository.get_revisions("first").await.is_empty());
+
+    // revisions from before they were a log come first
+    repository.create_timer(&timer("first")).await.unwrap();
+    let old_revisions = serde_json::json!([{"number": 1, "time": 0, "timer": timer("first")}]);
+    repository
+        .storage
+        .update_document(REVISIONS, "first", old_revisions.to_string())
+        .await;
+    repository
+        .add_revision(
+            &Timer {
+                revision: 2,
+                ..timer("first")
+            },
+            1000,
+        )
+        .await;
+    let numbers = repository
+        .get_revisions("first")
+        .await
+        .iter()
+        .map(|r| r.number)
+        .collect::<Vec<_>>();
+    assert_eq!(numbers, vec![1, 2]);
+
+    repository.delete_timer("first".to_owned()).await.unwrap();
+    assert!(repository.get_revisions("first").await.is_empty());
+}
+
+async fn test_expiring(storage: impl StorageBackend) {
+    assert!(storage.get_expiring("attempts", "first").await.is_none());
+
+    // only set if it doesn't exist yet
+    assert!(
+        storage
+            .swap_expiring("attempts", "first", None, "1", 1000)
+            .await
+    );
+    assert!(
+        !storage
+            .swap_expiring("attempts", "first", None, "2", 1000)
+            .await
+    );
+
+    // and only changed if it is still what was expected
+    assert!(
+        !storage
+            .swap_expiring("attempts", "first", Some("2"), "3", 1000)
+            .await
+    );
+    assert!(
+        storage
+            .swap_expiring("attempts", "first", Some("1"), "2", 1000)
+            .await
+    );
+    assert_eq!(
+        storage.get_expiring("attempts", "first").await.as_deref(),
+        Some("2")
+    );
+
+    // an expired record doesn't exist anymore
+    assert!(
+        storage
+            .swap_expiring("attempts", "second", None, "1", 0)
+            .await
+    );
+    assert!(storage.get_expiring("attempts", "second").await.is_none());
+    assert!(
+        storage
+            .swap_expiring("attempts", "second", None, "2", 1000)
+            .await
+    );
+}
@@ -0,0 +1,685 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts, Path, State};
+use axum::headers::authorization::{Authorization, Bearer};
+use axum::http::{header, request::Parts, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::TypedHeader;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+use crate::audit::Actor;
+use crate::models::{Claims, RefreshRequest, Role, SharedState, TokenKind, TokenResponse};
+use crate::repository::{LoginAttempts, Repository, Timer, User};
+use crate::timer_state::current_time;
+
+/// in seconds
+const ACCESS_TOKEN_LIFETIME: u64 = 60 * 60;
+const REFRESH_TOKEN_LIFETIME: u64 = 30 * 24 * 60 * 60;
+
+/// Failed password attempts which are allowed before further attempts are delayed
+const TIMER_FREE_ATTEMPTS: u32 = 5;
+/// Clients may try more often, e.g. a venue behind one address with several timers
+const CLIENT_FREE_ATTEMPTS: u32 = 20;
+/// in ms, doubled with every further failed attempt
+const BASE_LOCKOUT: u64 = 1000;
+const MAX_LOCKOUT: u64 = 60 * 60 * 1000;
+/// in ms, failed attempts are forgotten after this time without another one
+const FAILURE_MEMORY: u64 = 24 * 60 * 60 * 1000;
+
+/// What a token is issued for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenSubject {
+    Timer,
+    Group,
+    User,
+}
+
+impl TokenSubject {
+    /// Group tokens have their own issuer, so they can't be used for a timer with the same id
+    fn issuer(&self) -> &'static str {
+        match self {
+            TokenSubject::Timer => "de:itsblue:distributed-timer",
+            TokenSubject::Group => "de:itsblue:distributed-timer:group",
+            TokenSubject::User => "de:itsblue:distributed-timer:user",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            TokenSubject::Timer => "timer",
+            TokenSubject::Group => "group",
+            TokenSubject::User => "user",
+        }
+    }
+
+    /// The current token generation of the timer or group, `None` if it doesn't exist
+    async fn token_generation(&self, repository: &Repository, id: String) -> Option<u64> {
+        match self {
+            TokenSubject::Timer => repository
+                .get_timer(id)
+                .await
+                .map(|timer| timer.token_generation),
+            TokenSubject::Group => repository
+                .get_group(id)
+                .await
+                .map(|group| group.token_generation),
+            TokenSubject::User => repository
+                .get_user(id)
+                .await
+                .map(|user| user.token_generation),
+        }
+    }
+}
+
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    let argon2 = Argon2::default();
+    argon2
+        .hash_password(password.as_ref(), &salt)
+        .unwrap()
+        .to_string()
+}
+
+pub fn check_password_hash(password: &str, password_hash: &str) -> bool {
+    let parsed_hash = PasswordHash::new(password_hash).unwrap();
+    Argon2::default()
+        .verify_password(password.as_ref(), &parsed_hash)
+        .is_ok()
+}
+
+/// An error of an endpoint which checks passwords
+pub enum AuthError {
+    Status(StatusCode),
+    /// in ms
+    TooManyAttempts(u64),
+}
+
+impl From<StatusCode> for AuthError {
+    fn from(status: StatusCode) -> Self {
+        AuthError::Status(status)
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        match self {
+            AuthError::Status(status) => status.into_response(),
+            AuthError::TooManyAttempts(retry_after) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                // in whole seconds, rather a bit too long than too short
+                [(header::RETRY_AFTER, (retry_after / 1000 + 1).to_string())],
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// The address of the client, taken from the header set in `CLIENT_IP_HEADER`
+/// when the server runs behind a reverse proxy
+pub struct ClientIp(pub String);
+
+#[async_trait]
+impl FromRequestParts<SharedState> for ClientIp {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &SharedState,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(name) = &state.client_ip_header {
+            // the proxy appends the address it sees, everything before could be forged
+            return parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .map(|ip| ClientIp(ip.trim().to_owned()))
+                .ok_or(StatusCode::BAD_REQUEST);
+        }
+
+        parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| ClientIp(addr.ip().to_string()))
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+/// The user account of the access token in the `Authorization` header
+pub struct CurrentUser(pub User);
+
+#[async_trait]
+impl FromRequestParts<SharedState> for CurrentUser {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &SharedState,
+    ) -> Result<Self, Self::Rejection> {
+        let TypedHeader(auth) =
+            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
+                .await
+                .map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+        token_user(state, auth.token())
+            .await
+            .map(CurrentUser)
+            .ok_or(StatusCode::UNAUTHORIZED)
+    }
+}
+
+/// How long to wait after the last of `failures` failed attempts
+fn lockout(failures: u32, free_attempts: u32) -> u64 {
+    if failures < free_attempts {
+        return 0;
+    }
+
+    let exponent = (failures - free_attempts).min(32);
+    BASE_LOCKOUT.saturating_mul(1 << exponent).min(MAX_LOCKOUT)
+}
+
+/// How long to wait until the next attempt is allowed
+fn retry_after(attempts: &LoginAttempts, free_attempts: u32, now: u64) -> Option<u64> {
+    let locked_until = attempts.last_failure + lockout(attempts.failures, free_attempts);
+    (locked_until > now).then(|| locked_until - now)
+}
+
+/// Counts a failed attempt unless `attempts` are locked, attempts from before
+/// [`FAILURE_MEMORY`] are forgotten
+fn reserve_attempt(
+    attempts: &LoginAttempts,
+    free_attempts: u32,
+    now: u64,
+) -> Option<LoginAttempts> {
+    let failures = if now.saturating_sub(attempts.last_failure) > FAILURE_MEMORY {
+        0
+    } else if retry_after(attempts, free_attempts, now).is_some() {
+        return None;
+    } else {
+        attempts.failures
+    };
+
+    Some(LoginAttempts {
+        failures: failures + 1,
+        last_failure: now,
+    })
+}
+
+/// Restores the attempts from before `reserved`, unless they were changed since
+async fn release_attempt(
+    repository: &Repository,
+    key: &str,
+    previous: LoginAttempts,
+    reserved: LoginAttempts,
+) {
+    repository
+        .update_login_attempts(key, FAILURE_MEMORY, |attempts| {
+            (*attempts == reserved).then(|| previous.clone())
+        })
+        .await;
+}
+
+/// Checks `password` unless the client or the timer or group have too many failed attempts.
+/// The hash is `None` if there is nothing to log in to, which counts as failed attempt.
+///
+/// Every attempt is counted as failed before the password is checked, so concurrent
+/// attempts can't get past the limit, and the count is restored if the password is right.
+async fn check_login_attempt(
+    repository: &Repository,
+    client_key: &str,
+    subject_key: Option<&str>,
+    password: &str,
+    password_hash: Option<&str>,
+    now: u64,
+) -> Result<(), AuthError> {
+    let keys = std::iter::once((client_key, CLIENT_FREE_ATTEMPTS))
+        .chain(subject_key.map(|key| (key, TIMER_FREE_ATTEMPTS)));
+
+    let mut reserved = Vec::new();
+    for (key, free_attempts) in keys {
+        let (previous, attempts) = repository
+            .update_login_attempts(key, FAILURE_MEMORY, |attempts| {
+                reserve_attempt(attempts, free_attempts, now)
+            })
+            .await;
+
+        match attempts {
+            Some(attempts) => reserved.push((key, previous, attempts)),
+            None => {
+                for (key, previous, attempts) in reserved {
+                    release_attempt(repository, key, previous, attempts).await;
+                }
+                let retry_after = retry_after(&previous, free_attempts, now).unwrap_or_default();
+                return Err(AuthError::TooManyAttempts(retry_after));
+            }
+        }
+    }
+
+    if matches!(password_hash, Some(hash) if check_password_hash(password, hash)) {
+        let mut reserved = reserved.into_iter();
+        // the client is not reset, so an attacker can't reset it with a timer of their own
+        if let Some((key, previous, attempts)) = reserved.next() {
+            release_attempt(repository, key, previous, attempts).await;
+        }
+        if let Some((key, _, _)) = reserved.next() {
+            repository
+                .update_login_attempts(key, FAILURE_MEMORY, |_| Some(LoginAttempts::default()))
+                .await;
+        }
+
+        return Ok(());
+    }
+
+    Err(AuthError::Status(StatusCode::UNAUTHORIZED))
+}
+
+/// Checks the password of a timer or group with limited attempts per client and per
+/// timer or group. `password_hash` is `None` if the timer or group doesn't exist.
+pub async fn check_password_limited(
+    state: &SharedState,
+    client: &ClientIp,
+    subject: TokenSubject,
+    id: &str,
+    password: &str,
+    password_hash: Option<&str>,
+) -> Result<(), AuthError> {
+    // only existing ones are tracked, so unknown ids can't fill the storage
+    let subject_key = password_hash.map(|_| format!("{}:{}", subject.name(), id));
+
+    check_login_attempt(
+        &state.repository,
+        &format!("client:{}", client.0),
+        subject_key.as_deref(),
+        password,
+        password_hash,
+        current_time(),
+    )
+    .await
+}
+
+fn create_jwt(
+    id: String,
+    subject: TokenSubject,
+    role: Role,
+    kind: TokenKind,
+    generation: u64,
+    key: &str,
+) -> String {
+    let lifetime = match kind {
+        TokenKind::Access => ACCESS_TOKEN_LIFETIME,
+        TokenKind::Refresh => REFRESH_TOKEN_LIFETIME,
+    };
+
+    let claims = Claims {
+        id,
+        exp: (current_time() / 1000 + lifetime) as usize,
+        iss: subject.issuer().to_string(),
+        role,
+        kind,
+        generation,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(key.as_ref()),
+    )
+    .unwrap()
+}
+
+/// Creates an access and a refresh token for the current token generation
+pub fn create_tokens(
+    id: String,
+    subject: TokenSubject,
+    role: Role,
+    generation: u64,
+    key: &str,
+) -> TokenResponse {
+    TokenResponse {
+        token: create_jwt(
+            id.clone(),
+            subject,
+            role,
+            TokenKind::Access,
+            generation,
+            key,
+        ),
+        refresh_token: create_jwt(id, subject, role, TokenKind::Refresh, generation, key),
+    }
+}
+
+/// Decodes `token` if it is an unexpired token of `kind`, issued for `subject`
+fn decode_token(token: &str, subject: TokenSubject, kind: TokenKind, key: &str) -> Option<Claims> {
+    let mut validation = Validation::new(Algorithm::default());
+    validation.set_issuer(&[subject.issuer()]);
+
+    let token =
+        decode::<Claims>(token, &DecodingKey::from_secret(key.as_ref()), &validation).ok()?;
+    if token.claims.kind != kind {
+        return None;
+    }
+
+    Some(token.claims)
+}
+
+/// Returns the role of `token` if it is a valid access token for `id`,
+/// which wasn't revoked by increasing the token `generation`
+pub fn token_role(
+    token: &str,
+    subject: TokenSubject,
+    id: &str,
+    generation: u64,
+    key: &str,
+) -> Option<Role> {
+    let claims = decode_token(token, subject, TokenKind::Access, key)?;
+    if claims.id != id || claims.generation != generation {
+        return None;
+    }
+
+    Some(claims.role)
+}
+
+/// Returns the user if `token` is a valid access token of a user account
+pub async fn token_user(state: &SharedState, token: &str) -> Option<User> {
+    let claims = decode_token(token, TokenSubject::User, TokenKind::Access, &state.jwt_key)?;
+    let user = state.repository.get_user(claims.id).await?;
+
+    (user.token_generation == claims.generation).then_some(user)
+}
+
+/// Returns the role of `token` for the timer and who it was issued for, which is either
+/// the timer itself or the user owning it. Owners are admins of their timers.
+pub async fn timer_token_actor(
+    state: &SharedState,
+    token: &str,
+    timer: &Timer,
+) -> Option<(Role, Actor)> {
+    let role = token_role(
+        token,
+        TokenSubject::Timer,
+        &timer.id,
+        timer.token_generation,
+        &state.jwt_key,
+    );
+    if let Some(role) = role {
+        return Some((role, Actor::Token { role }));
+    }
+
+    timer.owner.as_ref()?;
+    let user = token_user(state, token).await?;
+    (timer.owner.as_ref() == Some(&user.username)).then_some((
+        Role::Admin,
+        Actor::User {
+            username: user.username,
+        },
+    ))
+}
+
+/// Creates new tokens with the same role, if the refresh token is still valid.
+/// Returns the id they were issued for and the tokens.
+pub async fn refresh_tokens(
+    state: &SharedState,
+    subject: TokenSubject,
+    request: RefreshRequest,
+) -> Result<(String, TokenResponse), StatusCode> {
+    let claims = decode_token(
+        &request.refresh_token,
+        subject,
+        TokenKind::Refresh,
+        &state.jwt_key,
+    )
+    .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let generation = subject
+        .token_generation(&state.repository, claims.id.clone())
+        .await;
+    if generation != Some(claims.generation) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let tokens = create_tokens(
+        claims.id.clone(),
+        subject,
+        claims.role,
+        claims.generation,
+        &state.jwt_key,
+    );
+
+    Ok((claims.id, tokens))
+}
+
+/// What the routes behind [`auth_middleware`] require
+#[derive(Clone)]
+pub struct AuthScope {
+    pub state: SharedState,
+    pub subject: TokenSubject,
+    pub role: Role,
+}
+
+/// Requires a token for the `id` of the route with at least the role of the scope.
+/// Who the token was issued for is added to the request as [`Actor`].
+pub async fn auth_middleware<B>(
+    State(scope): State<AuthScope>,
+    Path(params): Path<HashMap<String, String>>,
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, StatusCode> {
+    let id = params.get("id").ok_or(StatusCode::UNAUTHORIZED)?;
+    let role_actor = match scope.subject {
+        TokenSubject::Timer => {
+            let timer = scope
+                .state
+                .repository
+                .get_timer(id.clone())
+                .await
+                .ok_or(StatusCode::UNAUTHORIZED)?;
+            timer_token_actor(&scope.state, auth.token(), &timer).await
+        }
+        TokenSubject::Group => {
+            let generation = TokenSubject::Group
+                .token_generation(&scope.state.repository, id.clone())
+                .await
+                .ok_or(StatusCode::UNAUTHORIZED)?;
+            token_role(
+                auth.token(),
+                TokenSubject::Group,
+                id,
+                generation,
+                &scope.state.jwt_key,
+            )
+            .map(|role| (role, Actor::Group { id: id.clone() }))
+        }
+        // routes of users use `CurrentUser` instead
+        TokenSubject::User => None,
+    };
+
+    match role_actor {
+        None => Err(StatusCode::UNAUTHORIZED),
+        Some((role, _)) if role < scope.role => Err(StatusCode::FORBIDDEN),
+        Some((_, actor)) => {
+            request.extensions_mut().insert(actor);
+            Ok(next.run(request).await)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::MemoryStorage;
+
+    async fn stored_attempts(repository: &Repository, key: &str) -> LoginAttempts {
+        repository.update_login_attempts(key, 0, |_| None).await.0
+    }
+
+    #[test]
+    fn test_token_role() {
+        let tokens = create_tokens(
+            "test".to_owned(),
+            TokenSubject::Timer,
+            Role::Operator,
+            1,
+            "key",
+        );
+        let token = &tokens.token;
+        assert_eq!(
+            token_role(token, TokenSubject::Timer, "test", 1, "key"),
+            Some(Role::Operator)
+        );
+        assert_eq!(
+            token_role(token, TokenSubject::Timer, "other", 1, "key"),
+            None
+        );
+        assert_eq!(
+            token_role(token, TokenSubject::Group, "test", 1, "key"),
+            None
+        );
+        assert_eq!(
+            token_role(token, TokenSubject::Timer, "test", 1, "other key"),
+            None
+        );
+
+        // revoked
+        assert_eq!(
+            token_role(token, TokenSubject::Timer, "test", 2, "key"),
+            None
+        );
+
+        // refresh tokens can't be used for requests
+        assert_eq!(
+            token_role(&tokens.refresh_token, TokenSubject::Timer, "test", 1, "key"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_lockout() {
+        assert_eq!(lockout(0, 5), 0);
+        assert_eq!(lockout(4, 5), 0);
+        assert_eq!(lockout(5, 5), 1000);
+        assert_eq!(lockout(7, 5), 4000);
+        assert_eq!(lockout(100, 5), MAX_LOCKOUT);
+    }
+
+    #[tokio::test]
+    async fn test_check_login_attempt() {
+        let repository = Repository::new(MemoryStorage::new(None).await);
+        let hash = hash_password("password");
+        let check = |password: &'static str, now: u64| {
+            let repository = repository.clone();
+            let hash = hash.clone();
+            async move {
+                check_login_attempt(
+                    &repository,
+                    "client:127.0.0.1",
+                    Some("timer:test"),
+                    password,
+                    Some(&hash),
+                    now,
+                )
+                .await
+            }
+        };
+
+        assert!(check("password", 0).await.is_ok());
+        for _ in 0..TIMER_FREE_ATTEMPTS {
+            assert!(matches!(
+                check("wrong", 1000).await,
+                Err(AuthError::Status(StatusCode::UNAUTHORIZED))
+            ));
+        }
+
+        // the timer is locked for a second after too many failures
+        assert!(matches!(
+            check("password", 1500).await,
+            Err(AuthError::TooManyAttempts(500))
+        ));
+        assert!(check("password", 2000).await.is_ok());
+        assert_eq!(
+            stored_attempts(&repository, "timer:test").await,
+            LoginAttempts::default()
+        );
+
+        // the client is still remembered
+        let mut now = 2000;
+        for _ in TIMER_FREE_ATTEMPTS..CLIENT_FREE_ATTEMPTS {
+            now += MAX_LOCKOUT;
+            assert!(check("wrong", now).await.is_err());
+        }
+        assert!(matches!(
+            check("password", now).await,
+            Err(AuthError::TooManyAttempts(1000))
+        ));
+
+        // and forgotten after a day
+        assert!(check("password", now + FAILURE_MEMORY + 1).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_login_attempts() {
+        let repository = Repository::new(MemoryStorage::new(None).await);
+        let hash = hash_password("password");
+
+        let attempts = (0..2 * TIMER_FREE_ATTEMPTS).map(|_| {
+            check_login_attempt(
+                &repository,
+                "client:127.0.0.1",
+                Some("timer:test"),
+                "wrong",
+                Some(&hash),
+                1000,
+            )
+        });
+        let results = futures::future::join_all(attempts).await;
+
+        // only the free attempts are checked, however many are made at once
+        let checked = results
+            .iter()
+            .filter(|result| matches!(result, Err(AuthError::Status(StatusCode::UNAUTHORIZED))))
+            .count();
+        assert_eq!(checked, TIMER_FREE_ATTEMPTS as usize);
+        assert_eq!(
+            stored_attempts(&repository, "timer:test").await.failures,
+            TIMER_FREE_ATTEMPTS
+        );
+        // the rejected attempts are not counted for the client
+        assert_eq!(
+            stored_attempts(&repository, "client:127.0.0.1")
+                .await
+                .failures,
+            TIMER_FREE_ATTEMPTS
+        );
+    }
+
+    #[test]
+    fn test_expired_token() {
+        let claims = Claims {
+            id: "test".to_owned(),
+            exp: (current_time() / 1000 - 3600) as usize,
+            iss: TokenSubject::Timer.issuer().to_owned(),
+            role: Role::Admin,
+            kind: TokenKind::Access,
+            generation: 0,
+        };
+        let token = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret("key".as_ref()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            token_role(&token, TokenSubject::Timer, "test", 0, "key"),
+            None
+        );
+    }
+}
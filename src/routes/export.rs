@@ -0,0 +1,317 @@
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+
+use crate::audit::Action;
+use crate::export::{export_timer, import_timer, segments_from_csv, segments_to_csv, TimerExport};
+use crate::models::*;
+use crate::repository::{Sequence, Timer};
+use crate::timer_state::{current_time, stop};
+use crate::validation::{validate_sequences, FieldError, RequestError};
+use crate::webhooks::lifecycle_events;
+
+use super::auth::{hash_password, ClientIp, CurrentUser};
+use super::history::Audit;
+use super::osc::validate_osc_targets;
+use super::timer::{creator, insert_timer, is_valid_id};
+
+async fn get_export(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Result<Json<TimerExport>, StatusCode> {
+    let timer = state
+        .repository
+        .get_timer(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    Ok(Json(export_timer(&timer, current_time())))
+}
+
+/// Creates a stopped timer from an export, which belongs to the user of the token, if any.
+/// Webhooks in the export are dropped, its OSC targets are checked like when they are set
+pub async fn import(
+    State(state): State<SharedState>,
+    user: Option<CurrentUser>,
+    client: ClientIp,
+    Json(request): Json<TimerImportRequest>,
+) -> Result<Json<TimerCreationResponse>, RequestError> {
+    if !is_valid_id(&request.id) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let imported = import_timer(request.document).map_err(|e| FieldError::new("document", e))?;
+    validate_sequences(&imported.sequences).map_err(|error| error.within("document.timer"))?;
+    validate_osc_targets(&imported.osc_targets, state.allow_private_targets)
+        .await
+        .map_err(|error| error.within("document.timer"))?;
+    let user = user.map(|CurrentUser(user)| user);
+
+    let mut timer = Timer {
+        password: hash_password(&request.password),
+        id: request.id,
+        owner: user.as_ref().map(|user| user.username.clone()),
+        webhooks: Vec::new(),
+        ..imported
+    };
+    stop(&mut timer, current_time());
+
+    let audit = Audit::new(creator(user.as_ref()), client);
+    Ok(insert_timer(&state, &audit, timer, user).await?)
+}
+
+async fn get_segments_csv(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let timer = state
+        .repository
+        .get_timer(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/csv")],
+        segments_to_csv(&timer.segments()),
+    ))
+}
+
+/// Replaces the playlist with a single sequence of the segments in the csv
+async fn put_segments_csv(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+    csv: String,
+) -> Result<Json<TimerResponse>, RequestError> {
+    let segments = segments_from_csv(&csv).map_err(|e| FieldError::new("", e))?;
+    let sequences = vec![Sequence::from(segments)];
+    validate_sequences(&sequences)?;
+
+    let old_timer = state
+        .repository
+        .get_timer(id)
+        .await
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let mut timer = Timer {
+        sequences,
+        ..old_timer.clone()
+    };
+    if timer.sequences != old_timer.sequences {
+        timer.extension = None;
+    }
+
+    state
+        .repository
+        .update_timer(&mut timer)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+    state
+        .repository
+        .add_revision(&old_timer, current_time())
+        .await;
+    audit
+        .record(&state, Action::Update, Some(&old_timer), Some(&timer))
+        .await;
+
+    for event in lifecycle_events(&old_timer, &timer) {
+        state.webhooks.send_lifecycle_event(&timer, event);
+    }
+
+    Ok(Json(timer.into()))
+}
+
+pub fn routes() -> Router<SharedState> {
+    Router::new().route("/:id/export", get(get_export)).route(
+        "/:id/segments.csv",
+        get(get_segments_csv).put(put_segments_csv),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use super::super::testing::{request, TestClient};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_export_and_import() {
+        let client = TestClient::new().await;
+        let token = client.create_timer("source").await;
+
+        let export = client
+            .call(
+                Method::GET,
+                "/api/timer/source/export",
+                Some(&token),
+                json!(null),
+            )
+            .await;
+        assert_eq!(export.status, StatusCode::OK);
+        assert_eq!(export.body["format"], "distributed-timer");
+        assert_eq!(export.body["timer"]["password"], "");
+
+        let import = json!({"id": "copy", "password": "other", "document": export.body});
+        let response = client
+            .call(Method::POST, "/api/timer/import", None, import.clone())
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body["timer"]["id"], "copy");
+        assert!(response.body["token"].is_string());
+
+        let source = client.state.repository.get_timer("source".to_owned()).await;
+        let copy = client.state.repository.get_timer("copy".to_owned()).await;
+        let (source, copy) = (source.unwrap(), copy.unwrap());
+        assert_eq!(copy.sequences, source.sequences);
+        assert!(copy.stop_at.is_some());
+        assert_ne!(copy.password, source.password);
+
+        // the id is taken now
+        let response = client
+            .call(Method::POST, "/api/timer/import", None, import)
+            .await;
+        assert_eq!(response.status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_import_validation() {
+        let client = TestClient::new().await;
+        let token = client.create_timer("source").await;
+        let export = client
+            .call(
+                Method::GET,
+                "/api/timer/source/export",
+                Some(&token),
+                json!(null),
+            )
+            .await;
+
+        let mut document = export.body.clone();
+        document["timer"]["sequences"][0]["segments"][0]["time"] = json!(0);
+        let response = client
+            .call(
+                Method::POST,
+                "/api/timer/import",
+                None,
+                json!({"id": "invalid", "password": "password", "document": document}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            response.body["errors"][0]["field"],
+            "document.timer.sequences[0].segments[0].time"
+        );
+
+        let mut document = export.body.clone();
+        document["format"] = json!("other");
+        let response = client
+            .call(
+                Method::POST,
+                "/api/timer/import",
+                None,
+                json!({"id": "invalid", "password": "password", "document": document}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.body["errors"][0]["field"], "document");
+
+        let mut document = export.body.clone();
+        document["timer"]["osc_targets"] = json!([{"host": "127.0.0.1", "port": 0}]);
+        let response = client
+            .call(
+                Method::POST,
+                "/api/timer/import",
+                None,
+                json!({"id": "invalid", "password": "password", "document": document}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            response.body["errors"][0]["field"],
+            "document.timer.osc_targets[0].port"
+        );
+
+        assert!(client
+            .state
+            .repository
+            .get_timer("invalid".to_owned())
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_import_drops_webhooks() {
+        let client = TestClient::new().await;
+        let token = client.create_timer("source").await;
+        let export = client
+            .call(
+                Method::GET,
+                "/api/timer/source/export",
+                Some(&token),
+                json!(null),
+            )
+            .await;
+
+        let mut document = export.body;
+        document["timer"]["webhooks"] =
+            json!([{"url": "http://127.0.0.1:9000/hook", "secret": "known"}]);
+        let response = client
+            .call(
+                Method::POST,
+                "/api/timer/import",
+                None,
+                json!({"id": "copy", "password": "password", "document": document}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+
+        let copy = client.state.repository.get_timer("copy".to_owned()).await;
+        assert!(copy.unwrap().webhooks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_segments_csv() {
+        let client = TestClient::new().await;
+        let token = client.create_timer("test").await;
+        let put_csv = |csv: &'static str| {
+            request(Method::PUT, "/api/timer/test/segments.csv", Some(&token))
+                .header(header::CONTENT_TYPE, "text/csv")
+                .body(csv.into())
+                .unwrap()
+        };
+
+        let response = client
+            .send(put_csv("label,duration\nBoulder,4:00\nChange,15\n"))
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body["segments"][1]["time"], 15000);
+
+        let response = client.send(put_csv("label,duration\nBoulder,0\n")).await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            response.body["errors"][0]["field"],
+            "sequences[0].segments[0].time"
+        );
+
+        let response = client.send(put_csv("label,duration\nBoulder,soon\n")).await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.body["errors"][0]["field"], "");
+
+        let response = client
+            .call(
+                Method::GET,
+                "/api/timer/test/segments.csv",
+                Some(&token),
+                json!(null),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers[header::CONTENT_TYPE], "text/csv");
+    }
+}
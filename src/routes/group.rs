@@ -0,0 +1,618 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::routing::{get, post, put};
+use axum::Router;
+use axum::{response::IntoResponse, Json};
+use regex::Regex;
+
+use crate::audit::{Action, Actor, FieldChange};
+use crate::models::*;
+use crate::repository::{GroupMember, Timer, TimerGroup};
+use crate::timer_state::{current_time, pause, resume, start, stop};
+use crate::validation::{validate_request_playlist, RequestError};
+use crate::webhooks::control_event;
+
+use super::auth::{
+    auth_middleware, check_password_limited, create_tokens, hash_password, refresh_tokens,
+    AuthError, AuthScope, ClientIp, TokenSubject,
+};
+use super::history::Audit;
+
+/// Checks the passwords of the timers and returns them as members with their
+/// current token generation
+async fn verify_members(
+    state: &SharedState,
+    client: &ClientIp,
+    timers: Vec<TokenRequest>,
+) -> Result<Vec<GroupMember>, AuthError> {
+    let mut members: Vec<GroupMember> = Vec::new();
+
+    for request in timers {
+        let timer = state.repository.get_timer(request.id.clone()).await;
+
+        check_password_limited(
+            state,
+            client,
+            TokenSubject::Timer,
+            &request.id,
+            &request.password,
+            timer.as_ref().map(|timer| timer.password.as_str()),
+        )
+        .await?;
+        let timer = timer.ok_or(StatusCode::UNAUTHORIZED)?;
+
+        if !members.iter().any(|member| member.timer_id == timer.id) {
+            members.push(GroupMember {
+                timer_id: timer.id,
+                token_generation: timer.token_generation,
+            });
+        }
+    }
+
+    Ok(members)
+}
+
+/// Records in the history of each timer that it joined or left the group
+async fn record_membership(
+    state: &SharedState,
+    audit: &Audit,
+    group_id: &str,
+    timer_ids: impl Iterator<Item = &String>,
+    action: Action,
+) {
+    let (before, after) = match action {
+        Action::JoinGroup => (serde_json::Value::Null, group_id.into()),
+        _ => (group_id.into(), serde_json::Value::Null),
+    };
+
+    for timer_id in timer_ids {
+        let change = FieldChange {
+            field: "group".to_owned(),
+            before: before.clone(),
+            after: after.clone(),
+        };
+        audit
+            .record_changes(state, action, timer_id, vec![change])
+            .await;
+    }
+}
+
+async fn create_token(
+    State(state): State<SharedState>,
+    client: ClientIp,
+    Json(request): Json<TokenRequest>,
+) -> Result<Json<TokenResponse>, AuthError> {
+    let group = state.repository.get_group(request.id.clone()).await;
+
+    check_password_limited(
+        &state,
+        &client,
+        TokenSubject::Group,
+        &request.id,
+        &request.password,
+        group.as_ref().map(|group| group.password.as_str()),
+    )
+    .await?;
+    let group = group.ok_or(StatusCode::UNAUTHORIZED)?;
+
+    Ok(Json(create_tokens(
+        request.id,
+        TokenSubject::Group,
+        Role::Admin,
+        group.token_generation,
+        &state.jwt_key,
+    )))
+}
+
+async fn refresh_token(
+    State(state): State<SharedState>,
+    Json(request): Json<RefreshRequest>,
+) -> Result<Json<TokenResponse>, StatusCode> {
+    refresh_tokens(&state, TokenSubject::Group, request)
+        .await
+        .map(|(_, tokens)| Json(tokens))
+}
+
+async fn create_group(
+    State(state): State<SharedState>,
+    client: ClientIp,
+    Json(request): Json<GroupCreationRequest>,
+) -> Result<Json<GroupCreationResponse>, AuthError> {
+    let id_regex = Regex::new(r"^[a-zA-Z0-9-_]+$").unwrap();
+    if !id_regex.is_match(&request.id) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let group = TimerGroup {
+        members: verify_members(&state, &client, request.timers).await?,
+        password: hash_password(&request.password),
+        id: request.id,
+        token_generation: 0,
+    };
+
+    state
+        .repository
+        .create_group(&group)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+
+    // the passwords of the timers were used to add them
+    let audit = Audit::new(Actor::Password, client);
+    let timer_ids = group.members.iter().map(|member| &member.timer_id);
+    record_membership(&state, &audit, &group.id, timer_ids, Action::JoinGroup).await;
+
+    let tokens = create_tokens(
+        group.id.clone(),
+        TokenSubject::Group,
+        Role::Admin,
+        group.token_generation,
+        &state.jwt_key,
+    );
+
+    Ok(Json(GroupCreationResponse {
+        group: group.into(),
+        tokens,
+    }))
+}
+
+async fn get_group(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Result<Json<GroupResponse>, StatusCode> {
+    let group = state
+        .repository
+        .get_group(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+    Ok(Json(group.into()))
+}
+
+/// Replaces the members, which are recorded in the history of the timers which
+/// joined or left the group.
+/// Fails with 409 if the group was changed while the passwords were checked
+async fn update_members(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    client: ClientIp,
+    audit: Audit,
+    Json(request): Json<GroupMembersRequest>,
+) -> Result<Json<GroupResponse>, AuthError> {
+    let old_group = state
+        .repository
+        .get_group(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    let group = TimerGroup {
+        members: verify_members(&state, &client, request.timers).await?,
+        ..old_group.clone()
+    };
+
+    state
+        .repository
+        .update_group(&old_group, &group)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+
+    let timer_ids = |group: &TimerGroup| {
+        group
+            .members
+            .iter()
+            .map(|member| member.timer_id.clone())
+            .collect::<Vec<_>>()
+    };
+    let (old_ids, new_ids) = (timer_ids(&old_group), timer_ids(&group));
+    let joined = new_ids.iter().filter(|id| !old_ids.contains(id));
+    record_membership(&state, &audit, &group.id, joined, Action::JoinGroup).await;
+    let left = old_ids.iter().filter(|id| !new_ids.contains(id));
+    record_membership(&state, &audit, &group.id, left, Action::LeaveGroup).await;
+
+    Ok(Json(group.into()))
+}
+
+async fn delete_group(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+) -> impl IntoResponse {
+    let group = state
+        .repository
+        .get_group(id.clone())
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+    state
+        .repository
+        .delete_group(id)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let timer_ids = group.members.iter().map(|member| &member.timer_id);
+    record_membership(&state, &audit, &group.id, timer_ids, Action::LeaveGroup).await;
+
+    Ok::<_, StatusCode>(StatusCode::OK)
+}
+
+/// Revokes all tokens of the group, including the one used for this request.
+/// Fails with 409 if the group was changed in the meantime
+async fn logout_group(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+) -> Result<StatusCode, StatusCode> {
+    let old_group = state
+        .repository
+        .get_group(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    let group = TimerGroup {
+        token_generation: old_group.token_generation + 1,
+        ..old_group.clone()
+    };
+    state
+        .repository
+        .update_group(&old_group, &group)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+
+    for member in &group.members {
+        audit
+            .record_changes(&state, Action::LogoutGroup, &member.timer_id, vec![])
+            .await;
+    }
+
+    Ok(StatusCode::OK)
+}
+
+/// Applies `change` to all member timers with the same current time and stores
+/// them at once, so all displays change at the same instant.
+/// Each change is recorded as `action` in the history of the timer and sent to its webhooks
+/// like the action on a single timer, updates of the playlist also keep the previous
+/// version as a revision.
+/// Members which were deleted in the meantime or revoked their tokens are skipped.
+async fn change_timers(
+    state: SharedState,
+    id: String,
+    audit: Audit,
+    action: Action,
+    change: impl Fn(&mut Timer, u64),
+) -> Result<Json<Vec<TimerResponse>>, StatusCode> {
+    let group = state
+        .repository
+        .get_group(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    let mut old_timers = Vec::new();
+    for member in group.members {
+        match state.repository.get_timer(member.timer_id.clone()).await {
+            Some(timer) if member.is_current(&timer) => old_timers.push(timer),
+            _ => (),
+        }
+    }
+
+    let now = current_time();
+    let mut timers = old_timers
+        .iter()
+        .map(|timer| {
+            let mut timer = timer.clone();
+            change(&mut timer, now);
+            timer
+        })
+        .collect::<Vec<_>>();
+
+    state
+        .repository
+        .update_timers(&mut timers)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+
+    if action == Action::Update {
+        for old_timer in &old_timers {
+            state.repository.add_revision(old_timer, now).await;
+        }
+    }
+
+    for (old_timer, timer) in old_timers.iter().zip(&timers) {
+        audit
+            .record(&state, action, Some(old_timer), Some(timer))
+            .await;
+        state
+            .webhooks
+            .send_lifecycle_event(timer, control_event(action));
+    }
+
+    Ok(Json(timers.into_iter().map(|timer| timer.into()).collect()))
+}
+
+async fn start_group(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+    Json(request): Json<StartRequest>,
+) -> Result<Json<Vec<TimerResponse>>, StatusCode> {
+    change_timers(state, id, audit, Action::Start, |timer, _| {
+        start(timer, request.start_at)
+    })
+    .await
+}
+
+async fn stop_group(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+) -> Result<Json<Vec<TimerResponse>>, StatusCode> {
+    change_timers(state, id, audit, Action::Stop, stop).await
+}
+
+async fn pause_group(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+) -> Result<Json<Vec<TimerResponse>>, StatusCode> {
+    // timers which are paused already stay as they are
+    change_timers(state, id, audit, Action::Pause, |timer, now| {
+        pause(timer, now);
+    })
+    .await
+}
+
+async fn resume_group(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+) -> Result<Json<Vec<TimerResponse>>, StatusCode> {
+    change_timers(state, id, audit, Action::Resume, |timer, now| {
+        resume(timer, now);
+    })
+    .await
+}
+
+async fn apply_segments(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+    Json(request): Json<GroupSegmentsRequest>,
+) -> Result<Json<Vec<TimerResponse>>, RequestError> {
+    validate_request_playlist(&request.sequences, &request.segments)?;
+    let sequences = playlist(request.sequences, request.segments);
+
+    Ok(change_timers(state, id, audit, Action::Update, |timer, _| {
+        if timer.sequences != sequences {
+            timer.extension = None;
+        }
+        timer.sequences = sequences.clone();
+        timer.repeat = request.repeat;
+    })
+    .await?)
+}
+
+pub fn routes(state: SharedState) -> Router<SharedState> {
+    Router::new()
+        .route("/:id", get(get_group).delete(delete_group))
+        .route("/:id/timers", put(update_members))
+        .route("/:id/logout", post(logout_group))
+        .route("/:id/start", post(start_group))
+        .route("/:id/stop", post(stop_group))
+        .route("/:id/pause", post(pause_group))
+        .route("/:id/resume", post(resume_group))
+        .route("/:id/segments", put(apply_segments))
+        .route_layer(middleware::from_fn_with_state(
+            AuthScope {
+                state,
+                subject: TokenSubject::Group,
+                role: Role::Admin,
+            },
+            auth_middleware,
+        ))
+        .route("/token", post(create_token))
+        .route("/refresh", post(refresh_token))
+        .route("/", post(create_group))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use super::super::testing::{segment, TestClient};
+    use super::*;
+
+    async fn create_group(client: &TestClient) -> String {
+        client.create_timer("first").await;
+        client.create_timer("second").await;
+
+        let request = json!({
+            "id": "walls",
+            "password": "password",
+            "timers": [
+                {"id": "first", "password": "password"},
+                {"id": "second", "password": "password"},
+            ],
+        });
+        let response = client
+            .call(Method::POST, "/api/group/", None, request)
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        response.body["token"].as_str().unwrap().to_owned()
+    }
+
+    async fn timer(client: &TestClient, id: &str) -> Timer {
+        client
+            .state
+            .repository
+            .get_timer(id.to_owned())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_group_members() {
+        let client = TestClient::new().await;
+        let token = create_group(&client).await;
+
+        // a group is only created if all passwords are right
+        let request = json!({
+            "id": "boulders",
+            "password": "password",
+            "timers": [
+                {"id": "first", "password": "password"},
+                {"id": "second", "password": "wrong"},
+            ],
+        });
+        let response = client
+            .call(Method::POST, "/api/group/", None, request)
+            .await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+        assert!(client
+            .state
+            .repository
+            .get_group("boulders".to_owned())
+            .await
+            .is_none());
+
+        let response = client
+            .call(Method::GET, "/api/group/walls", None, json!(null))
+            .await;
+        assert!(response.status.is_client_error());
+        let response = client
+            .call(Method::GET, "/api/group/walls", Some(&token), json!(null))
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body["timer_ids"], json!(["first", "second"]));
+
+        // the members are only replaced if all passwords are right
+        let request = json!({"timers": [{"id": "first", "password": "wrong"}]});
+        let response = client
+            .call(
+                Method::PUT,
+                "/api/group/walls/timers",
+                Some(&token),
+                request,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+        let request = json!({"timers": [{"id": "second", "password": "password"}]});
+        let response = client
+            .call(
+                Method::PUT,
+                "/api/group/walls/timers",
+                Some(&token),
+                request,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body["timer_ids"], json!(["second"]));
+
+        let history = client.state.repository.get_history("first").await;
+        let actions = history.iter().map(|entry| entry.action).collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            vec![Action::Create, Action::JoinGroup, Action::LeaveGroup]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_control_group() {
+        let client = TestClient::new().await;
+        let token = create_group(&client).await;
+
+        let response = client
+            .call(
+                Method::POST,
+                "/api/group/walls/start",
+                Some(&token),
+                json!({"start_at": 1000}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let (first, second) = (
+            timer(&client, "first").await,
+            timer(&client, "second").await,
+        );
+        assert_eq!((first.start_at, first.revision), (1000, 1));
+        assert_eq!((second.start_at, second.revision), (1000, 1));
+
+        // a timer which revoked its tokens isn't controlled by the group anymore
+        let timer_token = client
+            .call(
+                Method::POST,
+                "/api/timer/token",
+                None,
+                json!({"id": "second", "password": "password"}),
+            )
+            .await
+            .body["token"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        let response = client
+            .call(
+                Method::POST,
+                "/api/timer/second/logout",
+                Some(&timer_token),
+                json!(null),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+
+        let response = client
+            .call(
+                Method::POST,
+                "/api/group/walls/stop",
+                Some(&token),
+                json!(null),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body.as_array().unwrap().len(), 1);
+        assert!(timer(&client, "first").await.stop_at.is_some());
+        assert!(timer(&client, "second").await.stop_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_group_segments() {
+        let client = TestClient::new().await;
+        let token = create_group(&client).await;
+
+        // an invalid playlist changes none of the timers
+        let invalid = json!({"segments": [segment("Boulder", 0)], "repeat": true});
+        let response = client
+            .call(
+                Method::PUT,
+                "/api/group/walls/segments",
+                Some(&token),
+                invalid,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.body["errors"][0]["field"], "segments[0].time");
+        assert_eq!(timer(&client, "first").await.revision, 0);
+        assert_eq!(timer(&client, "second").await.revision, 0);
+
+        let segments = json!({"segments": [segment("Final", 300000)], "repeat": false});
+        let response = client
+            .call(
+                Method::PUT,
+                "/api/group/walls/segments",
+                Some(&token),
+                segments,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        for id in ["first", "second"] {
+            let timer = timer(&client, id).await;
+            assert_eq!(timer.segments()[0].label, "Final");
+            assert!(!timer.repeat);
+            // the replaced playlist is kept as revision
+            let revisions = client.state.repository.get_revisions(id).await;
+            assert_eq!(revisions.len(), 1);
+            assert_eq!(revisions[0].number, 0);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use async_trait::async_trait;
+use axum::extract::{FromRequestParts, Path, State};
+use axum::http::{request::Parts, StatusCode};
+use axum::routing::get;
+use axum::{Json, Router};
+
+use crate::audit::{current_history, diff, Action, Actor, FieldChange, HistoryEntry};
+use crate::models::*;
+use crate::repository::Timer;
+use crate::timer_state::current_time;
+
+use super::auth::ClientIp;
+
+/// Who makes a request and from where, so the changes can be recorded in the history.
+/// The actor is added to the request by `auth_middleware`.
+pub struct Audit {
+    pub actor: Actor,
+    pub client_ip: String,
+}
+
+#[async_trait]
+impl FromRequestParts<SharedState> for Audit {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &SharedState,
+    ) -> Result<Self, Self::Rejection> {
+        let actor = parts
+            .extensions
+            .get::<Actor>()
+            .cloned()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        let ClientIp(client_ip) = ClientIp::from_request_parts(parts, state).await?;
+
+        Ok(Audit { actor, client_ip })
+    }
+}
+
+impl Audit {
+    pub fn new(actor: Actor, ClientIp(client_ip): ClientIp) -> Self {
+        Audit { actor, client_ip }
+    }
+
+    /// Records the change of a timer from `before` to `after`,
+    /// which are `None` if it didn't exist before or was deleted
+    pub async fn record(
+        &self,
+        state: &SharedState,
+        action: Action,
+        before: Option<&Timer>,
+        after: Option<&Timer>,
+    ) {
+        let timer_id = match before.or(after) {
+            Some(timer) => &timer.id,
+            None => return,
+        };
+
+        self.record_changes(state, action, timer_id, diff(before, after))
+            .await;
+    }
+
+    /// Records changes which aren't part of the timer itself, e.g. joining a group
+    pub async fn record_changes(
+        &self,
+        state: &SharedState,
+        action: Action,
+        timer_id: &str,
+        changes: Vec<FieldChange>,
+    ) {
+        let entry = HistoryEntry {
+            time: current_time(),
+            action,
+            actor: self.actor.clone(),
+            client_ip: self.client_ip.clone(),
+            changes,
+        };
+        state.repository.append_history(timer_id, &entry).await;
+    }
+}
+
+async fn get_history(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Json<Vec<HistoryEntry>> {
+    Json(current_history(state.repository.get_history(&id).await))
+}
+
+pub fn routes() -> Router<SharedState> {
+    Router::new().route("/:id/history", get(get_history))
+}
@@ -1,4 +1,32 @@
+pub mod auth;
+pub mod export;
+pub mod group;
+pub mod history;
 pub mod instance;
+pub mod osc;
+pub mod revision;
+pub mod template;
+#[cfg(test)]
+mod testing;
 pub mod timer;
+pub mod user;
 pub mod web;
+pub mod webhooks;
 pub mod ws;
+
+use axum::Router;
+
+use crate::models::SharedState;
+
+/// The api and the web client, the server adds its layers to it
+pub fn app(state: SharedState) -> Router<SharedState> {
+    Router::new()
+        .nest("/api/ws", ws::routes())
+        .nest("/api/timer", timer::routes(state.clone()))
+        .nest("/api/group", group::routes(state))
+        .nest("/api/user", user::routes())
+        .nest("/api/template", template::routes())
+        .nest("/api/me", user::me_routes())
+        .nest("/api/instance", instance::routes())
+        .fallback(web::web_assets)
+}
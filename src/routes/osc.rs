@@ -0,0 +1,90 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+
+use crate::audit::Action;
+use crate::models::*;
+use crate::repository::{OscTarget, Timer};
+use crate::targets::resolve;
+use crate::validation::{FieldError, RequestError};
+
+use super::history::Audit;
+
+async fn get_osc_targets(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<OscTarget>>, StatusCode> {
+    let timer = state
+        .repository
+        .get_timer(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    Ok(Json(timer.osc_targets))
+}
+
+/// Checks that every target has a port and a host which resolves to an address
+/// messages may be sent to
+pub async fn validate_osc_targets(
+    osc_targets: &[OscTarget],
+    allow_private_targets: bool,
+) -> Result<(), RequestError> {
+    let mut errors = Vec::new();
+    for (index, target) in osc_targets.iter().enumerate() {
+        if target.port == 0 {
+            errors.push(FieldError::new(
+                format!("osc_targets[{}].port", index),
+                "The port can't be 0",
+            ));
+        } else if let Err(message) = resolve(&target.host, target.port, allow_private_targets).await
+        {
+            errors.push(FieldError::new(
+                format!("osc_targets[{}].host", index),
+                message,
+            ));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(RequestError::Invalid(errors));
+    }
+
+    Ok(())
+}
+
+async fn update_osc_targets(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+    Json(osc_targets): Json<Vec<OscTarget>>,
+) -> Result<Json<Vec<OscTarget>>, RequestError> {
+    validate_osc_targets(&osc_targets, state.allow_private_targets).await?;
+
+    let old_timer = state
+        .repository
+        .get_timer(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    let mut timer = Timer {
+        osc_targets,
+        ..old_timer.clone()
+    };
+
+    state
+        .repository
+        .update_timer(&mut timer)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+    audit
+        .record(&state, Action::Osc, Some(&old_timer), Some(&timer))
+        .await;
+
+    Ok(Json(timer.osc_targets))
+}
+
+pub fn routes() -> Router<SharedState> {
+    Router::new().route("/:id/osc", get(get_osc_targets).put(update_osc_targets))
+}
@@ -0,0 +1,80 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+
+use crate::audit::Action;
+use crate::models::*;
+use crate::repository::Timer;
+use crate::timer_state::current_time;
+use crate::webhooks::lifecycle_events;
+
+use super::history::Audit;
+
+async fn get_revisions(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Json<Vec<RevisionResponse>> {
+    let revisions = state.repository.get_revisions(&id).await;
+    Json(revisions.into_iter().map(|r| r.into()).collect())
+}
+
+/// Restores the playlist and settings of a revision, the timer keeps running as it is.
+/// The current version is kept as a new revision, so the revert can be undone.
+async fn revert(
+    State(state): State<SharedState>,
+    Path((id, number)): Path<(String, u64)>,
+    audit: Audit,
+) -> Result<Json<TimerResponse>, StatusCode> {
+    let old_timer = state
+        .repository
+        .get_timer(id.clone())
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    let revision = state
+        .repository
+        .get_revisions(&id)
+        .await
+        .into_iter()
+        .find(|revision| revision.number == number)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut timer = Timer {
+        sequences: revision.sequences,
+        repeat: revision.repeat,
+        display_options: revision.display_options,
+        metadata: revision.metadata,
+        private: revision.private,
+        ..old_timer.clone()
+    };
+    if timer.sequences != old_timer.sequences {
+        timer.extension = None;
+    }
+
+    state
+        .repository
+        .update_timer(&mut timer)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+    state
+        .repository
+        .add_revision(&old_timer, current_time())
+        .await;
+    audit
+        .record(&state, Action::Revert, Some(&old_timer), Some(&timer))
+        .await;
+
+    for event in lifecycle_events(&old_timer, &timer) {
+        state.webhooks.send_lifecycle_event(&timer, event);
+    }
+
+    Ok(Json(timer.into()))
+}
+
+pub fn routes() -> Router<SharedState> {
+    Router::new()
+        .route("/:id/revisions", get(get_revisions))
+        .route("/:id/revisions/:number/revert", post(revert))
+}
@@ -0,0 +1,109 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use regex::Regex;
+
+use crate::models::*;
+use crate::repository::{Template, User};
+use crate::templates::{builtin_templates, sound_presets, SoundPreset};
+use crate::validation::{validate_request_playlist, RequestError};
+
+use super::auth::CurrentUser;
+
+/// Finds a built-in template or one of the user
+pub async fn find_template(state: &SharedState, id: &str, user: Option<&User>) -> Option<Template> {
+    if let Some(template) = builtin_templates().into_iter().find(|t| t.id == id) {
+        return Some(template);
+    }
+
+    state
+        .repository
+        .get_templates(&user?.username)
+        .await
+        .into_iter()
+        .find(|t| t.id == id)
+}
+
+/// The built-in templates and the ones of the user, if a user token is given
+async fn get_templates(
+    State(state): State<SharedState>,
+    user: Option<CurrentUser>,
+) -> Json<Vec<TemplateResponse>> {
+    let mut templates: Vec<TemplateResponse> = builtin_templates()
+        .into_iter()
+        .map(|template| TemplateResponse::new(template, true))
+        .collect();
+
+    if let Some(CurrentUser(user)) = user {
+        templates.extend(
+            state
+                .repository
+                .get_templates(&user.username)
+                .await
+                .into_iter()
+                .map(|template| TemplateResponse::new(template, false)),
+        );
+    }
+
+    Json(templates)
+}
+
+/// Saves a template for the user, an existing one with the same id is replaced
+async fn save_template(
+    State(state): State<SharedState>,
+    CurrentUser(user): CurrentUser,
+    Json(request): Json<TemplateRequest>,
+) -> Result<Json<TemplateResponse>, RequestError> {
+    let id_regex = Regex::new(r"^[a-zA-Z0-9-_]+$").unwrap();
+    if !id_regex.is_match(&request.id) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    if builtin_templates().iter().any(|t| t.id == request.id) {
+        return Err(StatusCode::CONFLICT.into());
+    }
+    validate_request_playlist(&request.sequences, &request.segments)?;
+
+    let template: Template = request.into();
+    let mut templates = state.repository.get_templates(&user.username).await;
+    templates.retain(|t| t.id != template.id);
+    templates.push(template.clone());
+    state
+        .repository
+        .update_templates(&user.username, &templates)
+        .await;
+
+    Ok(Json(TemplateResponse::new(template, false)))
+}
+
+async fn delete_template(
+    State(state): State<SharedState>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<String>,
+) -> StatusCode {
+    let mut templates = state.repository.get_templates(&user.username).await;
+    let count = templates.len();
+    templates.retain(|t| t.id != id);
+    if templates.len() == count {
+        return StatusCode::NOT_FOUND;
+    }
+
+    state
+        .repository
+        .update_templates(&user.username, &templates)
+        .await;
+
+    StatusCode::OK
+}
+
+async fn get_sound_presets() -> Json<Vec<SoundPreset>> {
+    Json(sound_presets())
+}
+
+pub fn routes() -> Router<SharedState> {
+    Router::new()
+        .route("/", get(get_templates).post(save_template))
+        .route("/:id", delete(delete_template))
+        .route("/sounds", get(get_sound_presets))
+}
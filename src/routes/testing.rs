@@ -0,0 +1,121 @@
+//! Sends requests to the routes of the app with the memory backend, for the tests
+//! of the route modules.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::ConnectInfo;
+use axum::http::{header, request::Builder, HeaderMap, Method, Request, StatusCode};
+use axum::{body::Body, Router};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+use crate::models::{AppState, InstanceProperties, SharedState};
+use crate::repository::{MemoryStorage, Repository};
+use crate::webhooks::WebhookDispatcher;
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// `Null` if the body is empty or not json
+    pub body: Value,
+}
+
+pub struct TestClient {
+    pub state: SharedState,
+    app: Router,
+}
+
+impl TestClient {
+    /// Webhooks and OSC messages may be sent to local addresses, so tests can receive them
+    pub async fn new() -> Self {
+        let state: SharedState = Arc::new(AppState {
+            repository: Repository::new(MemoryStorage::new(None).await),
+            webhooks: WebhookDispatcher::new(true),
+            jwt_key: "test".to_owned(),
+            client_ip_header: None,
+            allow_private_targets: true,
+            osc_enabled: true,
+            instance_properties: InstanceProperties {
+                demo: false,
+                donation: None,
+                s3_host: String::new(),
+            },
+        });
+
+        TestClient {
+            app: super::app(state.clone()).with_state(state.clone()),
+            state,
+        }
+    }
+
+    /// Sends the request as if it came from 127.0.0.1
+    pub async fn send(&self, mut request: Request<Body>) -> TestResponse {
+        let address = SocketAddr::from(([127, 0, 0, 1], 40000));
+        request.extensions_mut().insert(ConnectInfo(address));
+
+        let response = self.app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        TestResponse {
+            status,
+            headers,
+            body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+        }
+    }
+
+    /// Sends `body` as json, or no body if it is `Null`
+    pub async fn call(
+        &self,
+        method: Method,
+        uri: &str,
+        token: Option<&str>,
+        body: Value,
+    ) -> TestResponse {
+        self.send(json_body(request(method, uri, token), body))
+            .await
+    }
+
+    /// Creates a timer with the password `password` and returns its admin token
+    pub async fn create_timer(&self, id: &str) -> String {
+        let response = self
+            .call(Method::POST, "/api/timer/", None, timer_request(id))
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        response.body["token"].as_str().unwrap().to_owned()
+    }
+}
+
+pub fn request(method: Method, uri: &str, token: Option<&str>) -> Builder {
+    let request = Request::builder().method(method).uri(uri);
+    match token {
+        Some(token) => request.header(header::AUTHORIZATION, format!("Bearer {}", token)),
+        None => request,
+    }
+}
+
+pub fn json_body(request: Builder, body: Value) -> Request<Body> {
+    if body.is_null() {
+        return request.body(Body::empty()).unwrap();
+    }
+
+    request
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+pub fn segment(label: &str, time: u32) -> Value {
+    json!({"label": label, "time": time, "color": null, "count_to": 0, "sounds": []})
+}
+
+/// A timer with two segments and the password `password`
+pub fn timer_request(id: &str) -> Value {
+    json!({
+        "id": id,
+        "password": "password",
+        "segments": [segment("Boulder", 240000), segment("Change", 15000)],
+    })
+}
@@ -5,179 +5,980 @@ use axum::response::Response;
 use axum::routing::{get, post, put};
 use axum::Router;
 use axum::{
-    headers::authorization::{Authorization, Bearer},
+    headers::{
+        authorization::{Authorization, Bearer},
+        ContentType, ETag, IfMatch,
+    },
     response::IntoResponse,
     Json, TypedHeader,
 };
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use mime_guess::mime::Mime;
 use regex::Regex;
 use std::str;
 
-use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
+use crate::audit::{Action, Actor, FieldChange};
+use crate::models::*;
+use crate::patch::TimerPatch;
+use crate::repository::{Timer, User, Webhook};
+use crate::timer_state::{
+    adjust, calculate_state, current_time, pause, resume, skip, start, stop, SkipDirection,
 };
+use crate::validation::{validate_creation, validate_update, FieldError, RequestError};
+use crate::webhooks::{control_event, generate_secret, lifecycle_events, TimerLifecycleEvent};
 
-use crate::models::*;
-use crate::repository::Timer;
+use super::history::Audit;
+use super::template::find_template;
+use super::user::add_timer_id;
+
+use super::auth::{
+    auth_middleware, check_password_limited, create_tokens, hash_password, refresh_tokens,
+    timer_token_actor, AuthError, AuthScope, ClientIp, CurrentUser, TokenSubject,
+};
+
+/// Whether `token` may be used to see the timer, public timers can be seen by everyone
+pub async fn can_view(state: &SharedState, timer: &Timer, token: Option<&str>) -> bool {
+    if !timer.private {
+        return true;
+    }
+
+    match token {
+        Some(token) => matches!(
+            timer_token_actor(state, token, timer).await,
+            Some((role, _)) if role >= Role::Viewer
+        ),
+        None => false,
+    }
+}
 
-async fn auth_middleware<B>(
+async fn private_timer_middleware<B>(
     State(state): State<SharedState>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<String>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
     request: Request<B>,
     next: Next<B>,
 ) -> Result<Response, StatusCode> {
-    let mut validation = Validation::new(Algorithm::default());
-    validation.set_issuer(&["de:itsblue:distributed-timer"]);
-    validation.validate_exp = false;
-
-    let token = decode::<Claims>(
-        auth.token(),
-        &DecodingKey::from_secret(state.jwt_key.as_ref()),
-        &validation,
-    );
+    let token = auth.as_ref().map(|TypedHeader(auth)| auth.token());
+    let timer = state.repository.get_timer(id).await;
 
-    if token.is_err() || request.uri().to_string() != format!("/{}", token.unwrap().claims.id) {
-        return Err(StatusCode::UNAUTHORIZED);
+    if let Some(timer) = timer {
+        if !can_view(&state, &timer, token).await {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
     }
 
     Ok(next.run(request).await)
 }
 
-fn hash_password(password: &str) -> String {
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
-    argon2
-        .hash_password(password.as_ref(), &salt)
-        .unwrap()
-        .to_string()
-}
+async fn create_token(
+    State(state): State<SharedState>,
+    client: ClientIp,
+    Json(request): Json<TokenRequest>,
+) -> Result<Json<TokenResponse>, AuthError> {
+    let timer = state.repository.get_timer(request.id.clone()).await;
+
+    check_password_limited(
+        &state,
+        &client,
+        TokenSubject::Timer,
+        &request.id,
+        &request.password,
+        timer.as_ref().map(|timer| timer.password.as_str()),
+    )
+    .await?;
+    let timer = timer.ok_or(StatusCode::UNAUTHORIZED)?;
+
+    Audit::new(Actor::Password, client)
+        .record(&state, Action::Token, Some(&timer), Some(&timer))
+        .await;
 
-fn check_password_hash(password: &str, password_hash: &str) -> bool {
-    let parsed_hash = PasswordHash::new(password_hash).unwrap();
-    Argon2::default()
-        .verify_password(password.as_ref(), &parsed_hash)
-        .is_ok()
+    Ok(Json(create_tokens(
+        request.id,
+        TokenSubject::Timer,
+        Role::Admin,
+        timer.token_generation,
+        &state.jwt_key,
+    )))
 }
 
-fn create_jwt(id: String, key: &str) -> String {
-    let claims = Claims {
-        id: id,
-        exp: 0,
-        iss: "de:itsblue:distributed-timer".to_string(),
-    };
+async fn refresh_token(
+    State(state): State<SharedState>,
+    client: ClientIp,
+    Json(request): Json<RefreshRequest>,
+) -> Result<Json<TokenResponse>, StatusCode> {
+    let (id, tokens) = refresh_tokens(&state, TokenSubject::Timer, request).await?;
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(key.as_ref()),
-    )
-    .unwrap()
+    if let Some(timer) = state.repository.get_timer(id).await {
+        Audit::new(Actor::RefreshToken, client)
+            .record(&state, Action::Token, Some(&timer), Some(&timer))
+            .await;
+    }
+
+    Ok(Json(tokens))
 }
 
-async fn create_token(
+async fn create_scoped_token(
     State(state): State<SharedState>,
-    Json(request): Json<TokenRequest>,
+    Path(id): Path<String>,
+    audit: Audit,
+    Json(request): Json<ScopedTokenRequest>,
 ) -> Result<Json<TokenResponse>, StatusCode> {
     let timer = state
         .repository
-        .get_timer(request.id.clone())
+        .get_timer(id)
         .await
-        .map(|t| Ok(t))
+        .map(Ok)
         .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
 
-    if !check_password_hash(&request.password, &timer.password) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+    audit
+        .record(&state, Action::Token, Some(&timer), Some(&timer))
+        .await;
+
+    Ok(Json(create_tokens(
+        timer.id,
+        TokenSubject::Timer,
+        request.role,
+        timer.token_generation,
+        &state.jwt_key,
+    )))
+}
+
+/// Changes the password and revokes all tokens of the timer.
+/// Returns new tokens, so the client changing the password stays logged in.
+async fn change_password(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    client: ClientIp,
+    audit: Audit,
+    Json(request): Json<PasswordChangeRequest>,
+) -> Result<Json<TokenResponse>, AuthError> {
+    let old_timer = state
+        .repository
+        .get_timer(id)
+        .await
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let mut timer = old_timer.clone();
+
+    check_password_limited(
+        &state,
+        &client,
+        TokenSubject::Timer,
+        &timer.id,
+        &request.old_password,
+        Some(&timer.password),
+    )
+    .await?;
 
-    let token = create_jwt(request.id, &state.jwt_key);
+    timer.password = hash_password(&request.new_password);
+    timer.token_generation += 1;
+    state
+        .repository
+        .update_timer(&mut timer)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+    audit
+        .record(&state, Action::Password, Some(&old_timer), Some(&timer))
+        .await;
 
-    Ok(Json(TokenResponse { token }))
+    Ok(Json(create_tokens(
+        timer.id,
+        TokenSubject::Timer,
+        Role::Admin,
+        timer.token_generation,
+        &state.jwt_key,
+    )))
 }
 
-async fn create_timer(
+/// Revokes all tokens of the timer, including the one used for this request
+async fn logout_timer(
     State(state): State<SharedState>,
-    Json(request): Json<TimerCreationRequest>,
-) -> Result<Json<TimerCreationResponse>, StatusCode> {
-    let id_regex = Regex::new(r"^[a-zA-Z0-9-_]+$").unwrap();
-    if !id_regex.is_match(&request.id) {
-        return Err(StatusCode::BAD_REQUEST);
-    }
+    Path(id): Path<String>,
+    audit: Audit,
+) -> Result<StatusCode, StatusCode> {
+    let old_timer = state
+        .repository
+        .get_timer(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
 
-    let hashed_password = hash_password(&request.password);
-    let timer = request.into(hashed_password);
+    let mut timer = Timer {
+        token_generation: old_timer.token_generation + 1,
+        ..old_timer.clone()
+    };
+    state
+        .repository
+        .update_timer(&mut timer)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+    audit
+        .record(&state, Action::Logout, Some(&old_timer), Some(&timer))
+        .await;
 
+    Ok(StatusCode::OK)
+}
+
+pub fn is_valid_id(id: &str) -> bool {
+    let id_regex = Regex::new(r"^[a-zA-Z0-9-_]+$").unwrap();
+    id_regex.is_match(id)
+}
+
+/// Stores a new timer and returns it with tokens for it.
+/// If the timer has an owner, it is added to the timers of the `user`.
+pub async fn insert_timer(
+    state: &SharedState,
+    audit: &Audit,
+    timer: Timer,
+    user: Option<User>,
+) -> Result<Json<TimerCreationResponse>, StatusCode> {
     state
         .repository
         .create_timer(&timer)
         .await
         .map_err(|_| StatusCode::CONFLICT)?;
+    audit
+        .record(state, Action::Create, None, Some(&timer))
+        .await;
+
+    if let Some(user) = user {
+        add_timer_id(state, &user.username, &timer.id).await;
+    }
 
-    let token = create_jwt(timer.id.clone(), &state.jwt_key);
+    state
+        .webhooks
+        .send_lifecycle_event(&timer, TimerLifecycleEvent::Created);
+
+    let tokens = create_tokens(
+        timer.id.clone(),
+        TokenSubject::Timer,
+        Role::Admin,
+        timer.token_generation,
+        &state.jwt_key,
+    );
 
     Ok(Json(TimerCreationResponse {
         timer: timer.into(),
-        token: token,
+        tokens,
     }))
 }
 
+/// Timers created with the token of a user account belong to that user and can be
+/// created from the templates of the user
+async fn create_timer(
+    State(state): State<SharedState>,
+    user: Option<CurrentUser>,
+    client: ClientIp,
+    Json(request): Json<TimerCreationRequest>,
+) -> Result<Json<TimerCreationResponse>, RequestError> {
+    if !is_valid_id(&request.id) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let user = user.map(|CurrentUser(user)| user);
+    let template = match &request.template {
+        Some(id) => Some(
+            find_template(&state, id, user.as_ref())
+                .await
+                .ok_or(StatusCode::BAD_REQUEST)?,
+        ),
+        None => None,
+    };
+    validate_creation(&request, template.as_ref())?;
+
+    let hashed_password = hash_password(&request.password);
+    let mut timer = request.into(hashed_password);
+    timer.owner = user.as_ref().map(|user| user.username.clone());
+    if let Some(template) = template {
+        timer.sequences = template.sequences;
+        timer.repeat = template.repeat;
+        timer.display_options = template.display_options;
+        timer.metadata = template.metadata;
+    }
+
+    let audit = Audit::new(creator(user.as_ref()), client);
+    Ok(insert_timer(&state, &audit, timer, user).await?)
+}
+
+/// Timers are created by a user or by someone who sets the password
+pub fn creator(user: Option<&User>) -> Actor {
+    match user {
+        Some(user) => Actor::User {
+            username: user.username.clone(),
+        },
+        None => Actor::Password,
+    }
+}
+
+/// Creates a stopped copy of the timer with its playlist, settings, webhooks and
+/// OSC targets, which belongs to the same user. The copied webhooks get new secrets,
+/// so the copy can't sign payloads like the source timer. The copy is recorded for the source timer.
+async fn clone_timer(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+    Json(request): Json<CloneRequest>,
+) -> Result<Json<TimerCreationResponse>, StatusCode> {
+    if !is_valid_id(&request.id) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let source = state
+        .repository
+        .get_timer(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    let mut timer = Timer {
+        sequences: source.sequences,
+        repeat: source.repeat,
+        display_options: source.display_options,
+        password: hash_password(&request.password),
+        id: request.id,
+        metadata: source.metadata,
+        webhooks: source
+            .webhooks
+            .into_iter()
+            .map(|webhook| Webhook {
+                url: webhook.url,
+                secret: generate_secret(),
+            })
+            .collect(),
+        osc_targets: source.osc_targets,
+        private: source.private,
+        owner: source.owner,
+        ..Default::default()
+    };
+    stop(&mut timer, current_time());
+
+    let user = match &timer.owner {
+        Some(owner) => state.repository.get_user(owner.clone()).await,
+        None => None,
+    };
+
+    let response = insert_timer(&state, &audit, timer, user).await?;
+    let change = FieldChange {
+        field: "clone".to_owned(),
+        before: serde_json::Value::Null,
+        after: response.timer.id.clone().into(),
+    };
+    audit
+        .record_changes(&state, Action::Clone, &source.id, vec![change])
+        .await;
+
+    Ok(response)
+}
+
+/// The revision of the timer, which has to be sent as `If-Match` to update it
+fn etag(timer: &Timer) -> TypedHeader<ETag> {
+    TypedHeader(format!("\"{}\"", timer.revision).parse().unwrap())
+}
+
 async fn get_timer(
     State(state): State<SharedState>,
     Path(id): Path<String>,
-) -> Result<Json<TimerResponse>, StatusCode> {
+) -> Result<(TypedHeader<ETag>, Json<TimerResponse>), StatusCode> {
     let timer = state
         .repository
         .get_timer(id)
         .await
-        .map(|t| Ok(t))
+        .map(Ok)
         .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
-    Ok(Json(timer.into()))
+    Ok((etag(&timer), Json(timer.into())))
+}
+
+async fn get_timer_state(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Result<Json<TimerStateResponse>, StatusCode> {
+    let timer = state
+        .repository
+        .get_timer(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    let timestamp = current_time();
+    let timer_state = calculate_state(&timer, timestamp).ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    Ok(Json(TimerStateResponse {
+        id: timer.id,
+        timestamp,
+        state: timer_state,
+    }))
 }
 
 async fn update_timer(
     State(state): State<SharedState>,
     Path(id): Path<String>,
+    audit: Audit,
+    if_match: Option<TypedHeader<IfMatch>>,
     Json(request): Json<TimerUpdateRequest>,
-) -> Result<Json<TimerResponse>, StatusCode> {
+) -> Result<(TypedHeader<ETag>, Json<TimerResponse>), RequestError> {
+    let TypedHeader(if_match) = if_match.ok_or(StatusCode::PRECONDITION_REQUIRED)?;
+
     let old_timer: Timer = state
         .repository
         .get_timer(id)
         .await
-        .map(|t| Ok(t))
+        .map(Ok)
         .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
 
-    let timer = Timer {
-        segments: request.segments,
+    // the timer was changed since the client has seen it
+    if !if_match.precondition_passes(&etag(&old_timer)) {
+        return Err(StatusCode::CONFLICT.into());
+    }
+
+    apply_update(state, audit, old_timer, request).await
+}
+
+/// Validates the settings and applies them to the timer, which fails with a conflict
+/// if it was changed since `old_timer` was read
+async fn apply_update(
+    state: SharedState,
+    audit: Audit,
+    old_timer: Timer,
+    request: TimerUpdateRequest,
+) -> Result<(TypedHeader<ETag>, Json<TimerResponse>), RequestError> {
+    validate_update(&request)?;
+
+    let mut timer = Timer {
+        sequences: playlist(request.sequences, request.segments),
         repeat: request.repeat,
         display_options: request.display_options,
         metadata: request.metadata,
         start_at: request.start_at,
         stop_at: request.stop_at,
-        ..old_timer
+        private: request.private.unwrap_or(old_timer.private),
+        ..old_timer.clone()
     };
+    // the time added to a segment doesn't belong to it anymore once the timer is moved
+    if timer.start_at != old_timer.start_at || timer.sequences != old_timer.sequences {
+        timer.extension = None;
+    }
+
+    state
+        .repository
+        .update_timer(&mut timer)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+    state
+        .repository
+        .add_revision(&old_timer, current_time())
+        .await;
+    audit
+        .record(&state, Action::Update, Some(&old_timer), Some(&timer))
+        .await;
+
+    for event in lifecycle_events(&old_timer, &timer) {
+        state.webhooks.send_lifecycle_event(&timer, event);
+    }
+
+    Ok((etag(&timer), Json(timer.into())))
+}
+
+/// Changes some settings of the timer with a JSON Merge Patch or, with the content type
+/// `application/json-patch+json`, a JSON Patch.
+/// Requires `If-Match` like a PUT, so a patch isn't applied to settings the client hasn't seen.
+async fn patch_timer(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+    if_match: Option<TypedHeader<IfMatch>>,
+    content_type: Option<TypedHeader<ContentType>>,
+    body: String,
+) -> Result<(TypedHeader<ETag>, Json<TimerResponse>), RequestError> {
+    let TypedHeader(if_match) = if_match.ok_or(StatusCode::PRECONDITION_REQUIRED)?;
+
+    let content_type = content_type
+        .map(|TypedHeader(content_type)| Mime::from(content_type).essence_str().to_owned())
+        .unwrap_or_default();
+    let patch = match content_type.as_str() {
+        "application/merge-patch+json" | "application/json" => {
+            serde_json::from_str(&body).map(TimerPatch::Merge)
+        }
+        "application/json-patch+json" => serde_json::from_str(&body).map(TimerPatch::Operations),
+        _ => return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE.into()),
+    }
+    .map_err(|e| FieldError::new("", e.to_string()))?;
+
+    let old_timer: Timer = state
+        .repository
+        .get_timer(id)
+        .await
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // the timer was changed since the client has seen it
+    if !if_match.precondition_passes(&etag(&old_timer)) {
+        return Err(StatusCode::CONFLICT.into());
+    }
+
+    let request = crate::patch::patch_timer(&old_timer, patch)?;
+    apply_update(state, audit, old_timer, request).await
+}
+
+/// Changes the timer with `change`, records it as `action` and sends the event of the action
+/// to its webhooks. Responds with a conflict if `change` returns `false`.
+async fn control_timer(
+    state: SharedState,
+    id: String,
+    audit: Audit,
+    action: Action,
+    change: impl FnOnce(&mut Timer, u64) -> bool,
+) -> Result<Json<TimerResponse>, StatusCode> {
+    let old_timer: Timer = state
+        .repository
+        .get_timer(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    let mut timer = old_timer.clone();
+    if !change(&mut timer, current_time()) {
+        return Err(StatusCode::CONFLICT);
+    }
 
-    state.repository.update_timer(&timer).await;
+    state
+        .repository
+        .update_timer(&mut timer)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+    audit
+        .record(&state, action, Some(&old_timer), Some(&timer))
+        .await;
+    state
+        .webhooks
+        .send_lifecycle_event(&timer, control_event(action));
 
     Ok(Json(timer.into()))
 }
 
+async fn start_timer(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+    Json(request): Json<StartRequest>,
+) -> Result<Json<TimerResponse>, StatusCode> {
+    control_timer(state, id, audit, Action::Start, |timer, _| {
+        start(timer, request.start_at);
+        true
+    })
+    .await
+}
+
+async fn stop_timer(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+) -> Result<Json<TimerResponse>, StatusCode> {
+    control_timer(state, id, audit, Action::Stop, |timer, now| {
+        stop(timer, now);
+        true
+    })
+    .await
+}
+
+async fn pause_timer(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+) -> Result<Json<TimerResponse>, StatusCode> {
+    control_timer(state, id, audit, Action::Pause, pause).await
+}
+
+async fn resume_timer(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+) -> Result<Json<TimerResponse>, StatusCode> {
+    control_timer(state, id, audit, Action::Resume, resume).await
+}
+
+async fn skip_segment(
+    state: SharedState,
+    id: String,
+    audit: Audit,
+    direction: SkipDirection,
+) -> Result<Json<TimerResponse>, StatusCode> {
+    let action = match direction {
+        SkipDirection::Next => Action::Next,
+        SkipDirection::Previous => Action::Previous,
+    };
+    control_timer(state, id, audit, action, |timer, now| {
+        skip(timer, now, direction)
+    })
+    .await
+}
+
+async fn next_segment(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+) -> Result<Json<TimerResponse>, StatusCode> {
+    skip_segment(state, id, audit, SkipDirection::Next).await
+}
+
+async fn previous_segment(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+) -> Result<Json<TimerResponse>, StatusCode> {
+    skip_segment(state, id, audit, SkipDirection::Previous).await
+}
+
+async fn adjust_timer(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+    Json(request): Json<TimerAdjustRequest>,
+) -> Result<Json<TimerResponse>, StatusCode> {
+    control_timer(state, id, audit, Action::Adjust, |timer, now| {
+        adjust(timer, now, request.offset)
+    })
+    .await
+}
+
 async fn delete_timer(
     State(state): State<SharedState>,
     Path(id): Path<String>,
+    audit: Audit,
 ) -> impl IntoResponse {
+    let timer = state
+        .repository
+        .get_timer(id.clone())
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::NOT_FOUND))?;
+
+    // recorded first, so the entry comes before those of a new timer with the same id
+    audit
+        .record(&state, Action::Delete, Some(&timer), None)
+        .await;
     state
         .repository
         .delete_timer(id)
         .await
-        .map(|_| StatusCode::OK)
-        .map_err(|_| StatusCode::NOT_FOUND)
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    state
+        .webhooks
+        .send_lifecycle_event(&timer, TimerLifecycleEvent::Deleted);
+
+    Ok::<_, StatusCode>(StatusCode::OK)
 }
 
 pub fn routes(state: SharedState) -> Router<SharedState> {
+    let scope = |role| AuthScope {
+        state: state.clone(),
+        subject: TokenSubject::Timer,
+        role,
+    };
+
+    let mut admin_routes = Router::new()
+        .route(
+            "/:id",
+            put(update_timer).patch(patch_timer).delete(delete_timer),
+        )
+        .route("/:id/token", post(create_scoped_token))
+        .route("/:id/logout", post(logout_timer))
+        .route("/:id/password", put(change_password))
+        .route("/:id/clone", post(clone_timer))
+        .merge(super::webhooks::routes())
+        .merge(super::export::routes())
+        .merge(super::history::routes())
+        .merge(super::revision::routes());
+    if state.osc_enabled {
+        admin_routes = admin_routes.merge(super::osc::routes());
+    }
+    let admin_routes = admin_routes.route_layer(middleware::from_fn_with_state(
+        scope(Role::Admin),
+        auth_middleware,
+    ));
+
+    let operator_routes = Router::new()
+        .route("/:id/start", post(start_timer))
+        .route("/:id/stop", post(stop_timer))
+        .route("/:id/pause", post(pause_timer))
+        .route("/:id/resume", post(resume_timer))
+        .route("/:id/next", post(next_segment))
+        .route("/:id/previous", post(previous_segment))
+        .route("/:id/adjust", post(adjust_timer))
+        .route_layer(middleware::from_fn_with_state(
+            scope(Role::Operator),
+            auth_middleware,
+        ));
+
+    let view_routes = Router::new()
+        .route("/:id", get(get_timer))
+        .route("/:id/state", get(get_timer_state))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            private_timer_middleware,
+        ));
+
     Router::new()
-        .route("/:id", put(update_timer).delete(delete_timer))
-        .layer(middleware::from_fn_with_state(state, auth_middleware))
+        .merge(admin_routes)
+        .merge(operator_routes)
+        .merge(view_routes)
         .route("/token", post(create_token))
+        .route("/refresh", post(refresh_token))
+        .route("/import", post(super::export::import))
         .route("/", post(create_timer))
-        .route("/:id", get(get_timer))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, Method};
+    use serde_json::{json, Value};
+
+    use super::super::testing::{request, TestClient, TestResponse};
+    use super::*;
+
+    async fn get(client: &TestClient, token: &str) -> TestResponse {
+        client
+            .call(Method::GET, "/api/timer/test", Some(token), json!(null))
+            .await
+    }
+
+    async fn put(
+        client: &TestClient,
+        token: &str,
+        if_match: Option<&str>,
+        body: Value,
+    ) -> TestResponse {
+        let mut put = request(Method::PUT, "/api/timer/test", Some(token))
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(if_match) = if_match {
+            put = put.header(header::IF_MATCH, if_match);
+        }
+        client
+            .send(put.body(body.to_string().into()).unwrap())
+            .await
+    }
+
+    async fn patch(
+        client: &TestClient,
+        token: &str,
+        if_match: Option<&str>,
+        content_type: &str,
+        body: Value,
+    ) -> TestResponse {
+        let mut patch = request(Method::PATCH, "/api/timer/test", Some(token))
+            .header(header::CONTENT_TYPE, content_type);
+        if let Some(if_match) = if_match {
+            patch = patch.header(header::IF_MATCH, if_match);
+        }
+        client
+            .send(patch.body(body.to_string().into()).unwrap())
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_update_if_match() {
+        let client = TestClient::new().await;
+        let token = client.create_timer("test").await;
+
+        let timer = get(&client, &token).await;
+        assert_eq!(timer.headers[header::ETAG], "\"0\"");
+        let mut body = timer.body;
+        body["repeat"] = json!(true);
+
+        let response = put(&client, &token, None, body.clone()).await;
+        assert_eq!(response.status, StatusCode::PRECONDITION_REQUIRED);
+        let response = put(&client, &token, Some("\"5\""), body.clone()).await;
+        assert_eq!(response.status, StatusCode::CONFLICT);
+
+        let response = put(&client, &token, Some("\"0\""), body.clone()).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers[header::ETAG], "\"1\"");
+        assert_eq!(response.body["repeat"], true);
+
+        // someone who has only seen the first version can't overwrite the change
+        body["repeat"] = json!(false);
+        let response = put(&client, &token, Some("\"0\""), body.clone()).await;
+        assert_eq!(response.status, StatusCode::CONFLICT);
+        let response = put(&client, &token, Some("*"), body).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body["repeat"], false);
+    }
+
+    #[tokio::test]
+    async fn test_patch() {
+        let client = TestClient::new().await;
+        let token = client.create_timer("test").await;
+        let merge = json!({"display_options": {"clock": true}});
+
+        let response = patch(
+            &client,
+            &token,
+            None,
+            "application/merge-patch+json",
+            merge.clone(),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::PRECONDITION_REQUIRED);
+        let response = patch(
+            &client,
+            &token,
+            Some("\"3\""),
+            "application/merge-patch+json",
+            merge.clone(),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::CONFLICT);
+
+        let response = patch(
+            &client,
+            &token,
+            Some("\"0\""),
+            "application/merge-patch+json",
+            merge,
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers[header::ETAG], "\"1\"");
+        assert_eq!(response.body["display_options"]["clock"], true);
+        // everything else is kept
+        assert_eq!(response.body["segments"][0]["label"], "Boulder");
+
+        let operations = json!([{"op": "replace", "path": "/segments/1/label", "value": "Rest"}]);
+        let response = patch(
+            &client,
+            &token,
+            Some("\"1\""),
+            "application/json-patch+json",
+            operations,
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body["segments"][1]["label"], "Rest");
+        assert_eq!(response.body["display_options"]["clock"], true);
+
+        let invalid = json!([{"op": "replace", "path": "/segments/0/time", "value": 0}]);
+        let response = patch(
+            &client,
+            &token,
+            Some("*"),
+            "application/json-patch+json",
+            invalid,
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.body["errors"][0]["field"], "segments[0].time");
+
+        let response = patch(&client, &token, Some("*"), "text/plain", json!({})).await;
+        assert_eq!(response.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // neither of the failed patches was applied
+        assert_eq!(get(&client, &token).await.headers[header::ETAG], "\"2\"");
+    }
+
+    #[tokio::test]
+    async fn test_clone() {
+        let client = TestClient::new().await;
+        let token = client.create_timer("test").await;
+        let webhooks = json!([{"url": "http://127.0.0.1:9000/hook", "secret": "known"}]);
+        let response = client
+            .call(
+                Method::PUT,
+                "/api/timer/test/webhooks",
+                Some(&token),
+                webhooks,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+
+        let response = client
+            .call(
+                Method::POST,
+                "/api/timer/test/clone",
+                Some(&token),
+                json!({"id": "copy", "password": "password"}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+
+        // the webhooks are copied, but not their secrets
+        let copy = client.state.repository.get_timer("copy".to_owned()).await;
+        let webhooks = copy.unwrap().webhooks;
+        assert_eq!(webhooks.len(), 1);
+        assert_eq!(webhooks[0].url, "http://127.0.0.1:9000/hook");
+        assert_ne!(webhooks[0].secret, "known");
+        assert!(!webhooks[0].secret.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_export_needs_admin() {
+        let client = TestClient::new().await;
+        let admin = client.create_timer("test").await;
+        let scoped = |role: &'static str| {
+            let (client, admin) = (&client, &admin);
+            async move {
+                let response = client
+                    .call(
+                        Method::POST,
+                        "/api/timer/test/token",
+                        Some(admin),
+                        json!({"role": role}),
+                    )
+                    .await;
+                response.body["token"].as_str().unwrap().to_owned()
+            }
+        };
+        let export = |token: String| {
+            let client = &client;
+            async move {
+                client
+                    .call(
+                        Method::GET,
+                        "/api/timer/test/export",
+                        Some(&token),
+                        json!(null),
+                    )
+                    .await
+                    .status
+            }
+        };
+
+        assert_eq!(export(scoped("viewer").await).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            export(scoped("operator").await).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(export(scoped("admin").await).await, StatusCode::OK);
+        assert_eq!(export("invalid".to_owned()).await, StatusCode::UNAUTHORIZED);
+
+        // the role of a password login can't be chosen, it is always admin
+        let response = client
+            .call(
+                Method::POST,
+                "/api/timer/token",
+                None,
+                json!({"id": "test", "password": "password", "role": "viewer"}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let token = response.body["token"].as_str().unwrap().to_owned();
+        assert_eq!(export(token).await, StatusCode::OK);
+
+        // operators control the timer, but can't change it
+        let operator = scoped("operator").await;
+        let response = client
+            .call(
+                Method::POST,
+                "/api/timer/test/start",
+                Some(&operator),
+                json!({"start_at": 1000}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let response = client
+            .call(
+                Method::DELETE,
+                "/api/timer/test",
+                Some(&operator),
+                json!(null),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+    }
 }
@@ -0,0 +1,302 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use regex::Regex;
+
+use crate::audit::{Action, Actor};
+use crate::models::*;
+use crate::repository::{Timer, User};
+
+use super::auth::{
+    check_password_limited, create_tokens, hash_password, refresh_tokens, AuthError, ClientIp,
+    CurrentUser, TokenSubject,
+};
+use super::history::Audit;
+
+async fn create_user(
+    State(state): State<SharedState>,
+    Json(request): Json<UserRequest>,
+) -> Result<Json<TokenResponse>, StatusCode> {
+    let username_regex = Regex::new(r"^[a-zA-Z0-9-_]+$").unwrap();
+    if !username_regex.is_match(&request.username) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let user = User {
+        username: request.username,
+        password: hash_password(&request.password),
+        timer_ids: Vec::new(),
+        token_generation: 0,
+    };
+
+    state
+        .repository
+        .create_user(&user)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+
+    Ok(Json(create_tokens(
+        user.username,
+        TokenSubject::User,
+        Role::Admin,
+        user.token_generation,
+        &state.jwt_key,
+    )))
+}
+
+async fn create_token(
+    State(state): State<SharedState>,
+    client: ClientIp,
+    Json(request): Json<UserRequest>,
+) -> Result<Json<TokenResponse>, AuthError> {
+    let user = state.repository.get_user(request.username.clone()).await;
+
+    check_password_limited(
+        &state,
+        &client,
+        TokenSubject::User,
+        &request.username,
+        &request.password,
+        user.as_ref().map(|user| user.password.as_str()),
+    )
+    .await?;
+    let user = user.ok_or(StatusCode::UNAUTHORIZED)?;
+
+    Ok(Json(create_tokens(
+        user.username,
+        TokenSubject::User,
+        Role::Admin,
+        user.token_generation,
+        &state.jwt_key,
+    )))
+}
+
+async fn refresh_token(
+    State(state): State<SharedState>,
+    Json(request): Json<RefreshRequest>,
+) -> Result<Json<TokenResponse>, StatusCode> {
+    refresh_tokens(&state, TokenSubject::User, request)
+        .await
+        .map(|(_, tokens)| Json(tokens))
+}
+
+/// The timers the user owns, timers which were deleted or given away are skipped
+async fn get_timers(
+    State(state): State<SharedState>,
+    CurrentUser(user): CurrentUser,
+) -> Json<Vec<TimerResponse>> {
+    let mut timers = Vec::new();
+    for timer_id in user.timer_ids {
+        if let Some(timer) = state.repository.get_timer(timer_id).await {
+            if timer.owner.as_ref() == Some(&user.username) {
+                timers.push(timer.into());
+            }
+        }
+    }
+
+    Json(timers)
+}
+
+/// Adds an existing timer to the account, which needs the password of the timer
+async fn add_timer(
+    State(state): State<SharedState>,
+    CurrentUser(user): CurrentUser,
+    client: ClientIp,
+    Json(request): Json<TokenRequest>,
+) -> Result<Json<TimerResponse>, AuthError> {
+    let timer = state.repository.get_timer(request.id.clone()).await;
+
+    check_password_limited(
+        &state,
+        &client,
+        TokenSubject::Timer,
+        &request.id,
+        &request.password,
+        timer.as_ref().map(|timer| timer.password.as_str()),
+    )
+    .await?;
+    let old_timer = timer.ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let mut timer = Timer {
+        owner: Some(user.username.clone()),
+        ..old_timer.clone()
+    };
+    state
+        .repository
+        .update_timer(&mut timer)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+
+    let actor = Actor::User {
+        username: user.username.clone(),
+    };
+    Audit::new(actor, client)
+        .record(&state, Action::Owner, Some(&old_timer), Some(&timer))
+        .await;
+    add_timer_id(&state, &user.username, &timer.id).await;
+
+    Ok(Json(timer.into()))
+}
+
+/// Adds the timer to the timers of the user as stored now, so concurrent changes of the user
+/// aren't overwritten
+pub async fn add_timer_id(state: &SharedState, username: &str, timer_id: &str) {
+    state
+        .repository
+        .update_user(username, |user| {
+            if !user.timer_ids.iter().any(|id| id == timer_id) {
+                user.timer_ids.push(timer_id.to_owned());
+            }
+        })
+        .await;
+}
+
+/// Revokes all tokens of the user, including the one used for this request
+async fn logout_user(
+    State(state): State<SharedState>,
+    CurrentUser(user): CurrentUser,
+) -> StatusCode {
+    state
+        .repository
+        .update_user(&user.username, |user| user.token_generation += 1)
+        .await;
+
+    StatusCode::OK
+}
+
+pub fn routes() -> Router<SharedState> {
+    Router::new()
+        .route("/", post(create_user))
+        .route("/token", post(create_token))
+        .route("/refresh", post(refresh_token))
+}
+
+/// The routes of the user the token belongs to
+pub fn me_routes() -> Router<SharedState> {
+    Router::new()
+        .route("/timers", get(get_timers).post(add_timer))
+        .route("/logout", post(logout_user))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use super::super::testing::{timer_request, TestClient};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_user_timers() {
+        let client = TestClient::new().await;
+        let account = json!({"username": "setter", "password": "secret"});
+
+        let response = client
+            .call(Method::POST, "/api/user/", None, account.clone())
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let response = client
+            .call(Method::POST, "/api/user/", None, account.clone())
+            .await;
+        assert_eq!(response.status, StatusCode::CONFLICT);
+
+        let wrong = json!({"username": "setter", "password": "wrong"});
+        let response = client
+            .call(Method::POST, "/api/user/token", None, wrong)
+            .await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+        let response = client
+            .call(Method::POST, "/api/user/token", None, account)
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let token = response.body["token"].as_str().unwrap().to_owned();
+
+        // timers created with the token belong to the user
+        let response = client
+            .call(
+                Method::POST,
+                "/api/timer/",
+                Some(&token),
+                timer_request("owned"),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let owned = client.state.repository.get_timer("owned".to_owned()).await;
+        assert_eq!(owned.unwrap().owner.as_deref(), Some("setter"));
+
+        // existing timers are added with their password
+        client.create_timer("added").await;
+        let response = client
+            .call(
+                Method::POST,
+                "/api/me/timers",
+                Some(&token),
+                json!({"id": "added", "password": "wrong"}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+        let response = client
+            .call(
+                Method::POST,
+                "/api/me/timers",
+                Some(&token),
+                json!({"id": "added", "password": "password"}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+
+        let response = client
+            .call(Method::GET, "/api/me/timers", Some(&token), json!(null))
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let ids = response.body.as_array().unwrap().iter();
+        let ids = ids
+            .map(|timer| timer["id"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["owned", "added"]);
+        assert!(client
+            .call(Method::GET, "/api/me/timers", None, json!(null))
+            .await
+            .status
+            .is_client_error());
+
+        // the logout revokes the token which was used for it
+        let response = client
+            .call(Method::POST, "/api/me/logout", Some(&token), json!(null))
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let response = client
+            .call(Method::GET, "/api/me/timers", Some(&token), json!(null))
+            .await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_logout_while_adding_timers() {
+        let client = TestClient::new().await;
+        let account = json!({"username": "setter", "password": "secret"});
+        let response = client.call(Method::POST, "/api/user/", None, account).await;
+        let token = response.body["token"].as_str().unwrap().to_owned();
+
+        // timers are added by other requests while the user logs out
+        let (_, _, response) = tokio::join!(
+            add_timer_id(&client.state, "setter", "first"),
+            add_timer_id(&client.state, "setter", "second"),
+            client.call(Method::POST, "/api/me/logout", Some(&token), json!(null)),
+        );
+        assert_eq!(response.status, StatusCode::OK);
+
+        let user = client.state.repository.get_user("setter".to_owned()).await;
+        let user = user.unwrap();
+        assert_eq!(user.timer_ids, vec!["first", "second"]);
+        assert_eq!(user.token_generation, 1);
+
+        // the logout isn't undone by adding another timer afterwards
+        add_timer_id(&client.state, "setter", "third").await;
+        let response = client
+            .call(Method::GET, "/api/me/timers", Some(&token), json!(null))
+            .await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,288 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+
+use crate::audit::Action;
+use crate::models::*;
+use crate::repository::{Timer, Webhook};
+use crate::targets::check_url;
+use crate::validation::{FieldError, RequestError};
+use crate::webhooks::generate_secret;
+
+use super::history::Audit;
+
+async fn get_webhooks(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<WebhookResponse>>, StatusCode> {
+    let timer = state
+        .repository
+        .get_timer(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    let webhooks = timer
+        .webhooks
+        .into_iter()
+        .map(|webhook| WebhookResponse {
+            url: webhook.url,
+            secret: None,
+        })
+        .collect();
+
+    Ok(Json(webhooks))
+}
+
+async fn update_webhooks(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    audit: Audit,
+    Json(requests): Json<Vec<WebhookRequest>>,
+) -> Result<Json<Vec<WebhookResponse>>, RequestError> {
+    let mut errors = Vec::new();
+    for (index, request) in requests.iter().enumerate() {
+        if let Err(message) = check_url(&request.url, state.allow_private_targets).await {
+            errors.push(FieldError::new(format!("webhooks[{}].url", index), message));
+        }
+        if request.secret.as_deref() == Some("") {
+            errors.push(FieldError::new(
+                format!("webhooks[{}].secret", index),
+                "The secret can't be empty",
+            ));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(RequestError::Invalid(errors));
+    }
+
+    let old_timer = state
+        .repository
+        .get_timer(id)
+        .await
+        .map(Ok)
+        .unwrap_or(Err(StatusCode::UNAUTHORIZED))?;
+
+    let existing = |url: &str| old_timer.webhooks.iter().find(|w| w.url == url);
+    let webhooks = requests
+        .into_iter()
+        .map(|request| {
+            let secret = match (request.secret, existing(&request.url)) {
+                (Some(secret), _) => secret,
+                (None, Some(webhook)) => webhook.secret.clone(),
+                (None, None) => generate_secret(),
+            };
+            Webhook {
+                url: request.url,
+                secret,
+            }
+        })
+        .collect();
+
+    let mut timer = Timer {
+        webhooks,
+        ..old_timer.clone()
+    };
+
+    state
+        .repository
+        .update_timer(&mut timer)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+    audit
+        .record(&state, Action::Webhooks, Some(&old_timer), Some(&timer))
+        .await;
+
+    let response = timer
+        .webhooks
+        .into_iter()
+        .map(|webhook| WebhookResponse {
+            secret: existing(&webhook.url).is_none().then_some(webhook.secret),
+            url: webhook.url,
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
+pub fn routes() -> Router<SharedState> {
+    Router::new().route("/:id/webhooks", get(get_webhooks).put(update_webhooks))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use axum::http::{HeaderMap, Method};
+    use axum::routing::post;
+    use hmac::{Hmac, Mac};
+    use serde_json::{json, Value};
+    use sha2::Sha256;
+    use tokio::sync::mpsc;
+
+    use super::super::testing::{segment, TestClient};
+    use super::*;
+
+    /// Receives webhooks on a local port and passes their signature and payload on
+    async fn receiver() -> (String, mpsc::UnboundedReceiver<(String, String)>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: String| async move {
+                let signature = headers["X-Timer-Signature"].to_str().unwrap().to_owned();
+                tx.send((signature, body)).unwrap();
+                StatusCode::OK
+            }),
+        );
+
+        let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(app.into_make_service());
+        let url = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+        (url, rx)
+    }
+
+    /// The events received until no more arrive for a while
+    async fn received_events(rx: &mut mpsc::UnboundedReceiver<(String, String)>) -> Vec<String> {
+        let mut events = Vec::new();
+        while let Ok(Some((_, body))) =
+            tokio::time::timeout(Duration::from_millis(300), rx.recv()).await
+        {
+            let payload: Value = serde_json::from_str(&body).unwrap();
+            events.push(payload["event"].as_str().unwrap().to_owned());
+        }
+        events
+    }
+
+    async fn add_webhook(client: &TestClient, id: &str, token: &str, url: &str) {
+        let response = client
+            .call(
+                Method::PUT,
+                &format!("/api/timer/{}/webhooks", id),
+                Some(token),
+                json!([{"url": url}]),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_delivery() {
+        let client = TestClient::new().await;
+        let token = client.create_timer("test").await;
+        let (url, mut rx) = receiver().await;
+
+        let response = client
+            .call(
+                Method::PUT,
+                "/api/timer/test/webhooks",
+                Some(&token),
+                json!([{"url": url}]),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let secret = response.body[0]["secret"].as_str().unwrap().to_owned();
+
+        // the secret is only returned when the webhook is created
+        let response = client
+            .call(
+                Method::GET,
+                "/api/timer/test/webhooks",
+                Some(&token),
+                json!(null),
+            )
+            .await;
+        assert_eq!(response.body, json!([{"url": url}]));
+
+        let response = client
+            .call(
+                Method::POST,
+                "/api/timer/test/start",
+                Some(&token),
+                json!({"start_at": 1000}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+
+        let (signature, body) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("the webhook was not sent")
+            .unwrap();
+        let payload: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload["event"], "Started");
+        assert_eq!(payload["timer_id"], "test");
+        assert_eq!(payload["timer"]["start_at"], 1000);
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body.as_bytes());
+        let expected = hex::encode(mac.finalize().into_bytes());
+        assert_eq!(signature, format!("sha256={}", expected));
+        assert!(received_events(&mut rx).await.is_empty());
+
+        let response = client
+            .call(
+                Method::POST,
+                "/api/timer/test/stop",
+                Some(&token),
+                json!(null),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(received_events(&mut rx).await, vec!["Stopped"]);
+    }
+
+    #[tokio::test]
+    async fn test_group_webhook_events() {
+        let client = TestClient::new().await;
+        let token = client.create_timer("first").await;
+        let (url, mut rx) = receiver().await;
+        add_webhook(&client, "first", &token, &url).await;
+
+        let request = json!({
+            "id": "walls",
+            "password": "password",
+            "timers": [{"id": "first", "password": "password"}],
+        });
+        let response = client
+            .call(Method::POST, "/api/group/", None, request)
+            .await;
+        let group_token = response.body["token"].as_str().unwrap().to_owned();
+
+        // the same events as for controlling the timer itself
+        let response = client
+            .call(
+                Method::POST,
+                "/api/group/walls/start",
+                Some(&group_token),
+                json!({"start_at": 1000}),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(received_events(&mut rx).await, vec!["Started"]);
+
+        let response = client
+            .call(
+                Method::POST,
+                "/api/group/walls/stop",
+                Some(&group_token),
+                json!(null),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(received_events(&mut rx).await, vec!["Stopped"]);
+
+        let segments = json!({"segments": [segment("Final", 300000)], "repeat": false});
+        let response = client
+            .call(
+                Method::PUT,
+                "/api/group/walls/segments",
+                Some(&group_token),
+                segments,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(received_events(&mut rx).await, vec!["Updated"]);
+    }
+}
@@ -1,7 +1,7 @@
 use axum::{
     extract::{
         ws::{Message, WebSocket},
-        State, WebSocketUpgrade,
+        Query, State, WebSocketUpgrade,
     },
     response::IntoResponse,
     routing::get,
@@ -16,11 +16,12 @@ use futures::{
 use serde::{Deserialize, Serialize};
 use serde_json;
 
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::task::JoinHandle;
 
 use crate::{
-    repository::{Repository, Timer},
+    repository::TimerUpdate, timer_events::wait_for_next_events, timer_state::current_time,
     SharedState,
 };
 
@@ -28,12 +29,21 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::models::*;
 
+use super::timer::can_view;
+
+#[derive(Deserialize)]
+pub struct WsQuery {
+    /// needed to see private timers
+    token: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type", content = "data")]
 enum WSMessage {
     Hello(String),
     GetTime,
     Timer(WsTimerResponse),
+    Event(WsEventResponse),
     Timestamp(u128),
     Error((u128, String)),
 }
@@ -41,19 +51,22 @@ enum WSMessage {
 struct WsConnection {}
 
 impl WsConnection {
-    async fn new(state: SharedState, socket: WebSocket) {
+    async fn handle(state: SharedState, token: Option<String>, socket: WebSocket) {
         let (ws_sender, ws_receiver) = socket.split();
         let (ws_message_tx, ws_message_rx) = tokio::sync::mpsc::channel::<WSMessage>(32);
         let (redis_listen_id_tx, redis_listen_id_rx) = tokio::sync::mpsc::channel::<String>(32);
 
         let ws_sender_task = WsConnection::spawn_ws_sender_task(ws_sender, ws_message_rx);
         let ws_receiver_task = WsConnection::spawn_ws_receiver_task(
-            state.repository.clone(),
+            state.clone(),
+            token.clone(),
             ws_message_tx.clone(),
             redis_listen_id_tx,
             ws_receiver,
         );
         let redis_listener_task = WsConnection::spawn_redis_listener_task(
+            state.clone(),
+            token,
             ws_message_tx,
             redis_listen_id_rx,
             state.repository.updates_rx.resubscribe(),
@@ -66,24 +79,79 @@ impl WsConnection {
     }
 
     fn spawn_redis_listener_task(
+        state: SharedState,
+        token: Option<String>,
         ws_message_tx: Sender<WSMessage>,
         mut redis_listen_id_rx: Receiver<String>,
-        mut redis_task_rx: tokio::sync::broadcast::Receiver<Timer>,
+        mut redis_task_rx: tokio::sync::broadcast::Receiver<TimerUpdate>,
     ) -> JoinHandle<()> {
         tokio::spawn(async move {
-            let mut msg = None;
-            while msg.is_none() {
-                msg = redis_listen_id_rx.recv().await;
-            }
+            let timer_id = match redis_listen_id_rx.recv().await {
+                Some(timer_id) => timer_id,
+                None => return,
+            };
+            // only one timer is followed per connection
+            drop(redis_listen_id_rx);
+
+            let mut timer = state.repository.get_timer(timer_id.clone()).await;
+            let mut events_after = current_time();
+
+            loop {
+                tokio::select! {
+                    update = redis_task_rx.recv() => {
+                        let updated_timer = match update {
+                            Ok(TimerUpdate::Changed(updated_timer)) => *updated_timer,
+                            Ok(TimerUpdate::Deleted(id)) => {
+                                if id == timer_id {
+                                    timer = None;
+                                }
+                                continue;
+                            }
+                            // updates were missed, so the timer is read again
+                            Err(RecvError::Lagged(_)) => {
+                                match state.repository.get_timer(timer_id.clone()).await {
+                                    Some(stored_timer) => stored_timer,
+                                    None => {
+                                        timer = None;
+                                        continue;
+                                    }
+                                }
+                            }
+                            Err(RecvError::Closed) => break,
+                        };
+
+                        if updated_timer.id != timer_id {
+                            continue;
+                        }
+
+                        // the timer could have been made private in the meantime
+                        if !can_view(&state, &updated_timer, token.as_deref()).await {
+                            timer = None;
+                            continue;
+                        }
 
-            let timer_id = msg.unwrap();
+                        // events are only scheduled from now on, skipped ones are not sent
+                        events_after = current_time();
+                        timer = Some(updated_timer.clone());
 
-            while let Ok(timer) = redis_task_rx.recv().await {
-                if timer.id == timer_id {
-                    println!("Updated! {:?}", timer);
+                        let response = WSMessage::Timer(updated_timer.into());
+                        if ws_message_tx.send(response).await.is_err() {
+                            return;
+                        }
+                    }
+                    (time, events) = wait_for_next_events(timer.as_ref(), events_after) => {
+                        events_after = time;
 
-                    let response = WSMessage::Timer(timer.into());
-                    ws_message_tx.send(response).await.unwrap();
+                        for event in events {
+                            let response = WSMessage::Event(WsEventResponse {
+                                timestamp: time,
+                                event,
+                            });
+                            if ws_message_tx.send(response).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
                 }
             }
         })
@@ -95,30 +163,33 @@ impl WsConnection {
     ) -> JoinHandle<()> {
         tokio::spawn(async move {
             while let Some(msg) = rx.recv().await {
-                sender
-                    .send(serde_json::to_string(&msg).unwrap().into())
-                    .await
-                    .unwrap();
+                let msg = serde_json::to_string(&msg).unwrap();
+                // the client is gone
+                if sender.send(msg.into()).await.is_err() {
+                    break;
+                }
             }
         })
     }
 
     fn spawn_ws_receiver_task(
-        repository: Repository,
+        state: SharedState,
+        token: Option<String>,
         ws_message_tx: Sender<WSMessage>,
         redis_listen_id_tx: Sender<String>,
         ws_receiver: SplitStream<WebSocket>,
     ) -> JoinHandle<()> {
         tokio::spawn(async move {
             let mut message_handler =
-                WsMessageHandler::new(repository, ws_message_tx, redis_listen_id_tx, ws_receiver);
+                WsMessageHandler::new(state, token, ws_message_tx, redis_listen_id_tx, ws_receiver);
             message_handler.listen().await;
         })
     }
 }
 
 struct WsMessageHandler {
-    repository: Repository,
+    state: SharedState,
+    token: Option<String>,
     ws_message_tx: Sender<WSMessage>,
     redis_listen_id_tx: Sender<String>,
     ws_receiver: SplitStream<WebSocket>,
@@ -126,13 +197,15 @@ struct WsMessageHandler {
 
 impl WsMessageHandler {
     fn new(
-        repository: Repository,
+        state: SharedState,
+        token: Option<String>,
         ws_message_tx: Sender<WSMessage>,
         redis_listen_id_tx: Sender<String>,
         ws_receiver: SplitStream<WebSocket>,
     ) -> Self {
         WsMessageHandler {
-            repository,
+            state,
+            token,
             ws_message_tx,
             redis_listen_id_tx,
             ws_receiver,
@@ -143,9 +216,13 @@ impl WsMessageHandler {
         while let Some(msg) = self.ws_receiver.next().await {
             if let Ok(Message::Text(msg)) = msg {
                 println!("Received message: {:?}", msg);
-                let message: WSMessage = serde_json::from_str(&msg).unwrap();
-                let response = self.handle_message(message).await;
-                self.ws_message_tx.send(response).await.unwrap();
+                let response = match serde_json::from_str(&msg) {
+                    Ok(message) => self.handle_message(message).await,
+                    Err(_) => WSMessage::Error((400, "Invalid message".to_owned())),
+                };
+                if self.ws_message_tx.send(response).await.is_err() {
+                    break;
+                }
             }
         }
     }
@@ -172,9 +249,18 @@ impl WsMessageHandler {
             return WSMessage::Error((400, "Already said hello!".to_owned()));
         }
 
-        self.redis_listen_id_tx.send(id.clone()).await.unwrap();
+        let timer = self.state.repository.get_timer(id.clone()).await;
+        if let Some(timer) = &timer {
+            if !can_view(&self.state, timer, self.token.as_deref()).await {
+                return WSMessage::Error((401, "Timer is private!".to_owned()));
+            }
+        }
+
+        if self.redis_listen_id_tx.send(id).await.is_err() {
+            return WSMessage::Error((400, "Already said hello!".to_owned()));
+        }
 
-        self.repository.get_timer(id).await.map_or_else(
+        timer.map_or_else(
             || WSMessage::Error((404, "Timer not found!".to_owned())),
             |t| WSMessage::Timer(t.into()),
         )
@@ -183,9 +269,10 @@ impl WsMessageHandler {
 
 pub async fn ws_handler(
     State(state): State<SharedState>,
+    Query(query): Query<WsQuery>,
     ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| WsConnection::new(state, socket))
+    ws.on_upgrade(move |socket| WsConnection::handle(state, query.token, socket))
 }
 
 pub fn routes() -> Router<SharedState> {
@@ -0,0 +1,155 @@
+//! Checks the hosts webhooks and OSC messages are sent to, so timers can't be used
+//! to reach services in the network of the server.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+
+/// Whether the address is reachable from the internet, i.e. not a loopback,
+/// private, link-local or otherwise reserved address
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "this network", shared address space of carriers, protocol assignments,
+        // benchmarking and reserved
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // unique local, link-local and documentation
+        || (segments[0] & 0xfe00) == 0xfc00
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        // NAT64 can reach any ipv4 address
+        || (segments[0] == 0x64 && segments[1] == 0xff9b))
+}
+
+/// Resolves the host and checks all of its addresses, unless private targets are allowed.
+///
+/// Hosts with a single private address are rejected, as it could be used for any of the
+/// requests to them.
+pub async fn resolve(
+    host: &str,
+    port: u16,
+    allow_private: bool,
+) -> Result<Vec<SocketAddr>, String> {
+    let addresses = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("{} can't be resolved: {}", host, e))?
+        .collect::<Vec<_>>();
+
+    if !allow_private {
+        if let Some(address) = addresses.iter().find(|address| !is_public(address.ip())) {
+            return Err(format!(
+                "{} resolves to the private address {}",
+                host,
+                address.ip()
+            ));
+        }
+    }
+
+    Ok(addresses)
+}
+
+/// Checks that the url is a http(s) url of a host with public addresses
+pub async fn check_url(url: &str, allow_private: bool) -> Result<(), String> {
+    let url = reqwest::Url::parse(url).map_err(|e| format!("{} is not a valid url: {}", url, e))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!("{} is not a http or https url", url));
+    }
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| format!("{} has no host", url))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    // ipv6 addresses are in brackets in urls
+    resolve(
+        host.trim_start_matches('[').trim_end_matches(']'),
+        port,
+        allow_private,
+    )
+    .await
+    .map(|_| ())
+}
+
+/// Resolves hosts for reqwest like [`resolve`], so a host can't change to a private
+/// address between checking and using it
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_owned();
+        Box::pin(async move {
+            let addresses = resolve(&host, 0, false).await?;
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn test_is_public() {
+        assert!(public("1.1.1.1"));
+        assert!(public("2606:4700:4700::1111"));
+
+        assert!(!public("127.0.0.1"));
+        assert!(!public("10.1.2.3"));
+        assert!(!public("172.16.0.1"));
+        assert!(!public("192.168.1.1"));
+        assert!(!public("169.254.169.254"));
+        assert!(!public("100.64.0.1"));
+        assert!(!public("0.0.0.0"));
+        assert!(!public("::1"));
+        assert!(!public("fd00::1"));
+        assert!(!public("fe80::1"));
+        assert!(!public("::ffff:127.0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn test_check_url() {
+        assert!(check_url("http://127.0.0.1:8080/hook", false)
+            .await
+            .is_err());
+        assert!(check_url("http://[::1]/hook", false).await.is_err());
+        assert!(check_url("http://localhost/hook", false).await.is_err());
+        assert!(check_url("ftp://1.1.1.1/hook", false).await.is_err());
+        assert!(check_url("not a url", false).await.is_err());
+
+        assert!(check_url("http://127.0.0.1:8080/hook", true).await.is_ok());
+        assert!(check_url("https://1.1.1.1/hook", false).await.is_ok());
+    }
+}
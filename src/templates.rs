@@ -0,0 +1,56 @@
+//! The templates and sound presets which are built into every instance, so all clients
+//! can offer the same common formats.
+
+use serde::{Deserialize, Serialize};
+
+use crate::redis_migrations::RedisTemplate;
+use crate::repository::{Sound, Template};
+
+/// The sound files which are served with the web client, in `web/static/sound`
+pub const SOUND_FILES: &[&str] = &[
+    "beep-200.mp3",
+    "beep.mp3",
+    "boop.mp3",
+    "countdown.mp3",
+    "silence.mp3",
+];
+
+/// Sounds which are commonly used together for a segment
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SoundPreset {
+    pub id: String,
+    pub sounds: Vec<Sound>,
+}
+
+#[derive(Deserialize)]
+struct Builtins {
+    sound_presets: Vec<SoundPreset>,
+    templates: Vec<RedisTemplate>,
+}
+
+fn builtins() -> Builtins {
+    serde_json::from_str(include_str!("builtin_templates.json")).unwrap()
+}
+
+pub fn builtin_templates() -> Vec<Template> {
+    builtins().templates.into_iter().map(|t| t.into()).collect()
+}
+
+pub fn sound_presets() -> Vec<SoundPreset> {
+    builtins().sound_presets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtins_can_be_parsed() {
+        let templates = builtin_templates();
+        assert!(!templates.is_empty());
+        assert!(templates
+            .iter()
+            .all(|template| !template.sequences.is_empty()));
+        assert!(!sound_presets().is_empty());
+    }
+}
@@ -0,0 +1,550 @@
+//! Schedules the events happening while a timer runs, based on the timer state engine.
+//! All times are in milliseconds.
+
+use std::{collections::HashMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+
+use crate::{
+    repository::{playlist_segments, PreStartBehaviour, Repository, Segment, Timer, TimerUpdate},
+    timer_state::{current_time, elapsed_at, playlist_time, round_duration},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "event")]
+pub enum TimerEvent {
+    SegmentStarted {
+        segment_index: usize,
+        label: String,
+    },
+    SegmentEnded {
+        segment_index: usize,
+        label: String,
+    },
+    /// `round` is the number of completed rounds
+    RoundCompleted {
+        round: u64,
+    },
+    TimerFinished,
+    SoundTrigger {
+        segment_index: usize,
+        filename: String,
+        trigger_time: u32,
+    },
+}
+
+/// Offset from the start of the segment at which the clients play a sound.
+///
+/// The clients play it as soon as the shown seconds equal its `trigger_time`,
+/// so it is never played if the segment doesn't show that second at all.
+fn sound_offset(segment: &Segment, trigger_time: u32) -> Option<u64> {
+    let time = segment.time as u64;
+    let count_to = segment.count_to as u64;
+    let trigger_time = trigger_time as u64 * 1000;
+
+    if time == 0 || trigger_time + 999 < count_to || trigger_time >= count_to + time {
+        return None;
+    }
+
+    // remaining time at the first moment the display shows the trigger second
+    let remaining = (trigger_time + 999 - count_to).min(time - 1);
+    Some(time - remaining)
+}
+
+/// All events of the round `round`, ordered by the time they happen at
+fn round_events(timer: &Timer, round: i64) -> Vec<(i64, TimerEvent)> {
+    // the events happen at times in the playlist, which are moved by extended segments
+    let at = |playlist_time: i64| timer.start_at as i64 + elapsed_at(timer, playlist_time);
+
+    let mut events = Vec::new();
+    let mut segment_start = round * round_duration(&timer.sequences) as i64;
+
+    for (segment_index, segment) in playlist_segments(&timer.sequences).enumerate() {
+        let segment_end = segment_start + segment.time as i64;
+
+        events.push((
+            at(segment_start),
+            TimerEvent::SegmentStarted {
+                segment_index,
+                label: segment.label.clone(),
+            },
+        ));
+
+        // sounds are played at a remaining time, so they move with the end of the segment
+        let mut sounds = segment
+            .sounds
+            .iter()
+            .filter_map(|sound| {
+                sound_offset(segment, sound.trigger_time).map(|offset| {
+                    (
+                        at(segment_end) - (segment.time as u64 - offset) as i64,
+                        TimerEvent::SoundTrigger {
+                            segment_index,
+                            filename: sound.filename.clone(),
+                            trigger_time: sound.trigger_time,
+                        },
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        sounds.sort_by_key(|(time, _)| *time);
+        events.append(&mut sounds);
+
+        events.push((
+            at(segment_end),
+            TimerEvent::SegmentEnded {
+                segment_index,
+                label: segment.label.clone(),
+            },
+        ));
+
+        segment_start = segment_end;
+    }
+
+    events.push((
+        at(segment_start),
+        TimerEvent::RoundCompleted {
+            round: (round + 1) as u64,
+        },
+    ));
+
+    if !timer.repeat {
+        events.push((at(segment_start), TimerEvent::TimerFinished));
+    }
+
+    events
+}
+
+/// Returns the time of the next events happening after `after` and the events themselves.
+///
+/// Returns `None` if nothing happens anymore, e.g. because the timer was stopped.
+pub fn next_events(timer: &Timer, after: u64) -> Option<(u64, Vec<TimerEvent>)> {
+    let total_time_per_round = round_duration(&timer.sequences) as i64;
+    if total_time_per_round == 0 {
+        return None;
+    }
+
+    let elapsed_time = after as i64 - timer.start_at as i64;
+    let mut playlist_time = playlist_time(timer, elapsed_time);
+    // during an extension, the events at the end of the extended segment are still to come
+    if playlist_time < elapsed_time && elapsed_at(timer, playlist_time) > elapsed_time {
+        playlist_time -= 1;
+    }
+
+    let mut round = playlist_time.div_euclid(total_time_per_round);
+    if round < 0 && timer.display_options.pre_start_behaviour != PreStartBehaviour::RunNormally {
+        round = 0;
+    }
+    if !timer.repeat && round > 0 {
+        return None;
+    }
+
+    let mut events = round_events(timer, round);
+    if timer.repeat {
+        events.append(&mut round_events(timer, round + 1));
+    }
+
+    let mut events = events
+        .into_iter()
+        .filter(|(time, _)| *time > after as i64)
+        .filter(|(time, _)| !matches!(timer.stop_at, Some(stop_at) if *time > stop_at as i64))
+        .peekable();
+
+    let (next_time, _) = events.peek()?.clone();
+    let next_events = events
+        .take_while(|(time, _)| *time == next_time)
+        .map(|(_, event)| event)
+        .collect();
+
+    Some((next_time as u64, next_events))
+}
+
+/// Waits until the next events after `after` are due and returns them.
+///
+/// Never returns if nothing happens anymore, so it is meant to be used in `tokio::select!`
+/// together with the updates of the timer.
+pub async fn wait_for_next_events(timer: Option<&Timer>, after: u64) -> (u64, Vec<TimerEvent>) {
+    let next = match timer.and_then(|timer| next_events(timer, after)) {
+        Some(next) => next,
+        None => return futures::future::pending().await,
+    };
+
+    let delay = next.0.saturating_sub(current_time());
+    tokio::time::sleep(Duration::from_millis(delay)).await;
+
+    next
+}
+
+pub enum TrackedTimerUpdate {
+    /// The timer was created or changed, or was already stored when tracking started
+    Updated(Timer),
+    /// Events of the timer are due
+    Events(Timer, u64, Vec<TimerEvent>),
+    /// The timer was deleted or doesn't match the filter anymore
+    Removed(String),
+}
+
+/// Tracks all timers matching `filter`, starting with the stored ones,
+/// and reports their updates and events as they happen.
+///
+/// Used by the integrations which have to follow many timers at once.
+pub fn track_timers(
+    repository: Repository,
+    filter: impl Fn(&Timer) -> bool + Send + 'static,
+) -> mpsc::Receiver<TrackedTimerUpdate> {
+    let (tracker_tx, tracker_rx) = mpsc::channel(32);
+    let mut updates_rx = repository.updates_rx.resubscribe();
+
+    tokio::spawn(async move {
+        // the tracked timers and the time their events were reported until
+        let mut timers: HashMap<String, (Timer, u64)> = HashMap::new();
+
+        // subscribed before, so no change after reading the stored timers is missed
+        for timer in repository.get_timers().await {
+            if !filter(&timer) {
+                continue;
+            }
+            timers.insert(timer.id.clone(), (timer.clone(), current_time()));
+            if tracker_tx
+                .send(TrackedTimerUpdate::Updated(timer))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+
+        loop {
+            let next = timers
+                .values()
+                .filter_map(|(timer, after)| next_events(timer, *after))
+                .map(|(time, _)| time)
+                .min();
+
+            let sleep = async {
+                match next {
+                    Some(time) => {
+                        let delay = time.saturating_sub(current_time());
+                        tokio::time::sleep(Duration::from_millis(delay)).await
+                    }
+                    None => futures::future::pending().await,
+                }
+            };
+
+            let update = tokio::select! {
+                update = updates_rx.recv() => match update {
+                    Ok(TimerUpdate::Changed(timer)) => {
+                        let timer = *timer;
+                        let outdated = matches!(timers.get(&timer.id),
+                            Some((tracked, _)) if tracked.revision > timer.revision);
+                        if outdated {
+                            // changed before the stored timers were read
+                            vec![]
+                        } else if filter(&timer) {
+                            timers.insert(timer.id.clone(), (timer.clone(), current_time()));
+                            vec![TrackedTimerUpdate::Updated(timer)]
+                        } else if timers.remove(&timer.id).is_some() {
+                            vec![TrackedTimerUpdate::Removed(timer.id)]
+                        } else {
+                            vec![]
+                        }
+                    }
+                    Ok(TimerUpdate::Deleted(id)) => {
+                        if timers.remove(&id).is_some() {
+                            vec![TrackedTimerUpdate::Removed(id)]
+                        } else {
+                            vec![]
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => {
+                        // updates were missed, so the tracked timers are compared with the stored ones
+                        let stored = repository
+                            .get_timers()
+                            .await
+                            .into_iter()
+                            .filter(|timer| filter(timer))
+                            .map(|timer| (timer.id.clone(), timer))
+                            .collect::<HashMap<_, _>>();
+
+                        let removed = timers
+                            .keys()
+                            .filter(|id| !stored.contains_key(*id))
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        let mut updates = vec![];
+                        for id in removed {
+                            timers.remove(&id);
+                            updates.push(TrackedTimerUpdate::Removed(id));
+                        }
+                        for (id, timer) in stored {
+                            let unchanged = matches!(timers.get(&id),
+                                Some((tracked, _)) if tracked.revision == timer.revision);
+                            if !unchanged {
+                                timers.insert(id, (timer.clone(), current_time()));
+                                updates.push(TrackedTimerUpdate::Updated(timer));
+                            }
+                        }
+                        updates
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = sleep => {
+                    let due = next.unwrap();
+                    let due_ids = timers
+                        .iter()
+                        .filter(|(_, (timer, after))| {
+                            matches!(next_events(timer, *after), Some((time, _)) if time <= due)
+                        })
+                        .map(|(id, _)| id.clone())
+                        .collect::<Vec<_>>();
+
+                    let mut updates = vec![];
+                    for id in due_ids {
+                        let (timer, after) = timers.remove(&id).unwrap();
+                        let (time, events) = next_events(&timer, after).unwrap();
+                        timers.insert(id, (timer.clone(), time));
+                        updates.push(TrackedTimerUpdate::Events(timer, time, events));
+                    }
+                    updates
+                }
+            };
+
+            for update in update {
+                if tracker_tx.send(update).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    tracker_rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{MemoryStorage, SegmentExtension, Sound, UPDATES_CAPACITY};
+
+    fn segment(label: &str, time: u32, count_to: u32, sounds: Vec<Sound>) -> Segment {
+        Segment {
+            label: label.to_owned(),
+            time,
+            color: None,
+            count_to,
+            sounds,
+        }
+    }
+
+    fn sound(filename: &str, trigger_time: u32) -> Sound {
+        Sound {
+            filename: filename.to_owned(),
+            trigger_time,
+        }
+    }
+
+    fn timer(repeat: bool) -> Timer {
+        Timer {
+            sequences: vec![vec![
+                segment("Boulder", 240000, 0, vec![sound("beep.mp3", 60)]),
+                segment("Change", 15000, 1000, vec![sound("countdown.mp3", 5)]),
+            ]
+            .into()],
+            repeat,
+            start_at: 1000000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sound_offset() {
+        let boulder = segment("Boulder", 240000, 0, vec![]);
+        assert_eq!(sound_offset(&boulder, 60), Some(240000 - 60999));
+        assert_eq!(sound_offset(&boulder, 239), Some(1));
+        assert_eq!(sound_offset(&boulder, 240), None);
+        assert_eq!(sound_offset(&boulder, 0), Some(240000 - 999));
+
+        let change = segment("Change", 15000, 11000, vec![]);
+        assert_eq!(sound_offset(&change, 12), Some(15000 - 1999));
+        assert_eq!(sound_offset(&change, 10), None);
+    }
+
+    #[test]
+    fn test_next_events() {
+        let timer = timer(true);
+
+        let (time, events) = next_events(&timer, 0).unwrap();
+        assert_eq!(time, 1000000);
+        assert_eq!(
+            events,
+            vec![TimerEvent::SegmentStarted {
+                segment_index: 0,
+                label: "Boulder".to_owned()
+            }]
+        );
+
+        let (time, events) = next_events(&timer, 1000000).unwrap();
+        assert_eq!(time, 1000000 + 240000 - 60999);
+        assert_eq!(
+            events,
+            vec![TimerEvent::SoundTrigger {
+                segment_index: 0,
+                filename: "beep.mp3".to_owned(),
+                trigger_time: 60
+            }]
+        );
+
+        let (time, events) = next_events(&timer, 1000000 + 200000).unwrap();
+        assert_eq!(time, 1000000 + 240000);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            TimerEvent::SegmentEnded {
+                segment_index: 0,
+                ..
+            }
+        ));
+        assert!(matches!(
+            events[1],
+            TimerEvent::SegmentStarted {
+                segment_index: 1,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_round_completed() {
+        let (time, events) = next_events(&timer(true), 1000000 + 252000).unwrap();
+        assert_eq!(time, 1000000 + 255000);
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            events[0],
+            TimerEvent::SegmentEnded {
+                segment_index: 1,
+                ..
+            }
+        ));
+        assert_eq!(events[1], TimerEvent::RoundCompleted { round: 1 });
+        assert!(matches!(
+            events[2],
+            TimerEvent::SegmentStarted {
+                segment_index: 0,
+                ..
+            }
+        ));
+
+        let (time, events) = next_events(&timer(false), 1000000 + 252000).unwrap();
+        assert_eq!(time, 1000000 + 255000);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1], TimerEvent::RoundCompleted { round: 1 });
+        assert_eq!(events[2], TimerEvent::TimerFinished);
+
+        assert!(next_events(&timer(false), 1000000 + 255000).is_none());
+    }
+
+    #[test]
+    fn test_stopped() {
+        let mut timer = timer(true);
+        timer.stop_at = Some(1000000 + 200000);
+
+        assert!(next_events(&timer, 1000000 + 190000).is_none());
+
+        let (time, _) = next_events(&timer, 1000000).unwrap();
+        assert_eq!(time, 1000000 + 240000 - 60999);
+    }
+
+    #[test]
+    fn test_extended_segment() {
+        let mut timer = timer(true);
+        timer.extension = Some(SegmentExtension {
+            start: 240000,
+            end: 255000,
+            time: 10000,
+        });
+
+        // the countdown moves with the end of the extended segment
+        let (time, events) = next_events(&timer, 1000000 + 256000).unwrap();
+        assert_eq!(time, 1000000 + 260001);
+        assert!(matches!(events[0], TimerEvent::SoundTrigger { .. }));
+
+        let (time, events) = next_events(&timer, 1000000 + 261000).unwrap();
+        assert_eq!(time, 1000000 + 265000);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1], TimerEvent::RoundCompleted { round: 1 });
+
+        // the next round starts later
+        let (time, _) = next_events(&timer, 1000000 + 265000).unwrap();
+        assert_eq!(time, 1000000 + 265000 + 240000 - 60999);
+    }
+
+    #[tokio::test]
+    async fn test_track_stored_timers() {
+        let repository = Repository::new(MemoryStorage::new(None).await);
+        let stored = Timer {
+            id: "stored".to_owned(),
+            ..timer(true)
+        };
+        repository.create_timer(&stored).await.unwrap();
+        repository
+            .create_timer(&Timer {
+                id: "ignored".to_owned(),
+                ..timer(false)
+            })
+            .await
+            .unwrap();
+
+        let mut tracker_rx = track_timers(repository.clone(), |timer| timer.repeat);
+        match tracker_rx.recv().await {
+            Some(TrackedTimerUpdate::Updated(timer)) => assert_eq!(timer.id, stored.id),
+            _ => panic!("The stored timer is not tracked"),
+        }
+
+        repository.delete_timer("stored".to_owned()).await.unwrap();
+        match tracker_rx.recv().await {
+            Some(TrackedTimerUpdate::Removed(id)) => assert_eq!(id, "stored"),
+            _ => panic!("The deleted timer is not removed"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_track_missed_updates() {
+        let repository = Repository::new(MemoryStorage::new(None).await);
+        let deleted = Timer {
+            id: "deleted".to_owned(),
+            ..timer(true)
+        };
+        repository.create_timer(&deleted).await.unwrap();
+
+        let mut tracker_rx = track_timers(repository.clone(), |timer| timer.repeat);
+        assert!(matches!(
+            tracker_rx.recv().await,
+            Some(TrackedTimerUpdate::Updated(_))
+        ));
+
+        // the tracker waits until its updates are received, so the deletion
+        // is dropped from the updates by the ones after it
+        let mut busy = Timer {
+            id: "busy".to_owned(),
+            ..timer(true)
+        };
+        repository.create_timer(&busy).await.unwrap();
+        for _ in 0..100 {
+            repository.update_timer(&mut busy).await.unwrap();
+        }
+        tokio::task::yield_now().await;
+        repository.delete_timer("deleted".to_owned()).await.unwrap();
+        for _ in 0..UPDATES_CAPACITY + 100 {
+            repository.update_timer(&mut busy).await.unwrap();
+        }
+
+        loop {
+            let update = tokio::time::timeout(Duration::from_secs(5), tracker_rx.recv()).await;
+            match update {
+                Ok(Some(TrackedTimerUpdate::Removed(id))) if id == "deleted" => break,
+                Ok(Some(_)) => (),
+                _ => panic!("The deleted timer is not removed"),
+            }
+        }
+    }
+}
@@ -0,0 +1,849 @@
+//! Server side port of the timing logic in `web/src/utils/timer.ts`
+//! and `display/src/timer.cpp`. All times are in milliseconds.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::{
+    color::Color,
+    repository::{segment_count, PreStartBehaviour, Segment, SegmentExtension, Sequence, Timer},
+};
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum TimerStatus {
+    Running,
+    Waiting,
+    Finished,
+    Stopped,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TimerState {
+    pub status: TimerStatus,
+    /// number of completed rounds, only increases for repeating timers
+    pub round: u64,
+    /// index in the segments of the whole playlist, see [`Timer::segments`]
+    pub segment_index: usize,
+    pub sequence_index: usize,
+    pub sequence_name: String,
+    pub label: String,
+    pub color: Option<Color>,
+    pub time_in_round: u64,
+    /// time left until the current segment ends
+    pub time_remaining: u64,
+    /// the time shown on displays, `time_remaining` plus the `count_to` of the segment
+    pub display_time: u64,
+}
+
+pub fn current_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Back to the Past lul")
+        .as_millis() as u64
+}
+
+fn segments_duration(segments: &[Segment]) -> u64 {
+    segments.iter().map(|s| s.time as u64).sum()
+}
+
+/// The duration of one run through the playlist
+pub fn round_duration(sequences: &[Sequence]) -> u64 {
+    sequences
+        .iter()
+        .map(|sequence| segments_duration(&sequence.segments) * sequence.repetitions as u64)
+        .sum()
+}
+
+/// Finds the segment which is active at `time_in_round` and the time left in it.
+/// The repetitions of a sequence are skipped by their duration, so long playlists
+/// don't have to be unrolled.
+///
+/// Like the clients, a segment is active from right after its start until (including)
+/// its end, so at the exact end of a segment, that segment is still shown with 0 remaining.
+pub fn segment_at(sequences: &[Sequence], time_in_round: u64) -> (usize, u64) {
+    let mut sequence_start = 0;
+    let mut segment_offset = 0;
+    for sequence in sequences {
+        let duration = segments_duration(&sequence.segments);
+        let sequence_end = sequence_start + duration * sequence.repetitions as u64;
+
+        if duration > 0 && sequence.repetitions > 0 && time_in_round <= sequence_end {
+            let time = time_in_round.saturating_sub(sequence_start);
+            // the exact end of a repetition still belongs to it
+            let repetition = time.saturating_sub(1) / duration;
+            let time = time - repetition * duration;
+
+            let mut segment_end = 0;
+            for (index, segment) in sequence.segments.iter().enumerate() {
+                segment_end += segment.time as u64;
+                if time <= segment_end && (time > 0 || segment.time > 0) {
+                    let repetition_offset = repetition as usize * sequence.segments.len();
+                    return (
+                        segment_offset + repetition_offset + index,
+                        segment_end - time,
+                    );
+                }
+            }
+        }
+
+        sequence_start = sequence_end;
+        segment_offset += sequence.segments.len() * sequence.repetitions as usize;
+    }
+
+    (segment_count(sequences).saturating_sub(1) as usize, 0)
+}
+
+/// Returns the time since the start of the timer, up to the time it was stopped at
+fn elapsed_time(timer: &Timer, current_time: u64) -> (i64, bool) {
+    match timer.stop_at {
+        Some(stop_at) if stop_at < current_time => (stop_at as i64 - timer.start_at as i64, true),
+        _ => (current_time as i64 - timer.start_at as i64, false),
+    }
+}
+
+/// Maps the time since the start of the timer to the time in its playlist.
+///
+/// The playlist stands still at the end of an extended segment until the extension is over.
+pub fn playlist_time(timer: &Timer, elapsed_time: i64) -> i64 {
+    let extension = match &timer.extension {
+        Some(extension) => extension,
+        None => return elapsed_time,
+    };
+
+    let end = extension.end as i64;
+    let time = extension.time as i64;
+    if elapsed_time > end + time {
+        elapsed_time - time
+    } else {
+        elapsed_time.min(end)
+    }
+}
+
+/// Maps the time in the playlist to the time since the start of the timer,
+/// everything from the end of an extended segment on happens later
+pub fn elapsed_at(timer: &Timer, playlist_time: i64) -> i64 {
+    match &timer.extension {
+        Some(extension) if playlist_time >= extension.end as i64 => {
+            playlist_time + extension.time as i64
+        }
+        _ => playlist_time,
+    }
+}
+
+/// The time added to the current segment which is still left
+fn extension_left(timer: &Timer, elapsed_time: i64) -> u64 {
+    match &timer.extension {
+        Some(extension) if elapsed_time > extension.start as i64 => {
+            let left = (extension.end + extension.time) as i64 - elapsed_time;
+            left.clamp(0, extension.time as i64) as u64
+        }
+        _ => 0,
+    }
+}
+
+/// Returns the time in the current round and the completed rounds
+fn time_in_round(timer: &Timer, current_time: u64) -> (u64, u64, TimerStatus) {
+    let (elapsed_time, stopped) = elapsed_time(timer, current_time);
+    let total_time_per_round = round_duration(&timer.sequences) as i64;
+
+    if elapsed_time < 0 {
+        // the clients use 1 instead of 0 to land in the first segment
+        match timer.display_options.pre_start_behaviour {
+            PreStartBehaviour::ShowFirstSegment => return (1, 0, TimerStatus::Waiting),
+            PreStartBehaviour::ShowLastSegment => {
+                return (total_time_per_round as u64, 0, TimerStatus::Waiting)
+            }
+            PreStartBehaviour::RunNormally => (),
+        }
+    }
+
+    // the extended segment is shown until its extension is over, even at the end of a round
+    let extending = extension_left(timer, elapsed_time) > 0;
+    let elapsed_time = playlist_time(timer, elapsed_time);
+
+    if !timer.repeat && elapsed_time > total_time_per_round {
+        let status = if stopped {
+            TimerStatus::Stopped
+        } else {
+            TimerStatus::Finished
+        };
+        return (total_time_per_round as u64, 0, status);
+    }
+
+    let status = if stopped {
+        TimerStatus::Stopped
+    } else {
+        TimerStatus::Running
+    };
+    let mut round = if timer.repeat && elapsed_time > 0 {
+        elapsed_time / total_time_per_round
+    } else {
+        0
+    };
+    let mut time_in_round = elapsed_time.rem_euclid(total_time_per_round);
+    if extending && time_in_round == 0 {
+        time_in_round = total_time_per_round;
+        round = (round - 1).max(0);
+    }
+
+    (time_in_round as u64, round as u64, status)
+}
+
+/// Calculates the state of the timer at `current_time`.
+///
+/// Returns `None` if the playlist has no segments or all of them are empty.
+pub fn calculate_state(timer: &Timer, current_time: u64) -> Option<TimerState> {
+    if round_duration(&timer.sequences) == 0 {
+        return None;
+    }
+
+    let (time_in_round, round, status) = time_in_round(timer, current_time);
+    let (segment_index, mut time_remaining) = segment_at(&timer.sequences, time_in_round);
+    if status != TimerStatus::Waiting {
+        time_remaining += extension_left(timer, elapsed_time(timer, current_time).0);
+    }
+    let segment = timer.segment(segment_index)?;
+    let sequence_index = timer.sequence_index(segment_index)?;
+
+    Some(TimerState {
+        status,
+        round,
+        segment_index,
+        sequence_index,
+        sequence_name: timer.sequences[sequence_index].name.clone(),
+        label: segment.label.clone(),
+        color: segment.color.clone(),
+        time_in_round,
+        time_remaining,
+        display_time: time_remaining + segment.count_to as u64,
+    })
+}
+
+/// The current segment of a running or stopped timer, in ms after `start_at`
+struct CurrentSegment {
+    elapsed_time: i64,
+    /// the time in the playlist, which differs from the elapsed time after an extension
+    playlist_time: i64,
+    start: i64,
+    end: i64,
+    index: usize,
+    round: u64,
+    /// including the time added to the segment
+    time_remaining: u64,
+}
+
+fn current_segment(timer: &Timer, current_time: u64) -> Option<CurrentSegment> {
+    let state = calculate_state(timer, current_time)?;
+    if state.status != TimerStatus::Running && state.status != TimerStatus::Stopped {
+        return None;
+    }
+
+    let (elapsed_time, _) = elapsed_time(timer, current_time);
+    let playlist_time = playlist_time(timer, elapsed_time);
+    let (_, segment_left) = segment_at(&timer.sequences, state.time_in_round);
+    let end = playlist_time + segment_left as i64;
+
+    Some(CurrentSegment {
+        elapsed_time,
+        playlist_time,
+        start: end - timer.segment(state.segment_index)?.time as i64,
+        end,
+        index: state.segment_index,
+        round: state.round,
+        time_remaining: state.time_remaining,
+    })
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SkipDirection {
+    Next,
+    Previous,
+}
+
+/// Moves the timer to the start of the next or previous segment and drops the time
+/// added to the current one. Going back from the first segment of the first round restarts it.
+///
+/// Returns `false` if the timer is neither running nor stopped, or would have to start
+/// before 0 to be at the target segment.
+pub fn skip(timer: &mut Timer, current_time: u64, direction: SkipDirection) -> bool {
+    let current = match current_segment(timer, current_time) {
+        Some(current) => current,
+        None => return false,
+    };
+
+    // land 1ms after the start of the target segment, as the end of a segment still
+    // belongs to it. Otherwise a stopped timer would keep showing the old segment.
+    let target = match direction {
+        SkipDirection::Next => current.end + 1,
+        SkipDirection::Previous if current.index == 0 && current.round == 0 => current.start + 1,
+        SkipDirection::Previous => {
+            let previous_index = match current.index.checked_sub(1) {
+                Some(index) => index,
+                None => segment_count(&timer.sequences) as usize - 1,
+            };
+            let previous = timer
+                .segment(previous_index)
+                .map_or(0, |segment| segment.time);
+            current.start - previous as i64 + 1
+        }
+    };
+
+    let start_at = timer.start_at as i64 + current.elapsed_time - target;
+    if start_at < 0 {
+        return false;
+    }
+
+    timer.start_at = start_at as u64;
+    timer.extension = None;
+    true
+}
+
+/// Adds `offset` to the time remaining in the current segment, the segments after it
+/// start that much later. Removing more time than remaining ends the segment.
+///
+/// Returns `false` if the timer is neither running nor stopped, hasn't started yet
+/// or would have to start before 0.
+pub fn adjust(timer: &mut Timer, current_time: u64, offset: i64) -> bool {
+    // an extension of an earlier segment is the same as starting that much later
+    if let Some(extension) = timer.extension.clone() {
+        if elapsed_time(timer, current_time).0 > (extension.end + extension.time) as i64 {
+            timer.start_at += extension.time;
+            timer.extension = None;
+        }
+    }
+
+    let current = match current_segment(timer, current_time) {
+        Some(current) if current.elapsed_time >= 0 => current,
+        _ => return false,
+    };
+
+    let time_remaining = (current.time_remaining as i64 + offset).max(0);
+    let segment_left = current.end - current.playlist_time;
+    if time_remaining >= segment_left {
+        // includes the part of the extension which is already over
+        let time = current.elapsed_time + time_remaining - current.end;
+        timer.extension = (time > 0).then(|| SegmentExtension {
+            start: current.start.max(0) as u64,
+            end: current.end as u64,
+            time: time as u64,
+        });
+    } else {
+        timer.start_at = match timer
+            .start_at
+            .checked_sub((segment_left - time_remaining) as u64)
+        {
+            Some(start_at) => start_at,
+            None => return false,
+        };
+        timer.extension = None;
+    }
+
+    true
+}
+
+/// Starts the timer at `start_at`
+pub fn start(timer: &mut Timer, start_at: u64) {
+    timer.start_at = start_at;
+    timer.stop_at = None;
+    timer.paused_time = 0;
+    timer.extension = None;
+}
+
+/// Stops the timer and resets it to the start of its first segment
+pub fn stop(timer: &mut Timer, current_time: u64) {
+    timer.start_at = current_time;
+    timer.stop_at = Some(current_time);
+    timer.paused_time = 0;
+    timer.extension = None;
+}
+
+/// Pauses the timer. Returns `false` if it is already paused.
+pub fn pause(timer: &mut Timer, current_time: u64) -> bool {
+    if matches!(timer.stop_at, Some(stop_at) if stop_at <= current_time) {
+        return false;
+    }
+
+    timer.stop_at = Some(current_time);
+    true
+}
+
+/// Resumes the timer exactly where it was paused. Returns `false` if it isn't paused.
+pub fn resume(timer: &mut Timer, current_time: u64) -> bool {
+    let stop_at = match timer.stop_at {
+        Some(stop_at) if stop_at <= current_time => stop_at,
+        _ => return false,
+    };
+
+    // shift the start, so the timer continues where it was paused
+    let paused_for = current_time - stop_at;
+    timer.start_at += paused_for;
+    timer.paused_time += paused_for;
+    timer.stop_at = None;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{DisplayOptions, Sequence};
+
+    fn segment(label: &str, time: u32, count_to: u32) -> Segment {
+        Segment {
+            label: label.to_owned(),
+            time,
+            color: None,
+            count_to,
+            sounds: vec![],
+        }
+    }
+
+    fn timer(repeat: bool, pre_start_behaviour: PreStartBehaviour) -> Timer {
+        Timer {
+            sequences: vec![vec![
+                segment("Boulder", 240000, 0),
+                segment("Change", 15000, 1000),
+            ]
+            .into()],
+            repeat,
+            display_options: DisplayOptions {
+                clock: false,
+                pre_start_behaviour,
+            },
+            start_at: 1000000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_running() {
+        let timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+
+        let state = calculate_state(&timer, 1000000 + 10000).unwrap();
+        assert_eq!(state.status, TimerStatus::Running);
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.label, "Boulder");
+        assert_eq!(state.time_remaining, 230000);
+        assert_eq!(state.display_time, 230000);
+
+        let state = calculate_state(&timer, 1000000 + 245000).unwrap();
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.time_remaining, 10000);
+        assert_eq!(state.display_time, 11000);
+    }
+
+    #[test]
+    fn test_segment_boundaries() {
+        let timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+
+        let state = calculate_state(&timer, 1000000 + 240000).unwrap();
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 0);
+
+        let state = calculate_state(&timer, 1000000 + 240001).unwrap();
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.time_remaining, 14999);
+    }
+
+    #[test]
+    fn test_repeat() {
+        let timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+
+        let state = calculate_state(&timer, 1000000 + 2 * 255000 + 5000).unwrap();
+        assert_eq!(state.status, TimerStatus::Running);
+        assert_eq!(state.round, 2);
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 235000);
+    }
+
+    #[test]
+    fn test_finished() {
+        let timer = timer(false, PreStartBehaviour::ShowFirstSegment);
+
+        let state = calculate_state(&timer, 1000000 + 300000).unwrap();
+        assert_eq!(state.status, TimerStatus::Finished);
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.time_remaining, 0);
+        assert_eq!(state.display_time, 1000);
+    }
+
+    #[test]
+    fn test_waiting() {
+        let state = calculate_state(&timer(true, PreStartBehaviour::ShowFirstSegment), 0).unwrap();
+        assert_eq!(state.status, TimerStatus::Waiting);
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 239999);
+
+        let state = calculate_state(&timer(true, PreStartBehaviour::ShowLastSegment), 0).unwrap();
+        assert_eq!(state.status, TimerStatus::Waiting);
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.time_remaining, 0);
+
+        let state =
+            calculate_state(&timer(true, PreStartBehaviour::RunNormally), 1000000 - 5000).unwrap();
+        assert_eq!(state.status, TimerStatus::Running);
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.time_remaining, 5000);
+    }
+
+    #[test]
+    fn test_stopped() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        timer.stop_at = Some(1000000 + 60000);
+
+        let state = calculate_state(&timer, 1000000 + 30000).unwrap();
+        assert_eq!(state.status, TimerStatus::Running);
+        assert_eq!(state.time_remaining, 210000);
+
+        let state = calculate_state(&timer, 1000000 + 120000).unwrap();
+        assert_eq!(state.status, TimerStatus::Stopped);
+        assert_eq!(state.time_remaining, 180000);
+    }
+
+    #[test]
+    fn test_playlist() {
+        let mut timer = timer(false, PreStartBehaviour::ShowFirstSegment);
+        timer.sequences = vec![
+            Sequence {
+                name: "Warm-up".to_owned(),
+                segments: vec![segment("Warm-up", 10000, 0)],
+                repetitions: 2,
+            },
+            Sequence {
+                name: "Finals".to_owned(),
+                segments: vec![segment("Boulder", 5000, 0)],
+                repetitions: 1,
+            },
+        ];
+
+        let state = calculate_state(&timer, 1000000 + 15000).unwrap();
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.sequence_index, 0);
+        assert_eq!(state.sequence_name, "Warm-up");
+        assert_eq!(state.time_remaining, 5000);
+
+        let state = calculate_state(&timer, 1000000 + 22000).unwrap();
+        assert_eq!(state.segment_index, 2);
+        assert_eq!(state.sequence_index, 1);
+        assert_eq!(state.label, "Boulder");
+        assert_eq!(state.time_remaining, 3000);
+
+        let state = calculate_state(&timer, 1000000 + 30000).unwrap();
+        assert_eq!(state.status, TimerStatus::Finished);
+        assert_eq!(state.sequence_name, "Finals");
+    }
+
+    #[test]
+    fn test_segment_at_repetitions() {
+        let sequences = vec![
+            Sequence {
+                name: "Skipped".to_owned(),
+                segments: vec![segment("Skipped", 1000, 0)],
+                repetitions: 0,
+            },
+            Sequence {
+                name: "Qualification".to_owned(),
+                segments: vec![segment("Boulder", 3000, 0), segment("Change", 2000, 0)],
+                repetitions: 3,
+            },
+            Sequence {
+                name: "Finals".to_owned(),
+                segments: vec![segment("Boulder", 4000, 0)],
+                repetitions: 2,
+            },
+        ];
+        let unrolled = vec![Sequence {
+            name: String::new(),
+            segments: crate::repository::unroll(&sequences),
+            repetitions: 1,
+        }];
+
+        // the same as going through all segments one by one
+        for time_in_round in 0..=round_duration(&sequences) {
+            assert_eq!(
+                segment_at(&sequences, time_in_round),
+                segment_at(&unrolled, time_in_round),
+                "{}",
+                time_in_round
+            );
+        }
+        assert_eq!(segment_at(&sequences, 5000), (1, 0));
+        assert_eq!(segment_at(&sequences, 5001), (2, 2999));
+        assert_eq!(segment_at(&sequences, 16000), (6, 3000));
+    }
+
+    #[test]
+    fn test_pause_resume() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        assert!(!resume(&mut timer, 1000000 + 10000));
+
+        assert!(pause(&mut timer, 1000000 + 10000));
+        assert!(!pause(&mut timer, 1000000 + 20000));
+        assert_eq!(
+            calculate_state(&timer, 1000000 + 20000)
+                .unwrap()
+                .time_remaining,
+            230000
+        );
+
+        assert!(resume(&mut timer, 1000000 + 30000));
+        assert_eq!(timer.paused_time, 20000);
+        assert_eq!(
+            calculate_state(&timer, 1000000 + 30000)
+                .unwrap()
+                .time_remaining,
+            230000
+        );
+
+        stop(&mut timer, 1000000 + 40000);
+        let state = calculate_state(&timer, 1000000 + 50000).unwrap();
+        assert_eq!(state.status, TimerStatus::Stopped);
+        assert_eq!(state.time_remaining, 240000);
+    }
+
+    #[test]
+    fn test_no_segments() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        timer.sequences = vec![];
+        assert!(calculate_state(&timer, 0).is_none());
+
+        timer.sequences = vec![vec![segment("Empty", 0, 0)].into()];
+        assert!(calculate_state(&timer, 0).is_none());
+    }
+
+    #[test]
+    fn test_skip_next() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        let now = 1000000 + 10000;
+
+        assert!(skip(&mut timer, now, SkipDirection::Next));
+        let state = calculate_state(&timer, now).unwrap();
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.time_remaining, 14999);
+
+        assert!(skip(&mut timer, now, SkipDirection::Next));
+        let state = calculate_state(&timer, now).unwrap();
+        assert_eq!(state.round, 1);
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 239999);
+    }
+
+    #[test]
+    fn test_skip_previous() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        let now = 1000000 + 250000;
+
+        assert!(skip(&mut timer, now, SkipDirection::Previous));
+        let state = calculate_state(&timer, now).unwrap();
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 239999);
+
+        assert!(skip(&mut timer, now, SkipDirection::Previous));
+        let state = calculate_state(&timer, now).unwrap();
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 239999);
+    }
+
+    #[test]
+    fn test_skip_stopped() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        timer.stop_at = Some(1000000 + 10000);
+        let now = 1000000 + 60000;
+
+        assert!(skip(&mut timer, now, SkipDirection::Next));
+        assert!(skip(&mut timer, now, SkipDirection::Next));
+        assert!(skip(&mut timer, now, SkipDirection::Previous));
+        let state = calculate_state(&timer, now).unwrap();
+        assert_eq!(state.status, TimerStatus::Stopped);
+        assert_eq!(state.round, 0);
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.time_remaining, 14999);
+    }
+
+    #[test]
+    fn test_skip_waiting() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        assert!(!skip(&mut timer, 0, SkipDirection::Next));
+    }
+
+    #[test]
+    fn test_skip_before_zero() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        timer.start_at = 0;
+
+        // the next segment would have started before 0
+        assert!(!skip(&mut timer, 10000, SkipDirection::Next));
+        assert_eq!(timer.start_at, 0);
+        assert!(skip(&mut timer, 10000, SkipDirection::Previous));
+        assert_eq!(
+            calculate_state(&timer, 10000).unwrap().time_remaining,
+            239999
+        );
+    }
+
+    #[test]
+    fn test_skip_extended() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        let now = 1000000 + 250000;
+
+        assert!(adjust(&mut timer, now, 60000));
+        assert!(skip(&mut timer, now + 30000, SkipDirection::Next));
+        assert!(timer.extension.is_none());
+        let state = calculate_state(&timer, now + 30000).unwrap();
+        assert_eq!(state.round, 1);
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 239999);
+    }
+
+    #[test]
+    fn test_adjust() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        let now = 1000000 + 60000;
+
+        assert!(adjust(&mut timer, now, 30000));
+        let state = calculate_state(&timer, now).unwrap();
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 210000);
+
+        assert!(adjust(&mut timer, now, -60000));
+        let state = calculate_state(&timer, now).unwrap();
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 150000);
+
+        assert!(adjust(&mut timer, now, -200000));
+        let state = calculate_state(&timer, now).unwrap();
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 0);
+
+        let state = calculate_state(&timer, now + 1).unwrap();
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.time_remaining, 14999);
+    }
+
+    #[test]
+    fn test_adjust_more_than_elapsed() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        let now = 1000000 + 10000;
+
+        assert!(adjust(&mut timer, now, 60000));
+        let state = calculate_state(&timer, now).unwrap();
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 290000);
+
+        // the segment is shown with the added time after its normal end
+        let state = calculate_state(&timer, 1000000 + 280000).unwrap();
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 20000);
+
+        // and the segments after it start later
+        let state = calculate_state(&timer, 1000000 + 300001).unwrap();
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.time_remaining, 14999);
+
+        let state = calculate_state(&timer, 1000000 + 315001).unwrap();
+        assert_eq!(state.round, 1);
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 239999);
+    }
+
+    #[test]
+    fn test_adjust_during_extension() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+
+        assert!(adjust(&mut timer, 1000000 + 230000, 60000));
+        let now = 1000000 + 260000;
+        assert_eq!(calculate_state(&timer, now).unwrap().time_remaining, 40000);
+
+        assert!(adjust(&mut timer, now, -30000));
+        let state = calculate_state(&timer, now).unwrap();
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 10000);
+
+        assert!(adjust(&mut timer, now, -20000));
+        let state = calculate_state(&timer, now).unwrap();
+        assert_eq!(state.segment_index, 0);
+        assert_eq!(state.time_remaining, 0);
+        let state = calculate_state(&timer, now + 1).unwrap();
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.time_remaining, 14999);
+    }
+
+    #[test]
+    fn test_adjust_after_extension() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+
+        assert!(adjust(&mut timer, 1000000 + 10000, 60000));
+        // the extension of the first segment is over, the second one is adjusted now
+        let now = 1000000 + 305000;
+        assert!(adjust(&mut timer, now, 5000));
+        assert_eq!(timer.start_at, 1000000 + 60000);
+
+        let state = calculate_state(&timer, now).unwrap();
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.time_remaining, 15000);
+    }
+
+    #[test]
+    fn test_adjust_last_segment() {
+        let mut timer = timer(false, PreStartBehaviour::ShowFirstSegment);
+
+        assert!(adjust(&mut timer, 1000000 + 250000, 10000));
+        let state = calculate_state(&timer, 1000000 + 260000).unwrap();
+        assert_eq!(state.status, TimerStatus::Running);
+        assert_eq!(state.segment_index, 1);
+        assert_eq!(state.time_remaining, 5000);
+
+        let state = calculate_state(&timer, 1000000 + 265001).unwrap();
+        assert_eq!(state.status, TimerStatus::Finished);
+    }
+
+    #[test]
+    fn test_adjust_paused() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+
+        assert!(pause(&mut timer, 1000000 + 10000));
+        assert!(adjust(&mut timer, 1000000 + 20000, 60000));
+        assert_eq!(
+            calculate_state(&timer, 1000000 + 20000)
+                .unwrap()
+                .time_remaining,
+            290000
+        );
+
+        // the extension moves with the timer when it is resumed
+        assert!(resume(&mut timer, 1000000 + 30000));
+        assert_eq!(
+            calculate_state(&timer, 1000000 + 50000)
+                .unwrap()
+                .time_remaining,
+            270000
+        );
+    }
+
+    #[test]
+    fn test_adjust_limits() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        assert!(!adjust(&mut timer, 0, -10000));
+
+        timer.display_options.pre_start_behaviour = PreStartBehaviour::RunNormally;
+        assert!(!adjust(&mut timer, 1000000 - 5000, 10000));
+    }
+
+    #[test]
+    fn test_adjust_before_zero() {
+        let mut timer = timer(true, PreStartBehaviour::ShowFirstSegment);
+        timer.start_at = 0;
+
+        // removing a minute would move the start of the timer before 0
+        assert!(!adjust(&mut timer, 10000, -60000));
+        assert_eq!(timer.start_at, 0);
+
+        // adding time doesn't move the start
+        assert!(adjust(&mut timer, 10000, 60000));
+        assert_eq!(timer.start_at, 0);
+        assert_eq!(
+            calculate_state(&timer, 10000).unwrap().time_remaining,
+            290000
+        );
+    }
+}
@@ -0,0 +1,430 @@
+//! Checks of timers sent by clients, which are well-formed json but would make clients
+//! misbehave, e.g. segments without any time.
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{TimerCreationRequest, TimerUpdateRequest};
+use crate::repository::{playlist_segments, segment_count, Segment, Sequence, Template};
+use crate::templates::SOUND_FILES;
+
+/// How often a sequence can be repeated
+pub const MAX_REPETITIONS: u32 = 1000;
+/// How many segments a playlist can have with the repetitions unrolled,
+/// as clients show and run through all of them
+pub const MAX_SEGMENTS: u64 = 1000;
+
+/// A problem with one field of a request
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FieldError {
+    /// the path to the field, e.g. `sequences[0].segments[1].time`,
+    /// empty if the problem is with the request as a whole
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ValidationResponse {
+    errors: Vec<FieldError>,
+}
+
+/// An error of an endpoint which validates its request
+pub enum RequestError {
+    Status(StatusCode),
+    /// the request can't be processed because of these problems
+    Invalid(Vec<FieldError>),
+}
+
+impl RequestError {
+    /// Names the fields as part of `field`, for a playlist nested in the request
+    pub fn within(self, field: &str) -> Self {
+        match self {
+            RequestError::Invalid(errors) => RequestError::Invalid(
+                errors
+                    .into_iter()
+                    .map(|error| FieldError {
+                        field: format!("{}.{}", field, error.field),
+                        ..error
+                    })
+                    .collect(),
+            ),
+            status => status,
+        }
+    }
+}
+
+impl From<StatusCode> for RequestError {
+    fn from(status: StatusCode) -> Self {
+        RequestError::Status(status)
+    }
+}
+
+impl From<FieldError> for RequestError {
+    fn from(error: FieldError) -> Self {
+        RequestError::Invalid(vec![error])
+    }
+}
+
+impl IntoResponse for RequestError {
+    fn into_response(self) -> Response {
+        match self {
+            RequestError::Status(status) => status.into_response(),
+            RequestError::Invalid(errors) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(ValidationResponse { errors }),
+            )
+                .into_response(),
+        }
+    }
+}
+
+fn validate_segment(field: &str, segment: &Segment, errors: &mut Vec<FieldError>) {
+    if segment.time == 0 {
+        errors.push(FieldError::new(
+            format!("{}.time", field),
+            "The time has to be greater than 0",
+        ));
+    }
+
+    // `count_to` may be greater than the time, which is used to show an almost fixed time,
+    // e.g. while waiting for the next boulder in the built-in final template.
+    // Sounds may be triggered at times the segment never shows, as sound presets are
+    // applied to all segments of a template, those sounds are just not played.
+
+    for (index, sound) in segment.sounds.iter().enumerate() {
+        if !SOUND_FILES.contains(&sound.filename.as_str()) {
+            errors.push(FieldError::new(
+                format!("{}.sounds[{}].filename", field, index),
+                format!(
+                    "Unknown sound, the available sounds are {}",
+                    SOUND_FILES.join(", ")
+                ),
+            ));
+        }
+    }
+}
+
+/// The problems with a playlist, which every playlist that is stored is checked for
+fn sequence_errors(sequences: &[Sequence]) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    for (sequence_index, sequence) in sequences.iter().enumerate() {
+        for (index, segment) in sequence.segments.iter().enumerate() {
+            let field = format!("sequences[{}].segments[{}]", sequence_index, index);
+            validate_segment(&field, segment, &mut errors);
+        }
+
+        if sequence.repetitions > MAX_REPETITIONS {
+            errors.push(FieldError::new(
+                format!("sequences[{}].repetitions", sequence_index),
+                format!(
+                    "A sequence can be repeated at most {} times",
+                    MAX_REPETITIONS
+                ),
+            ));
+        }
+    }
+
+    if segment_count(sequences) > MAX_SEGMENTS {
+        errors.push(FieldError::new(
+            "sequences",
+            format!(
+                "The timer can have at most {} segments with the repetitions",
+                MAX_SEGMENTS
+            ),
+        ));
+    }
+
+    let runs_segments = sequences
+        .iter()
+        .any(|sequence| sequence.repetitions > 0 && !sequence.segments.is_empty());
+    if !runs_segments {
+        errors.push(FieldError::new(
+            "sequences",
+            "The timer needs at least one sequence with segments which is repeated at least once",
+        ));
+    }
+
+    errors
+}
+
+/// Checks the playlist as [`crate::models::playlist`] builds it from the request,
+/// the fields are named after the ones of the request
+fn validate_playlist(sequences: &[Sequence], segments: &[Segment]) -> Vec<FieldError> {
+    if sequences.is_empty() {
+        // the segments become the only sequence, the errors are named after the segments
+        return sequence_errors(&[segments.to_vec().into()])
+            .into_iter()
+            .map(|error| FieldError {
+                field: match error.field.strip_prefix("sequences[0].") {
+                    Some(field) => field.to_owned(),
+                    None => "segments".to_owned(),
+                },
+                ..error
+            })
+            .collect();
+    }
+
+    let mut errors = Vec::new();
+    // the sequences win, so segments changed next to them would be lost
+    let matching = segment_count(sequences) == segments.len() as u64
+        && playlist_segments(sequences).eq(segments.iter());
+    if !segments.is_empty() && !matching {
+        errors.push(FieldError::new(
+            "segments",
+            "The segments don't match the sequences, only one of them can be changed",
+        ));
+    }
+
+    errors.extend(sequence_errors(sequences));
+    errors
+}
+
+fn result(errors: Vec<FieldError>) -> Result<(), RequestError> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(RequestError::Invalid(errors))
+    }
+}
+
+/// Checks a playlist which doesn't come from the request as it is, e.g. of a template
+/// or an import
+pub fn validate_sequences(sequences: &[Sequence]) -> Result<(), RequestError> {
+    result(sequence_errors(sequences))
+}
+
+/// Checks the playlist of a request which can send `sequences` or only `segments`
+pub fn validate_request_playlist(
+    sequences: &[Sequence],
+    segments: &[Segment],
+) -> Result<(), RequestError> {
+    result(validate_playlist(sequences, segments))
+}
+
+/// The playlist of the template replaces the one of the request, so it is checked instead
+pub fn validate_creation(
+    request: &TimerCreationRequest,
+    template: Option<&Template>,
+) -> Result<(), RequestError> {
+    match template {
+        Some(template) => validate_sequences(&template.sequences),
+        None => validate_request_playlist(&request.sequences, &request.segments),
+    }
+}
+
+pub fn validate_update(request: &TimerUpdateRequest) -> Result<(), RequestError> {
+    validate_request_playlist(&request.sequences, &request.segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::Sound;
+    use crate::templates::builtin_templates;
+
+    fn segment(time: u32, count_to: u32, sounds: Vec<Sound>) -> Segment {
+        Segment {
+            label: "Boulder".to_owned(),
+            time,
+            color: None,
+            count_to,
+            sounds,
+        }
+    }
+
+    fn sound(filename: &str, trigger_time: u32) -> Sound {
+        Sound {
+            filename: filename.to_owned(),
+            trigger_time,
+        }
+    }
+
+    fn fields(errors: Vec<FieldError>) -> Vec<String> {
+        errors.into_iter().map(|error| error.field).collect()
+    }
+
+    #[test]
+    fn test_valid_playlist() {
+        let segments = vec![
+            segment(
+                240000,
+                0,
+                vec![sound("beep.mp3", 240), sound("beep.mp3", 60)],
+            ),
+            segment(11000, 11000, vec![sound("countdown.mp3", 22)]),
+            segment(1000, 240000, vec![]),
+            // the sound is never played in a segment this short
+            segment(11000, 0, vec![sound("beep.mp3", 60)]),
+        ];
+        assert!(validate_playlist(&[], &segments).is_empty());
+
+        for template in builtin_templates() {
+            assert!(validate_playlist(&template.sequences, &[]).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_invalid_segments() {
+        let segments = vec![
+            segment(0, 0, vec![]),
+            segment(1000, 0, vec![sound("beep.mp3", 2), sound("unknown.mp3", 0)]),
+        ];
+
+        assert_eq!(
+            fields(validate_playlist(&[], &segments)),
+            vec!["segments[0].time", "segments[1].sounds[1].filename",]
+        );
+    }
+
+    #[test]
+    fn test_empty_playlist() {
+        assert_eq!(fields(validate_playlist(&[], &[])), vec!["segments"]);
+
+        let sequences = vec![
+            Sequence {
+                name: "Skipped".to_owned(),
+                segments: vec![segment(1000, 0, vec![])],
+                repetitions: 0,
+            },
+            Sequence {
+                name: "Empty".to_owned(),
+                segments: vec![],
+                repetitions: 1,
+            },
+        ];
+        assert_eq!(
+            fields(validate_playlist(&sequences, &[])),
+            vec!["sequences"]
+        );
+
+        let sequences = vec![Sequence {
+            name: "Qualification".to_owned(),
+            segments: vec![segment(0, 0, vec![])],
+            repetitions: 1,
+        }];
+        assert_eq!(
+            fields(validate_playlist(&sequences, &[])),
+            vec!["sequences[0].segments[0].time"]
+        );
+    }
+
+    #[test]
+    fn test_sequences_and_segments() {
+        let sequences = vec![Sequence {
+            name: "Qualification".to_owned(),
+            segments: vec![segment(1000, 0, vec![]), segment(2000, 0, vec![])],
+            repetitions: 2,
+        }];
+
+        // clients which know about sequences may send the unrolled segments back unchanged
+        let segments = crate::repository::unroll(&sequences);
+        assert!(validate_playlist(&sequences, &segments).is_empty());
+
+        let mut changed = segments.clone();
+        changed[1].time = 3000;
+        assert_eq!(
+            fields(validate_playlist(&sequences, &changed)),
+            vec!["segments"]
+        );
+        assert_eq!(
+            fields(validate_playlist(&sequences, &segments[..2])),
+            vec!["segments"]
+        );
+    }
+
+    #[test]
+    fn test_playlist_limits() {
+        let sequences = vec![Sequence {
+            name: "Endless".to_owned(),
+            segments: vec![segment(1000, 0, vec![])],
+            repetitions: u32::MAX,
+        }];
+        assert_eq!(
+            fields(validate_playlist(&sequences, &[])),
+            vec!["sequences[0].repetitions", "sequences"]
+        );
+
+        let sequences = vec![Sequence {
+            name: "Long".to_owned(),
+            segments: vec![segment(1000, 0, vec![]); 2],
+            repetitions: MAX_REPETITIONS,
+        }];
+        assert_eq!(
+            fields(validate_playlist(&sequences, &[])),
+            vec!["sequences"]
+        );
+
+        let segments = vec![segment(1000, 0, vec![]); MAX_SEGMENTS as usize + 1];
+        assert_eq!(fields(validate_playlist(&[], &segments)), vec!["segments"]);
+    }
+
+    #[test]
+    fn test_template_playlist() {
+        let request = TimerCreationRequest {
+            sequences: vec![],
+            segments: vec![],
+            id: "test".to_owned(),
+            password: "password".to_owned(),
+            repeat: false,
+            start_at: 0,
+            metadata: Default::default(),
+            display_options: Default::default(),
+            private: false,
+            template: Some("saved".to_owned()),
+        };
+        let template = Template {
+            id: "saved".to_owned(),
+            name: "Saved".to_owned(),
+            sequences: vec![vec![segment(0, 0, vec![])].into()],
+            repeat: false,
+            display_options: Default::default(),
+            metadata: Default::default(),
+        };
+
+        // the playlist of the template is checked instead of the one of the request
+        match validate_creation(&request, Some(&template)) {
+            Err(RequestError::Invalid(errors)) => {
+                assert_eq!(fields(errors), vec!["sequences[0].segments[0].time"])
+            }
+            _ => panic!("the template is not checked"),
+        }
+
+        let template = Template {
+            sequences: builtin_templates()[0].sequences.clone(),
+            ..template
+        };
+        assert!(validate_creation(&request, Some(&template)).is_ok());
+    }
+
+    #[test]
+    fn test_nested_playlist() {
+        let sequences = vec![Sequence {
+            name: "Qualification".to_owned(),
+            segments: vec![segment(1000, 0, vec![])],
+            repetitions: MAX_REPETITIONS + 1,
+        }];
+
+        match validate_sequences(&sequences).map_err(|error| error.within("document.timer")) {
+            Err(RequestError::Invalid(errors)) => assert_eq!(
+                fields(errors),
+                vec![
+                    "document.timer.sequences[0].repetitions",
+                    "document.timer.sequences"
+                ]
+            ),
+            _ => panic!("the playlist is not checked"),
+        }
+    }
+}
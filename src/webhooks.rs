@@ -0,0 +1,233 @@
+//! Sends the lifecycle and segment transition events of timers to the webhooks
+//! configured for them.
+
+use std::{sync::Arc, time::Duration};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use hmac::{Hmac, Mac};
+use reqwest::redirect::Policy;
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::{
+    audit::Action,
+    models::TimerResponse,
+    repository::{Repository, Timer, Webhook},
+    targets::{check_url, PublicResolver},
+    timer_events::{track_timers, TimerEvent, TrackedTimerUpdate},
+    timer_state::current_time,
+};
+
+const MAX_ATTEMPTS: u32 = 5;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "event")]
+pub enum TimerLifecycleEvent {
+    Created,
+    Updated,
+    Started,
+    Stopped,
+    Deleted,
+}
+
+/// The lifecycle events caused by replacing `old` with `new`
+pub fn lifecycle_events(old: &Timer, new: &Timer) -> Vec<TimerLifecycleEvent> {
+    let mut events = vec![TimerLifecycleEvent::Updated];
+
+    match (old.stop_at, new.stop_at) {
+        (None, Some(_)) => events.push(TimerLifecycleEvent::Stopped),
+        (Some(_), None) => events.push(TimerLifecycleEvent::Started),
+        (None, None) if old.start_at != new.start_at => events.push(TimerLifecycleEvent::Started),
+        _ => (),
+    }
+
+    events
+}
+
+/// The event of controlling a timer with `action`, which is the same for a single timer
+/// and the timers of a group
+pub fn control_event(action: Action) -> TimerLifecycleEvent {
+    match action {
+        Action::Start | Action::Resume => TimerLifecycleEvent::Started,
+        Action::Stop | Action::Pause => TimerLifecycleEvent::Stopped,
+        _ => TimerLifecycleEvent::Updated,
+    }
+}
+
+/// A random secret for a new webhook
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    hex::encode(secret)
+}
+
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    http: reqwest::Client,
+    allow_private_targets: bool,
+}
+
+impl WebhookDispatcher {
+    /// Unless `allow_private_targets` is set, webhooks are only sent to public addresses
+    pub fn new(allow_private_targets: bool) -> Self {
+        let mut http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            // a redirect could lead to a private address
+            .redirect(Policy::none());
+        if !allow_private_targets {
+            http = http.dns_resolver(Arc::new(PublicResolver));
+        }
+
+        WebhookDispatcher {
+            http: http.build().unwrap(),
+            allow_private_targets,
+        }
+    }
+
+    pub fn send_lifecycle_event(&self, timer: &Timer, event: TimerLifecycleEvent) {
+        let mut payload = serde_json::to_value(event).unwrap();
+        payload["timer"] = serde_json::to_value(TimerResponse::from(timer.clone())).unwrap();
+        self.send(timer, current_time(), payload);
+    }
+
+    pub fn send_timer_event(&self, timer: &Timer, timestamp: u64, event: &TimerEvent) {
+        self.send(timer, timestamp, serde_json::to_value(event).unwrap());
+    }
+
+    fn send(&self, timer: &Timer, timestamp: u64, mut payload: serde_json::Value) {
+        payload["timer_id"] = timer.id.clone().into();
+        payload["timestamp"] = timestamp.into();
+        let payload = payload.to_string();
+
+        for webhook in &timer.webhooks {
+            tokio::spawn(deliver(
+                self.http.clone(),
+                webhook.clone(),
+                payload.clone(),
+                self.allow_private_targets,
+            ));
+        }
+    }
+
+    /// Sends the segment transitions of all timers with webhooks.
+    pub fn spawn_transition_task(&self, repository: Repository) -> tokio::task::JoinHandle<()> {
+        let dispatcher = self.clone();
+        let mut tracker_rx = track_timers(repository, |timer| !timer.webhooks.is_empty());
+
+        tokio::spawn(async move {
+            while let Some(update) = tracker_rx.recv().await {
+                let (timer, time, events) = match update {
+                    TrackedTimerUpdate::Events(timer, time, events) => (timer, time, events),
+                    _ => continue,
+                };
+
+                for event in events
+                    .iter()
+                    .filter(|e| !matches!(e, TimerEvent::SoundTrigger { .. }))
+                {
+                    dispatcher.send_timer_event(&timer, time, event);
+                }
+            }
+        })
+    }
+}
+
+fn sign(secret: &str, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Posts the payload to the webhook, retrying with an exponential backoff
+async fn deliver(
+    http: reqwest::Client,
+    webhook: Webhook,
+    payload: String,
+    allow_private_targets: bool,
+) {
+    // the resolver of the client is not used for urls with an ip address
+    if let Err(e) = check_url(&webhook.url, allow_private_targets).await {
+        println!("Webhook {} is not sent: {}", webhook.url, e);
+        return;
+    }
+
+    let signature = format!("sha256={}", sign(&webhook.secret, &payload));
+    let mut retry_delay = FIRST_RETRY_DELAY;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let response = http
+            .post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .header("X-Timer-Signature", &signature)
+            .body(payload.clone())
+            .send()
+            .await;
+
+        match response {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => println!(
+                "Webhook {} failed with {} (attempt {})",
+                webhook.url,
+                response.status(),
+                attempt
+            ),
+            Err(e) => println!(
+                "Webhook {} failed: {} (attempt {})",
+                webhook.url, e, attempt
+            ),
+        }
+
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(retry_delay).await;
+            retry_delay *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign("key", "The quick brown fox jumps over the lazy dog"),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn test_lifecycle_events() {
+        let old = Timer {
+            start_at: 1000,
+            ..Default::default()
+        };
+
+        let stopped = Timer {
+            stop_at: Some(2000),
+            ..old.clone()
+        };
+        assert_eq!(
+            lifecycle_events(&old, &stopped),
+            vec![TimerLifecycleEvent::Updated, TimerLifecycleEvent::Stopped]
+        );
+        assert_eq!(
+            lifecycle_events(&stopped, &old),
+            vec![TimerLifecycleEvent::Updated, TimerLifecycleEvent::Started]
+        );
+
+        let restarted = Timer {
+            start_at: 3000,
+            ..old.clone()
+        };
+        assert_eq!(
+            lifecycle_events(&old, &restarted),
+            vec![TimerLifecycleEvent::Updated, TimerLifecycleEvent::Started]
+        );
+        assert_eq!(
+            lifecycle_events(&old, &old),
+            vec![TimerLifecycleEvent::Updated]
+        );
+    }
+}
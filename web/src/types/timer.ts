@@ -21,6 +21,13 @@ export interface TimerUpdateRequest {
 	display_options: DisplayOptions;
 }
 
+/** Time added to a segment, all times are in ms after `start_at` */
+export interface SegmentExtension {
+	start: number;
+	end: number;
+	time: number;
+}
+
 export interface Timer {
 	id: string;
 	start_at: number;
@@ -29,13 +36,16 @@ export interface Timer {
 	segments: Segment[];
 	metadata: TimerMetadata;
 	display_options: DisplayOptions;
+	revision: number;
+	extension?: SegmentExtension;
 }
 
 export interface TimerLoginResponse {
 	token: string;
+	/** exchanged for a new token once the token expired */
+	refresh_token: string;
 }
 
-export interface TimerCreationResponse {
+export interface TimerCreationResponse extends TimerLoginResponse {
 	timer: Timer;
-	token: string;
 }
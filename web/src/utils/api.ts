@@ -9,6 +9,69 @@ import type {
 	TimerUpdateRequest
 } from 'types/timer';
 
+interface ValidationErrors {
+	errors: { field: string; message: string }[];
+}
+
+/** The problems the server found with the timer, one per line */
+const validationError = async (res: Response): Promise<Error> => {
+	const { errors }: ValidationErrors = await res.json();
+	return new Error(
+		errors.map(({ field, message }) => (field ? `${field}: ${message}` : message)).join('\n')
+	);
+};
+
+/** Keeps the tokens for the next requests, the token expires after an hour */
+const storeTokens = ({ token, refresh_token }: TimerLoginResponse) => {
+	localStorage.setItem('token', token);
+	localStorage.setItem('refresh_token', refresh_token);
+};
+
+/** Exchanges the stored refresh token for new tokens, returns the new token if that worked */
+const refreshTokens = async (fetch: Fetch): Promise<string | undefined> => {
+	const refresh_token = localStorage.getItem('refresh_token');
+	if (!refresh_token) return undefined;
+
+	const res = await fetch(`${get(API_URL)}/timer/refresh`, {
+		method: 'POST',
+		headers: {
+			'Content-Type': 'application/json'
+		},
+		body: JSON.stringify({ refresh_token })
+	});
+
+	if (!res.ok) {
+		// revoked or expired, the password is needed again
+		localStorage.removeItem('token');
+		localStorage.removeItem('refresh_token');
+		return undefined;
+	}
+
+	const tokens: TimerLoginResponse = await res.json();
+	storeTokens(tokens);
+	return tokens.token;
+};
+
+/**
+ * Sends the request with the token. If the stored token expired,
+ * it is refreshed and the request is sent once more with the new one
+ */
+const fetchWithToken = async (
+	fetch: Fetch,
+	url: string,
+	init: RequestInit,
+	token: string
+): Promise<Response> => {
+	const send = (token: string) =>
+		fetch(url, { ...init, headers: { ...init.headers, Authorization: `Bearer ${token}` } });
+
+	const res = await send(token);
+	if (res.status !== 401 || token !== localStorage.getItem('token')) return res;
+
+	const refreshed = await refreshTokens(fetch);
+	return refreshed ? send(refreshed) : res;
+};
+
 const getTimer = async (id: string, fetch: Fetch): Promise<Timer> => {
 	const res = await fetch(`${get(API_URL)}/timer/${id}`);
 
@@ -42,22 +105,69 @@ const loginTimer = async (
 	return await res.json();
 };
 
+/**
+ * Updates the timer if it still has the given revision,
+ * without a revision the timer is overwritten no matter what changed in the meantime
+ */
 const updateTimer = async (
 	id: string,
 	newTimerData: TimerUpdateRequest,
 	token: string,
-	fetch: Fetch
+	fetch: Fetch,
+	revision?: number
 ) => {
-	const res = await fetch(`${get(API_URL)}/timer/${id}`, {
-		method: 'PUT',
-		body: JSON.stringify(newTimerData),
-		headers: {
-			'Content-Type': 'application/json',
-			Authorization: `Bearer ${token}`
-		}
-	});
+	// only the fields of the request are sent, the timer passed in also has its `sequences`,
+	// which would override the edited `segments`
+	const { start_at, stop_at, repeat, segments, metadata, display_options } = newTimerData;
+	const res = await fetchWithToken(
+		fetch,
+		`${get(API_URL)}/timer/${id}`,
+		{
+			method: 'PUT',
+			body: JSON.stringify({ start_at, stop_at, repeat, segments, metadata, display_options }),
+			headers: {
+				'Content-Type': 'application/json',
+				'If-Match': revision === undefined ? '*' : `"${revision}"`
+			}
+		},
+		token
+	);
 
-	if (!res.ok) {
+	if (res.status === 409) {
+		throw new Error('The timer was changed in the meantime, please reload it');
+	} else if (res.status === 422) {
+		throw await validationError(res);
+	} else if (!res.ok) {
+		throw new Error(res.statusText);
+	}
+
+	return await res.json();
+};
+
+/** Runs one of the controls of the timer on the server, e.g. `next` skips the current segment */
+const controlTimer = async (
+	id: string,
+	action: 'start' | 'pause' | 'resume' | 'next',
+	token: string,
+	fetch: Fetch,
+	body?: object
+): Promise<Timer> => {
+	const res = await fetchWithToken(
+		fetch,
+		`${get(API_URL)}/timer/${id}/${action}`,
+		{
+			method: 'POST',
+			body: body === undefined ? undefined : JSON.stringify(body),
+			headers: {
+				'Content-Type': 'application/json'
+			}
+		},
+		token
+	);
+
+	if (res.status === 409) {
+		throw new Error('This is not possible right now, please reload the timer');
+	} else if (!res.ok) {
 		throw new Error(res.statusText);
 	}
 
@@ -86,6 +196,8 @@ const createTimer = async (
 
 	if (!res.ok && res.status in errorMessages) {
 		throw new Error(errorMessages[res.status]);
+	} else if (res.status === 422) {
+		throw await validationError(res);
 	} else if (!res.ok) {
 		throw new Error(res.statusText);
 	}
@@ -93,4 +205,4 @@ const createTimer = async (
 	return await res.json();
 };
 
-export { loginTimer, updateTimer, createTimer, getTimer };
+export { loginTimer, storeTokens, updateTimer, controlTimer, createTimer, getTimer };
@@ -23,10 +23,38 @@ function mod(num: number, mod: number) {
 	return ((num % mod) + mod) % mod;
 }
 
+/**
+ * The time added to the current segment which is still left, and the time in the playlist,
+ * which stands still at the end of an extended segment until the extension is over
+ */
+function applyExtension(
+	timerData: Timer,
+	elapsedTime: number
+): { playlistTime: number; extensionLeft: number } {
+	const extension = timerData.extension;
+	if (!extension || elapsedTime <= extension.start) {
+		return { playlistTime: elapsedTime, extensionLeft: 0 };
+	}
+
+	const extensionEnd = extension.end + extension.time;
+	if (elapsedTime > extensionEnd) {
+		return { playlistTime: elapsedTime - extension.time, extensionLeft: 0 };
+	}
+
+	return {
+		playlistTime: Math.min(elapsedTime, extension.end),
+		extensionLeft: Math.min(extensionEnd - elapsedTime, extension.time)
+	};
+}
+
 function calculateTimeInCurrentRound(
 	timerData: Timer,
 	currentTime: number
-): { timeInCurrentRound: number; state: 'running' | 'waiting' | 'finished' | 'stopped' } {
+): {
+	timeInCurrentRound: number;
+	extensionLeft: number;
+	state: 'running' | 'waiting' | 'finished' | 'stopped';
+} {
 	let stopped = false;
 
 	if (timerData.stop_at && timerData.stop_at < currentTime) {
@@ -39,6 +67,7 @@ function calculateTimeInCurrentRound(
 	if (elapsedTime < 0 && timerData.display_options.pre_start_behaviour === 'ShowFirstSegment') {
 		return {
 			timeInCurrentRound: 1,
+			extensionLeft: 0,
 			state: 'waiting'
 		};
 	}
@@ -48,21 +77,30 @@ function calculateTimeInCurrentRound(
 	if (elapsedTime < 0 && timerData.display_options.pre_start_behaviour === 'ShowLastSegment') {
 		return {
 			timeInCurrentRound: totalTimePerRound,
+			extensionLeft: 0,
 			state: 'waiting'
 		};
 	}
 
-	if (!timerData.repeat && !stopped && elapsedTime > totalTimePerRound) {
+	const { playlistTime, extensionLeft } = applyExtension(timerData, elapsedTime);
+
+	if (!timerData.repeat && !stopped && playlistTime > totalTimePerRound) {
 		return {
 			timeInCurrentRound: totalTimePerRound,
+			extensionLeft: 0,
 			state: 'finished'
 		};
 	}
 
-	let timeInCurrentRound = Math.floor(mod(elapsedTime, totalTimePerRound));
+	let timeInCurrentRound = Math.floor(mod(playlistTime, totalTimePerRound));
+	// the extended segment is shown until its extension is over, even at the end of a round
+	if (timeInCurrentRound === 0 && extensionLeft > 0) {
+		timeInCurrentRound = totalTimePerRound;
+	}
 
 	return {
 		timeInCurrentRound,
+		extensionLeft,
 		state: stopped ? 'stopped' : 'running'
 	};
 }